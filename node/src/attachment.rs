@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use self_encryption::{bytes::Bytes, ChunkInfo, DataMap, EncryptedChunk};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::db::Database;
+use super::item;
+use super::secret::SecretBuf;
+use super::types::Item;
+use super::utils::{self, MemData};
+
+pub const MAX_ATTACHMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+// sealed with the vault key, the chunks alone can't be put back together
+#[derive(Deserialize, Serialize)]
+struct StoredMap {
+    data_map: DataMap,
+    // MemData pads small files, this is the size without padding
+    length: usize,
+}
+
+fn chunk_hash(info: &ChunkInfo) -> String {
+    hex::encode(info.dst_hash.0)
+}
+
+/// Chunk encrypts data and adds it to the item as name; the caller saves the item.
+/// Chunks are stored by the hash of their content, so a file attached twice is only
+/// stored once. Files under MemData's minimum size are padded at random and never
+/// share chunks.
+pub fn attach(db: &Database, key: &[u8], item: &mut Item, name: &str, data: Vec<u8>) -> Result<()> {
+    if data.len() as u64 > MAX_ATTACHMENT_SIZE {
+        bail!("{} is larger than {} MB", name, MAX_ATTACHMENT_SIZE / 1024 / 1024);
+    }
+    let size = data.len() as u64;
+    let mem_data = MemData::new(data)?;
+    let infos = mem_data.data_map().infos();
+    let mut chunks = Vec::new();
+    for chunk in mem_data.chunks() {
+        let info = infos.iter().find(|info| info.index == chunk.index).ok_or_else(|| anyhow!("chunk {} not in the data map", chunk.index))?;
+        chunks.push((chunk_hash(info), &chunk.content[..]));
+    }
+    db.put_chunks(&chunks)?;
+    let stored = StoredMap { data_map: mem_data.data_map().clone(), length: mem_data.len() };
+    let data_map = SecretBuf::from_vec(serde_json::to_vec(&stored)?)?;
+    let attachment = item::seal_attachment(key, item, &Uuid::new_v4().to_string(), name, &data_map, size, utils::now())?;
+    item.attachments.push(attachment);
+    Ok(())
+}
+
+pub fn attach_file<P: AsRef<Path>>(db: &Database, key: &[u8], item: &mut Item, path: P) -> Result<()> {
+    let path = path.as_ref();
+    let name = path.file_name().ok_or_else(|| anyhow!("{} is not a file", path.display()))?.to_string_lossy().to_string();
+    if std::fs::metadata(path)?.len() > MAX_ATTACHMENT_SIZE {
+        bail!("{} is larger than {} MB", name, MAX_ATTACHMENT_SIZE / 1024 / 1024);
+    }
+    attach(db, key, item, &name, std::fs::read(path)?)
+}
+
+// (file name, content) of the item's attachment
+pub fn read(db: &Database, key: &[u8], item: &Item, id: &str) -> Result<(String, SecretBuf)> {
+    let attachment = item.attachments.iter().find(|attachment| attachment.id == id).ok_or_else(|| anyhow!("no attachment {}", id))?;
+    let (name, data_map) = item::open_attachment(key, item, attachment)?;
+    let stored: StoredMap = serde_json::from_slice(&data_map)?;
+    let mut chunks = Vec::new();
+    for info in stored.data_map.infos() {
+        let content = db.get_chunk(&chunk_hash(&info))?.ok_or_else(|| anyhow!("chunk {} of {} is missing", info.index, name))?;
+        chunks.push(EncryptedChunk { index: info.index, content: Bytes::from(content) });
+    }
+    let data = MemData::from_parts(stored.data_map, stored.length, chunks).decrypt()?;
+    Ok((name, data))
+}
+
+/// Writes a decrypted copy to path, or into it under the file's name when it is a
+/// directory. An existing file is never overwritten.
+pub fn save_copy<P: AsRef<Path>>(db: &Database, key: &[u8], item: &Item, id: &str, path: P) -> Result<PathBuf> {
+    let (name, data) = read(db, key, item, id)?;
+    let path = path.as_ref();
+    let target = if path.is_dir() {
+        // a stored name can't point outside the directory
+        let name = Path::new(&name).file_name().ok_or_else(|| anyhow!("attachment has no file name"))?;
+        path.join(name)
+    } else {
+        path.to_path_buf()
+    };
+    // only the owner can read the decrypted copy
+    utils::write_private(&target, &data)?;
+    Ok(target)
+}
+
+/// Deletes every chunk no attachment of items refers to and returns how many. An
+/// attachment that doesn't open stops it, rather than its chunks being taken for garbage.
+pub fn collect_garbage(db: &Database, key: &[u8], items: &[Item]) -> Result<usize> {
+    let mut used = HashSet::new();
+    for item in items {
+        for attachment in &item.attachments {
+            let (_, data_map) = item::open_attachment(key, item, attachment)?;
+            let stored: StoredMap = serde_json::from_slice(&data_map)?;
+            used.extend(stored.data_map.infos().iter().map(chunk_hash));
+        }
+    }
+    let unused: Vec<String> = db.get_chunk_hashes()?.into_iter().filter(|hash| !used.contains(hash)).collect();
+    db.delete_chunks(&unused)?;
+    Ok(unused.len())
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::TempDatabase;
+
+    #[test]
+    fn attach_and_collect() {
+        let db = TempDatabase::new();
+        let key = utils::aes256_key().unwrap();
+        let document: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut first = item::new_item(&key, "alice", "secret", "").unwrap();
+        let mut second = item::new_item(&key, "bob", "secret", "").unwrap();
+        attach(&db, &key, &mut first, "scan.pdf", document.clone()).unwrap();
+        attach(&db, &key, &mut first, "key.txt", b"small key file".to_vec()).unwrap();
+        let chunks = db.get_chunk_hashes().unwrap().len();
+        // the same document again adds no chunks
+        attach(&db, &key, &mut second, "copy.pdf", document.clone()).unwrap();
+        assert_eq!(db.get_chunk_hashes().unwrap().len(), chunks);
+        assert!(!first.attachments[0].name.contains(&hex::encode("scan.pdf")));
+
+        let (name, data) = read(&db, &key, &first, &first.attachments[0].id).unwrap();
+        assert_eq!((name.as_str(), data.as_bytes()), ("scan.pdf", &document[..]));
+        let (_, data) = read(&db, &key, &first, &first.attachments[1].id).unwrap();
+        assert_eq!(data.as_bytes(), b"small key file");
+        let new_key = utils::aes256_key().unwrap();
+        let moved = item::reencrypt(&first, &key, &new_key).unwrap();
+        assert_eq!(read(&db, &new_key, &moved, &first.attachments[1].id).unwrap().1.as_bytes(), b"small key file");
+
+        let dir = std::env::temp_dir().join(format!("cpandas-attachment-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = save_copy(&db, &key, &first, &first.attachments[0].id, &dir).unwrap();
+        assert_eq!(path, dir.join("scan.pdf"));
+        assert_eq!(std::fs::read(&path).unwrap(), document);
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+        assert!(save_copy(&db, &key, &first, &first.attachments[0].id, &path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // deleting the first item keeps the document chunks the second one shares
+        assert_eq!(collect_garbage(&db, &key, &[first.clone(), second.clone()]).unwrap(), 0);
+        assert!(collect_garbage(&db, &key, &[second.clone()]).unwrap() > 0);
+        assert_eq!(read(&db, &key, &second, &second.attachments[0].id).unwrap().1.as_bytes(), &document[..]);
+        assert!(read(&db, &key, &first, &first.attachments[1].id).is_err());
+        let left = db.get_chunk_hashes().unwrap().len();
+        assert_eq!(collect_garbage(&db, &key, &[]).unwrap(), left);
+        assert!(db.get_chunk_hashes().unwrap().is_empty());
+    }
+}
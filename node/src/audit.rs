@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use hmac::{Mac, SimpleHmac};
+use serde::Serialize;
+use sha2::Sha256;
+
+use super::hibp::BreachList;
+use super::item;
+use super::strength::{self, Hashing};
+use super::types::{Entry, FieldKind, ItemStatus, Settings};
+use super::utils;
+
+// secrets are compared by a hash keyed with this subkey, never by plaintext
+const REUSE_HASH_CONTEXT: &[u8] = b"cpandas:audit:reuse";
+// each problem by the name --fail-on takes, with its severity, higher is worse
+const SEVERITIES: [(&str, u8); 6] = [("breached", 5), ("reused", 4), ("weak", 3), ("old", 2), ("no-2fa", 1), ("empty-desc", 0)];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Problem {
+    Breached { count: u32 },
+    // every item of a group shares one secret
+    Reused { group: usize, count: usize },
+    Weak { score: u8 },
+    Old { days: u64 },
+    NoTwoFactor,
+    EmptyDesc,
+}
+
+impl Problem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Problem::Breached { .. } => "breached",
+            Problem::Reused { .. } => "reused",
+            Problem::Weak { .. } => "weak",
+            Problem::Old { .. } => "old",
+            Problem::NoTwoFactor => "no-2fa",
+            Problem::EmptyDesc => "empty-desc",
+        }
+    }
+
+    // higher is worse
+    pub fn severity(&self) -> u8 {
+        Problem::severity_named(self.name()).unwrap_or_default()
+    }
+
+    // the lowest severity --fail-on takes by name
+    pub fn severity_named(name: &str) -> Option<u8> {
+        SEVERITIES.iter().find(|(named, _)| *named == name).map(|(_, severity)| *severity)
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Problem::Breached { count } => format!("seen {} times in breaches", count),
+            Problem::Reused { group, count } => format!("same secret as {} other items (group {})", count - 1, group),
+            Problem::Weak { score } => format!("weak secret, score {} of {}", score, strength::MAX_SCORE),
+            Problem::Old { days } => format!("not rotated for {} days", days),
+            Problem::NoTwoFactor => "no one-time code set up".to_string(),
+            Problem::EmptyDesc => "empty description".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    // index into the audited entries
+    pub index: usize,
+    pub id: String,
+    pub account: String,
+    pub problem: Problem,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditOptions {
+    // secrets scoring lower are weak
+    pub min_score: u8,
+    // secrets older than this are due, 0 turns the check off
+    pub rotation_days: u64,
+}
+
+impl From<&Settings> for AuditOptions {
+    fn from(settings: &Settings) -> Self {
+        AuditOptions { min_score: settings.min_password_score, rotation_days: settings.rotation_days }
+    }
+}
+
+fn reuse_hash(reuse_key: &[u8], secret: &[u8]) -> Result<Vec<u8>> {
+    let mut mac = <SimpleHmac<Sha256> as Mac>::new_from_slice(reuse_key).map_err(|e| anyhow!("{}",e))?;
+    mac.update(secret);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Decrypts every secret in turn and reports what is wrong with each entry. Tampered
+/// items are left out, they show up as such in the list already.
+pub fn audit(key: &[u8], entries: &[Entry], options: &AuditOptions, breaches: Option<&BreachList>, now: u64) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut hashes: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+    let reuse_key = utils::subkey(key, REUSE_HASH_CONTEXT)?;
+    for (index, entry) in entries.iter().enumerate() {
+        // trashed items are on their way out
+        if entry.item.status == ItemStatus::Trashed {
+            continue;
+        }
+        let secret = match item::open_secret(key, &entry.item) {
+            Ok(secret) => secret,
+            Err(e) if e.is::<item::TamperError>() => continue,
+            Err(e) => return Err(e),
+        };
+        let mut found = |problem| findings.push(Finding {
+            index,
+            id: entry.item.id.clone(),
+            account: entry.account.clone(),
+            problem,
+        });
+        // card numbers, notes and keys are not passwords
+        let password = entry.item.kind.has_password();
+        if password && !secret.is_empty() {
+            if let Some(list) = breaches {
+                let count = list.count(&secret)?;
+                if count > 0 {
+                    found(Problem::Breached { count });
+                }
+            }
+            let score = strength::estimate(secret.as_str().unwrap_or_default(), &[&entry.account, &entry.desc], Hashing::Fast).score;
+            if score < options.min_score {
+                found(Problem::Weak { score });
+            }
+            hashes.entry(reuse_hash(&reuse_key, &secret)?).or_default().push(index);
+        }
+        if let Some(days) = item::rotation_due(&entry.item, now, options.rotation_days) {
+            found(Problem::Old { days });
+        }
+        // a TOTP custom field counts the same as the one-time code of the item
+        let totp_field = entry.item.fields.iter().any(|field| field.kind == FieldKind::Totp);
+        if password && entry.item.otp == "" && !totp_field {
+            found(Problem::NoTwoFactor);
+        }
+        if entry.desc.trim() == "" {
+            found(Problem::EmptyDesc);
+        }
+    }
+    // groups numbered by their first item, so the report reads the same every time
+    let mut groups: Vec<Vec<usize>> = hashes.into_values().filter(|group| group.len() > 1).collect();
+    groups.sort();
+    for (group, indexes) in groups.iter().enumerate() {
+        for index in indexes {
+            findings.push(Finding {
+                index: *index,
+                id: entries[*index].item.id.clone(),
+                account: entries[*index].account.clone(),
+                problem: Problem::Reused { group: group + 1, count: indexes.len() },
+            });
+        }
+    }
+    sort_by_severity(&mut findings);
+    Ok(findings)
+}
+
+// worst first, then by account
+pub fn sort_by_severity(findings: &mut [Finding]) {
+    findings.sort_by(|a, b| b.problem.severity().cmp(&a.problem.severity()).then_with(|| a.account.cmp(&b.account)));
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY_SECS: u64 = 24 * 60 * 60;
+
+    #[test]
+    fn audit_entries() {
+        let key = crate::utils::aes256_key().unwrap();
+        let strong = "tX9#qv!Lm2$wR8zK";
+        let uri = "otpauth://totp/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        let mut items = vec![
+            item::new_item(&key, "alice", strong, "mail").unwrap(),
+            item::new_item(&key, "bob", "password", "bank").unwrap(),
+            item::new_item(&key, "carol", strong, "").unwrap(),
+            item::new_item(&key, "dave", "Zk8#vq!Lm2$wR9tX", "shop").unwrap(),
+        ];
+        item::seal_otp(&key, &mut items[0], uri).unwrap();
+        item::seal_otp(&key, &mut items[1], uri).unwrap();
+        item::seal_otp(&key, &mut items[2], uri).unwrap();
+        let now = items[3].secret_changed + 400 * DAY_SECS;
+        items[0].secret_changed = now - DAY_SECS;
+        items[1].secret_changed = now;
+        items[2].secret_changed = 0;
+        let entries: Vec<Entry> = items.iter().map(|it| item::open_entry(&key, it).unwrap()).collect();
+
+        let options = AuditOptions { min_score: 3, rotation_days: 180 };
+        let findings = audit(&key, &entries, &options, None, now).unwrap();
+        let problems: Vec<(&str, &Problem)> = findings.iter().map(|f| (f.account.as_str(), &f.problem)).collect();
+        assert_eq!(problems, vec![
+            ("alice", &Problem::Reused { group: 1, count: 2 }),
+            ("carol", &Problem::Reused { group: 1, count: 2 }),
+            ("bob", &Problem::Weak { score: 0 }),
+            ("dave", &Problem::Old { days: 400 }),
+            ("dave", &Problem::NoTwoFactor),
+            ("carol", &Problem::EmptyDesc),
+        ]);
+        assert_eq!(findings[2].index, 1);
+        assert_eq!(findings[2].id, items[1].id);
+
+        let mut note = items[1].clone();
+        note.kind = crate::kind::ItemKind::Note;
+        let notes = vec![item::open_entry(&key, &note).unwrap()];
+        assert_eq!(audit(&key, &notes, &options, None, now).unwrap(), vec![]);
+        // a trashed copy doesn't count as reuse
+        let mut trashed = entries.clone();
+        trashed[2].item.status = ItemStatus::Trashed;
+        assert!(audit(&key, &trashed, &options, None, now).unwrap().iter().all(|f| f.account != "carol" && !matches!(f.problem, Problem::Reused { .. })));
+
+        let settings = Settings { min_password_score: 1, ..Default::default() };
+        assert_eq!(AuditOptions::from(&settings), AuditOptions { min_score: 1, rotation_days: settings.rotation_days });
+        let failing: Vec<&Finding> = findings.iter().filter(|f| f.problem.severity() >= Problem::severity_named("weak").unwrap()).collect();
+        assert_eq!(failing.len(), 3);
+        assert!(Problem::Breached { count: 1 }.severity() > Problem::Reused { group: 0, count: 2 }.severity());
+        assert_eq!(Problem::NoTwoFactor.severity(), Problem::severity_named("no-2fa").unwrap());
+        assert_eq!(Problem::severity_named("2fa"), None);
+        // the reuse hash is keyed apart from the vault key
+        assert_ne!(reuse_hash(&key, b"password").unwrap(), reuse_hash(&utils::subkey(&key, REUSE_HASH_CONTEXT).unwrap(), b"password").unwrap());
+
+        let options = AuditOptions { min_score: 0, rotation_days: 0 };
+        assert_eq!(audit(&key, &entries, &options, None, now).unwrap().len(), 4);
+
+        let mut dave = items[3].clone();
+        item::add_field(&key, &mut dave, FieldKind::Totp, "code", uri).unwrap();
+        let dave = vec![item::open_entry(&key, &dave).unwrap()];
+        assert!(audit(&key, &dave, &options, None, now).unwrap().iter().all(|f| f.problem != Problem::NoTwoFactor));
+    }
+}
@@ -0,0 +1,240 @@
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::{AeadInPlace, NewAead, Nonce, Tag};
+use anyhow::{anyhow, bail, Result};
+use chacha20poly1305::{ChaCha20Poly1305, XChaCha20Poly1305};
+use serde::{Deserialize, Serialize};
+
+use super::secret::SecretBuf;
+use super::utils;
+
+const ENVELOPE_VERSION: u8 = 1;
+const TAG_NUM: usize = 16;
+
+// suite for everything sealed from now on, the 24 byte nonce is safe to pick at random
+// for any number of items
+pub const DEFAULT_SUITE: SuiteId = SuiteId::XChaCha20Poly1305;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum SuiteId {
+    Aes256Gcm = 1,
+    ChaCha20Poly1305 = 2,
+    XChaCha20Poly1305 = 3,
+}
+
+impl SuiteId {
+    pub fn from_u8(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(SuiteId::Aes256Gcm),
+            2 => Ok(SuiteId::ChaCha20Poly1305),
+            3 => Ok(SuiteId::XChaCha20Poly1305),
+            _ => bail!("unknown cipher suite {}", id),
+        }
+    }
+
+    pub fn suite(self) -> &'static dyn CipherSuite {
+        match self {
+            SuiteId::Aes256Gcm => &Aes256GcmSuite,
+            SuiteId::ChaCha20Poly1305 => &ChaCha20Poly1305Suite,
+            SuiteId::XChaCha20Poly1305 => &XChaCha20Poly1305Suite,
+        }
+    }
+}
+
+/// An AEAD with a 256 bit key and a 16 byte tag.
+pub trait CipherSuite {
+    fn id(&self) -> SuiteId;
+    fn nonce_len(&self) -> usize;
+    // returns (ciphertext, tag)
+    fn encrypt(&self, key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+    fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBuf>;
+}
+
+pub struct Aes256GcmSuite;
+
+pub struct ChaCha20Poly1305Suite;
+
+pub struct XChaCha20Poly1305Suite;
+
+impl CipherSuite for Aes256GcmSuite {
+    fn id(&self) -> SuiteId {
+        SuiteId::Aes256Gcm
+    }
+
+    fn nonce_len(&self) -> usize {
+        12
+    }
+
+    fn encrypt(&self, key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        encrypt_detached::<Aes256Gcm>(key, nonce, msg, aad)
+    }
+
+    fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+        decrypt_detached::<Aes256Gcm>(key, nonce, ciphertext, tag, aad)
+    }
+}
+
+impl CipherSuite for ChaCha20Poly1305Suite {
+    fn id(&self) -> SuiteId {
+        SuiteId::ChaCha20Poly1305
+    }
+
+    fn nonce_len(&self) -> usize {
+        12
+    }
+
+    fn encrypt(&self, key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        encrypt_detached::<ChaCha20Poly1305>(key, nonce, msg, aad)
+    }
+
+    fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+        decrypt_detached::<ChaCha20Poly1305>(key, nonce, ciphertext, tag, aad)
+    }
+}
+
+impl CipherSuite for XChaCha20Poly1305Suite {
+    fn id(&self) -> SuiteId {
+        SuiteId::XChaCha20Poly1305
+    }
+
+    fn nonce_len(&self) -> usize {
+        24
+    }
+
+    fn encrypt(&self, key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        encrypt_detached::<XChaCha20Poly1305>(key, nonce, msg, aad)
+    }
+
+    fn decrypt(&self, key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+        decrypt_detached::<XChaCha20Poly1305>(key, nonce, ciphertext, tag, aad)
+    }
+}
+
+fn encrypt_detached<A: NewAead + AeadInPlace>(key: &[u8], nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let cipher = A::new_from_slice(key).map_err(|e| anyhow!("{}",e))?;
+    if nonce.len() != Nonce::<A>::default().len() {
+        bail!("invalid nonce length {}", nonce.len());
+    }
+    let mut buffer = msg.to_vec();
+    let tag = cipher.encrypt_in_place_detached(Nonce::<A>::from_slice(nonce), aad, &mut buffer)
+        .map_err(|e| anyhow!("{}",e))?;
+    Ok((buffer, tag.to_vec()))
+}
+
+fn decrypt_detached<A: NewAead + AeadInPlace>(key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+    let cipher = A::new_from_slice(key).map_err(|e| anyhow!("{}",e))?;
+    if nonce.len() != Nonce::<A>::default().len() || tag.len() != Tag::<A>::default().len() {
+        bail!("invalid nonce or tag length");
+    }
+    let mut buffer = ciphertext.to_vec();
+    cipher.decrypt_in_place_detached(Nonce::<A>::from_slice(nonce), aad, &mut buffer, Tag::<A>::from_slice(tag))
+        .map_err(|e| anyhow!("{}",e))?;
+    SecretBuf::from_vec(buffer)
+}
+
+/// A self describing ciphertext, stored as
+/// version (1 byte) || suite id (1 byte) || nonce || ciphertext || tag (16 bytes).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    pub suite: SuiteId,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn seal(suite: SuiteId, key: &[u8], msg: &[u8], aad: &[u8]) -> Result<Self> {
+        let cipher = suite.suite();
+        let nonce = utils::gen_rand_key(cipher.nonce_len())?;
+        let (ciphertext, tag) = cipher.encrypt(key, &nonce, msg, aad)?;
+        Ok(Envelope { suite, nonce, ciphertext, tag })
+    }
+
+    pub fn open(&self, key: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+        self.suite.suite().decrypt(key, &self.nonce, &self.ciphertext, &self.tag, aad)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![ENVELOPE_VERSION, self.suite as u8];
+        data.extend_from_slice(&self.nonce);
+        data.extend_from_slice(&self.ciphertext);
+        data.extend_from_slice(&self.tag);
+        data
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < 2 {
+            bail!("envelope too short");
+        }
+        if data[0] != ENVELOPE_VERSION {
+            bail!("unknown envelope version {}", data[0]);
+        }
+        let suite = SuiteId::from_u8(data[1])?;
+        let nonce_len = suite.suite().nonce_len();
+        let body = &data[2..];
+        if body.len() < nonce_len + TAG_NUM {
+            bail!("envelope too short");
+        }
+        let (nonce, rest) = body.split_at(nonce_len);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_NUM);
+        Ok(Envelope { suite, nonce: nonce.to_vec(), ciphertext: ciphertext.to_vec(), tag: tag.to_vec() })
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_bytes())
+    }
+
+    pub fn from_hex(data: &str) -> Result<Self> {
+        Envelope::from_bytes(&hex::decode(data)?)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SUITES: [SuiteId; 3] = [SuiteId::Aes256Gcm, SuiteId::ChaCha20Poly1305, SuiteId::XChaCha20Poly1305];
+
+    #[test]
+    fn seal_and_open() {
+        let key = utils::aes256_key().unwrap();
+        for suite in SUITES {
+            let envelope = Envelope::seal(suite, &key, b"secret", b"aad").unwrap();
+            assert_eq!(envelope.suite.suite().id(), suite);
+            assert_eq!(envelope.nonce.len(), suite.suite().nonce_len());
+            assert_eq!(envelope.tag.len(), TAG_NUM);
+            assert_eq!(envelope.open(&key, b"aad").unwrap().as_bytes(), b"secret");
+            assert!(envelope.open(&key, b"other aad").is_err());
+            assert!(envelope.open(&utils::aes256_key().unwrap(), b"aad").is_err());
+
+            let parsed = Envelope::from_hex(&envelope.to_hex()).unwrap();
+            assert_eq!(parsed, envelope);
+            assert_eq!(parsed.open(&key, b"aad").unwrap().as_bytes(), b"secret");
+
+            let mut tampered = envelope.clone();
+            tampered.tag[0] ^= 1;
+            assert!(tampered.open(&key, b"aad").is_err());
+        }
+        assert_eq!(SuiteId::XChaCha20Poly1305.suite().nonce_len(), 24);
+    }
+
+    #[test]
+    fn aes_suite_matches_utils() {
+        let key = utils::aes256_key().unwrap();
+        let (ciphertext, nonce) = utils::aes256_encode_aad(b"secret", &key, b"aad").unwrap();
+        let (body, tag) = ciphertext.split_at(ciphertext.len() - TAG_NUM);
+        let plain = Aes256GcmSuite.decrypt(&key, &nonce, body, tag, b"aad").unwrap();
+        assert_eq!(plain.as_bytes(), b"secret");
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Envelope::from_bytes(&[]).is_err());
+        assert!(Envelope::from_bytes(&[2, 1]).is_err());
+        assert!(Envelope::from_bytes(&[ENVELOPE_VERSION, 9]).is_err());
+        assert!(Envelope::from_bytes(&[ENVELOPE_VERSION, 3, 0, 0]).is_err());
+        let key = utils::aes256_key().unwrap();
+        assert!(Aes256GcmSuite.encrypt(&key[..16], &[0; 12], b"msg", b"").is_err());
+        assert!(Aes256GcmSuite.encrypt(&key, &[0; 24], b"msg", b"").is_err());
+    }
+}
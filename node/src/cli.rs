@@ -0,0 +1,119 @@
+use anyhow::{anyhow, bail, Result};
+use copypasta::{ClipboardContext, ClipboardProvider};
+use zeroize::Zeroizing;
+
+use super::{audit, DB, generator, item, utils, vault};
+use super::audit::{AuditOptions, Problem};
+use super::hibp::BreachList;
+use super::secret::SecretBuf;
+use super::types::ItemStatus;
+
+const USAGE: &str = "usage: CPandas [list | otp <account> | generate | audit [--json] [--fail-on <problem>]]
+    list            list the accounts of the vault
+    otp <account>   print the one-time code of an account and copy it
+    generate        print a new secret with the last used generator policy
+    audit           report weak, reused, old and breached secrets, fails on problems as bad as
+                    --fail-on: breached, reused, weak (the default), old, no-2fa or empty-desc
+the password is read from CPANDAS_PASSWORD when set, for unattended jobs
+without arguments the GUI starts";
+
+const PASSWORD_ENV: &str = "CPANDAS_PASSWORD";
+// weak, reused and breached secrets fail a job, the rest is only reported
+const DEFAULT_FAIL_ON: &str = "weak";
+
+// runs one command against the vault in the working directory
+pub fn run(args: &[String]) -> Result<()> {
+    match args.first().map(|arg| arg.as_str()) {
+        Some("list") => list(),
+        Some("generate") => generate(),
+        Some("audit") => {
+            let json = args.iter().any(|arg| arg == "--json");
+            let fail_on = match args.iter().position(|arg| arg == "--fail-on") {
+                Some(i) => args.get(i + 1).map(|name| name.as_str()),
+                None => Some(DEFAULT_FAIL_ON),
+            };
+            let fail_on = fail_on.and_then(Problem::severity_named).ok_or_else(|| anyhow!("{}", USAGE))?;
+            audit(json, fail_on)
+        }
+        Some("otp") => match args.get(1) {
+            Some(account) => otp_code(account),
+            None => bail!("{}", USAGE),
+        },
+        _ => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn unlock() -> Result<SecretBuf> {
+    // unlock creates a vault when there is none, that is left to the GUI
+    if !vault::is_created(&DB)? {
+        bail!("no vault here, create one in the GUI first");
+    }
+    let password = match std::env::var(PASSWORD_ENV) {
+        Ok(password) => Zeroizing::new(password),
+        Err(_) => Zeroizing::new(rpassword::prompt_password("Password: ")?),
+    };
+    vault::unlock(&DB, password.as_bytes())?.ok_or_else(|| anyhow!("password not correct"))
+}
+
+fn list() -> Result<()> {
+    let key = unlock()?;
+    for entry in item::open_entries(&DB, &key)? {
+        if entry.item.status == ItemStatus::Trashed {
+            continue;
+        }
+        let otp = if entry.item.otp != "" { "\t[otp]" } else { "" };
+        println!("{}\t{}{}", entry.account, entry.desc, otp);
+    }
+    Ok(())
+}
+
+fn audit(json: bool, fail_on: u8) -> Result<()> {
+    let key = unlock()?;
+    let settings = DB.get_settings()?.unwrap_or_default();
+    let breaches = match settings.hibp_path.as_str() {
+        "" => None,
+        path => Some(BreachList::open(path)?),
+    };
+    let entries = item::open_entries(&DB, &key)?;
+    let findings = audit::audit(&key, &entries, &AuditOptions::from(&settings), breaches.as_ref(), utils::now())?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in &findings {
+            println!("{}\t{}\t{}", finding.account, finding.id, finding.problem.describe());
+        }
+    }
+    let failing = findings.iter().filter(|finding| finding.problem.severity() >= fail_on).count();
+    if failing > 0 {
+        bail!("{} of {} problems found are as bad as --fail-on", failing, findings.len());
+    }
+    Ok(())
+}
+
+// the policy is not secret, so no unlock is needed
+fn generate() -> Result<()> {
+    let policy = DB.get_generator_policy()?.unwrap_or_default();
+    let secret = generator::generate(&policy)?;
+    println!("{}", secret.as_str()?);
+    Ok(())
+}
+
+fn otp_code(account: &str) -> Result<()> {
+    let key = unlock()?;
+    let entry = item::open_entries(&DB, &key)?.into_iter()
+        .find(|entry| entry.account == account && entry.item.otp != "" && entry.item.status != ItemStatus::Trashed)
+        .ok_or_else(|| anyhow!("no authenticator item for {}", account))?;
+    let mut it = entry.item.clone();
+    let code = item::take_otp_code(&key, &mut it, utils::now())?.ok_or_else(|| anyhow!("no otp for {}", account))?;
+    it.last_used = utils::now();
+    DB.put_item(&it)?;
+    println!("{}", code);
+    let copied = ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(code));
+    if let Err(e) = copied {
+        log::warn!("copy to clipboard failed: {}", e);
+    }
+    Ok(())
+}
@@ -1,6 +1,16 @@
 pub const SECRET_HASH_KEY: &str = "secretHashKey";
 pub const ITEM_KEYS_KEY: &str = "itemKeys";
 pub const ITEM_LIST_KEY: &str = "itemList";
+pub const KDF_PARAMS_KEY: &str = "kdfParams";
+pub const VAULT_HEADER_KEY: &str = "vaultHeader";
+pub const GENERATOR_POLICY_KEY: &str = "generatorPolicy";
+pub const SETTINGS_KEY: &str = "settings";
+// followed by the item id
+pub const HISTORY_KEY_PREFIX: &str = "history:";
+// followed by an index term, the value is the ids of the items under it
+pub const INDEX_KEY_PREFIX: &str = "index:";
+// followed by the hex hash of the encrypted chunk
+pub const CHUNK_KEY_PREFIX: &str = "chunk:";
 
 pub const DEFAULT_FONT_PATH: &str = "../../fonts/Hack-Regular.ttf";
 
@@ -1,11 +1,13 @@
+use std::collections::BTreeMap;
 use std::path::Path;
 
 use anyhow::{anyhow, bail, Result};
-use rocksdb::{DB, Error, Options};
+use rocksdb::{DB, Error, Options, WriteBatch};
 use uuid::Uuid;
 
-use super::constants::{ITEM_KEYS_KEY, ITEM_LIST_KEY, SECRET_HASH_KEY};
-use super::types::Item;
+use super::constants::{GENERATOR_POLICY_KEY, CHUNK_KEY_PREFIX, HISTORY_KEY_PREFIX, INDEX_KEY_PREFIX, ITEM_KEYS_KEY, ITEM_LIST_KEY, KDF_PARAMS_KEY, SECRET_HASH_KEY, SETTINGS_KEY, VAULT_HEADER_KEY};
+use super::generator::Policy;
+use super::types::{FieldVersion, Item, KdfParams, Settings, VaultHeader};
 
 #[derive(Debug)]
 pub struct Database {
@@ -30,6 +32,70 @@ impl Database {
         self.db.get(SECRET_HASH_KEY).map_err(|e| anyhow!("{}",e))
     }
 
+    pub fn get_kdf_params(&self) -> Result<Option<KdfParams>> {
+        let result = self.db.get(KDF_PARAMS_KEY).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let params: KdfParams = serde_json::from_slice(&data)?;
+            return Ok(Some(params));
+        }
+        Ok(None)
+    }
+
+    pub fn put_vault_header(&self, header: &VaultHeader) -> Result<()> {
+        let data = serde_json::to_string(header)?;
+        self.db.put(VAULT_HEADER_KEY, data).map_err(|e| anyhow!("{}",e))
+    }
+
+    pub fn get_vault_header(&self) -> Result<Option<VaultHeader>> {
+        let result = self.db.get(VAULT_HEADER_KEY).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let header: VaultHeader = serde_json::from_slice(&data)?;
+            return Ok(Some(header));
+        }
+        Ok(None)
+    }
+
+    pub fn put_generator_policy(&self, policy: &Policy) -> Result<()> {
+        let data = serde_json::to_string(policy)?;
+        self.db.put(GENERATOR_POLICY_KEY, data).map_err(|e| anyhow!("{}",e))
+    }
+
+    pub fn get_generator_policy(&self) -> Result<Option<Policy>> {
+        let result = self.db.get(GENERATOR_POLICY_KEY).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let policy: Policy = serde_json::from_slice(&data)?;
+            return Ok(Some(policy));
+        }
+        Ok(None)
+    }
+
+    pub fn put_settings(&self, settings: &Settings) -> Result<()> {
+        let data = serde_json::to_string(settings)?;
+        self.db.put(SETTINGS_KEY, data).map_err(|e| anyhow!("{}",e))
+    }
+
+    pub fn get_settings(&self) -> Result<Option<Settings>> {
+        let result = self.db.get(SETTINGS_KEY).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let settings: Settings = serde_json::from_slice(&data)?;
+            return Ok(Some(settings));
+        }
+        Ok(None)
+    }
+
+    // replace the vault header and every re-encrypted item in one write, so the vault
+    // is never left with items under two different keys
+    pub fn rekey(&self, header: &VaultHeader, items: &[Item]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        batch.put(VAULT_HEADER_KEY, serde_json::to_string(header)?);
+        batch.delete(SECRET_HASH_KEY);
+        batch.delete(KDF_PARAMS_KEY);
+        for item in items {
+            batch.put(&item.id, serde_json::to_string(item)?);
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
     pub fn put_item(&self, item: &Item) -> Result<()> {
         let uuid = &item.id;
         let data = serde_json::to_string(item)?;
@@ -37,6 +103,15 @@ impl Database {
         self.db.put(uuid, data).map_err(|e| anyhow!("{}",e))
     }
 
+    // items already in the item keys, written in one batch
+    pub fn put_items(&self, items: &[Item]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for item in items {
+            batch.put(&item.id, serde_json::to_string(item)?);
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
     pub fn get_item<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Item>> {
         let result = self.db.get(key).map_err(|e| anyhow!("{}",e))?;
         if let Some(data) = result {
@@ -46,10 +121,46 @@ impl Database {
         Ok(None)
     }
 
+    // the item, its key and its history go in one write
     pub fn del_item(&self, key: &str) -> Result<()> {
-        self.delete_item_key(key)?;
-        self.db.delete(key).map_err(|e| anyhow!("{}",e))?;
-        Ok(())
+        let mut batch = WriteBatch::default();
+        if let Some(mut keys) = self.get_item_keys()? {
+            keys.retain(|k| k != key);
+            batch.put(ITEM_KEYS_KEY, serde_json::to_string(&keys)?);
+        }
+        batch.delete(key);
+        batch.delete(history_key(key));
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
+    // oldest first, empty when the item was never changed
+    pub fn get_history(&self, id: &str) -> Result<Vec<FieldVersion>> {
+        let result = self.db.get(history_key(id)).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let versions: Vec<FieldVersion> = serde_json::from_slice(&data)?;
+            return Ok(versions);
+        }
+        Ok(Vec::new())
+    }
+
+    pub fn put_history(&self, id: &str, versions: &[FieldVersion]) -> Result<()> {
+        if versions.is_empty() {
+            return self.db.delete(history_key(id)).map_err(|e| anyhow!("{}",e));
+        }
+        let data = serde_json::to_string(versions)?;
+        self.db.put(history_key(id), data).map_err(|e| anyhow!("{}",e))
+    }
+
+    // the changed item and its history in one write
+    pub fn put_item_history(&self, item: &Item, versions: &[FieldVersion]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        batch.put(&item.id, serde_json::to_string(item)?);
+        if versions.is_empty() {
+            batch.delete(history_key(&item.id));
+        } else {
+            batch.put(history_key(&item.id), serde_json::to_string(versions)?);
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
     }
 
 
@@ -69,10 +180,14 @@ impl Database {
     }
 
 
+    // an item saved again keeps its one key
     fn put_item_key(&self, value: &str) -> Result<()> {
         let result = self.db.get(ITEM_KEYS_KEY).map_err(|e| anyhow!("{}",e))?;
         if let Some(data) = result {
             let mut keys: Vec<&str> = serde_json::from_slice(&data)?;
+            if keys.contains(&value) {
+                return Ok(());
+            }
             keys.push(value);
             let db_keys = serde_json::to_string(&keys)?;
             self.db.put(ITEM_KEYS_KEY, db_keys)?;
@@ -86,30 +201,95 @@ impl Database {
     }
 
 
-    fn delete_item_key(&self, key: &str) -> Result<()> {
-        let result = self.get_item_keys()?;
-        let mut keys: Vec<String> = Vec::new();
-        if let Some(list) = result {
-            for k in list {
-                if k != key {
-                    keys.push(k)
-                }
-            }
-            let db_keys = serde_json::to_string(&keys)?;
-            self.db.put(ITEM_KEYS_KEY, db_keys).map_err(|e| anyhow!("{}",e))?;
-        }
-        Ok(())
-    }
-
+    // keys written more than once by older versions are only returned once
     fn get_item_keys(&self) -> Result<Option<Vec<String>>> {
         let result = self.db.get(ITEM_KEYS_KEY).map_err(|e| anyhow!("{}",e))?;
         if let Some(data) = result {
-            let keys: Vec<String> = serde_json::from_slice(&data)?;
+            let mut keys: Vec<String> = serde_json::from_slice(&data)?;
+            let mut seen = std::collections::HashSet::new();
+            keys.retain(|key| seen.insert(key.clone()));
             return Ok(Some(keys));
         }
         Ok(None)
     }
 
+    pub fn get_index(&self, term: &str) -> Result<Vec<String>> {
+        let result = self.db.get(index_key(term)).map_err(|e| anyhow!("{}",e))?;
+        if let Some(data) = result {
+            let ids: Vec<String> = serde_json::from_slice(&data)?;
+            return Ok(ids);
+        }
+        Ok(Vec::new())
+    }
+
+    // takes the item out of the removed terms and puts it under the added ones, in one write
+    pub fn update_index(&self, id: &str, removed: &[String], added: &[String]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for term in removed.iter().chain(added) {
+            let mut ids = self.get_index(term)?;
+            ids.retain(|other| other != id);
+            if added.contains(term) {
+                ids.push(id.to_string());
+            }
+            if ids.is_empty() {
+                batch.delete(index_key(term));
+            } else {
+                batch.put(index_key(term), serde_json::to_string(&ids)?);
+            }
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
+    // replaces every index term
+    pub fn rebuild_index(&self, index: &BTreeMap<String, Vec<String>>) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        let old = self.db.prefix_iterator(INDEX_KEY_PREFIX)
+            .take_while(|(key, _)| key.starts_with(INDEX_KEY_PREFIX.as_bytes()));
+        for (key, _) in old {
+            batch.delete(key);
+        }
+        for (term, ids) in index {
+            batch.put(index_key(term), serde_json::to_string(ids)?);
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
+    // chunks are content addressed, one already stored is the same chunk and is kept
+    pub fn put_chunks(&self, chunks: &[(String, &[u8])]) -> Result<usize> {
+        let mut batch = WriteBatch::default();
+        let mut added = 0;
+        for (hash, content) in chunks {
+            let key = chunk_key(hash);
+            if self.db.get(&key).map_err(|e| anyhow!("{}",e))?.is_none() {
+                batch.put(key, content);
+                added += 1;
+            }
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))?;
+        Ok(added)
+    }
+
+    pub fn get_chunk(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        self.db.get(chunk_key(hash)).map_err(|e| anyhow!("{}",e))
+    }
+
+    // hashes of every stored chunk
+    pub fn get_chunk_hashes(&self) -> Result<Vec<String>> {
+        let hashes = self.db.prefix_iterator(CHUNK_KEY_PREFIX)
+            .take_while(|(key, _)| key.starts_with(CHUNK_KEY_PREFIX.as_bytes()))
+            .map(|(key, _)| String::from_utf8_lossy(&key[CHUNK_KEY_PREFIX.len()..]).to_string())
+            .collect();
+        Ok(hashes)
+    }
+
+    pub fn delete_chunks(&self, hashes: &[String]) -> Result<()> {
+        let mut batch = WriteBatch::default();
+        for hash in hashes {
+            batch.delete(chunk_key(hash));
+        }
+        self.db.write(batch).map_err(|e| anyhow!("{}",e))
+    }
+
     pub fn put<K, V>(&self, key: K, value: V) -> Result<()> where K: AsRef<[u8]>, V: AsRef<[u8]> {
         self.db.put(key, value).map_err(|e| anyhow!("{}",e))
     }
@@ -123,6 +303,52 @@ impl Database {
     }
 }
 
+/// A database in a temp directory of its own, removed again when dropped.
+#[cfg(test)]
+pub struct TempDatabase {
+    path: std::path::PathBuf,
+    db: Option<Database>,
+}
+
+#[cfg(test)]
+impl TempDatabase {
+    pub fn new() -> Self {
+        let path = std::env::temp_dir().join(format!("cpandas-{}", Uuid::new_v4()));
+        let db = Database::new(&path).unwrap();
+        TempDatabase { path, db: Some(db) }
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        self.db.as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        // closed before its files are removed
+        self.db.take();
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+fn history_key(id: &str) -> String {
+    format!("{}{}", HISTORY_KEY_PREFIX, id)
+}
+
+fn chunk_key(hash: &str) -> String {
+    format!("{}{}", CHUNK_KEY_PREFIX, hash)
+}
+
+fn index_key(term: &str) -> String {
+    format!("{}{}", INDEX_KEY_PREFIX, term)
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -130,7 +356,7 @@ mod tests {
 
     #[test]
     fn db_operation() {
-        let db = Database::new(".db").unwrap();
+        let db = TempDatabase::new();
         let items = db.get_item_list().unwrap();
         if let Some(list) = items {
             for i in list {
@@ -144,11 +370,13 @@ mod tests {
             account: "test01".to_string(),
             secret: "abcd1234".to_string(),
             desc: "email".to_string(),
-            status: 0,
-            nonce: "".to_string()
+            ..Default::default()
         };
         db.put_item(&item).unwrap();
+        db.put_item(&item).unwrap();
         println!("add item: {:?}", uuid);
+        let count = db.get_item_list().unwrap().unwrap().iter().filter(|it| it.id == uuid).count();
+        assert_eq!(count, 1);
 
         let option = db.get_item(&uuid).unwrap();
         if let Some(it) = option {
@@ -164,7 +392,11 @@ mod tests {
         }
 
 
-        // db.del_item(&uuid).unwrap();
+        db.put_history(&uuid, &[FieldVersion { field: "secret".to_string(), value: "sealed".to_string(), replaced: 0 }]).unwrap();
+        db.del_item(&uuid).unwrap();
+        assert!(db.get_item(&uuid).unwrap().is_none());
+        assert!(db.get_history(&uuid).unwrap().is_empty());
+        assert!(db.get_item_list().unwrap().unwrap().iter().all(|it| it.id != uuid));
         let items = db.get_item_list().unwrap();
         if let Some(list) = items {
             for i in list {
@@ -178,7 +410,7 @@ mod tests {
 
     #[test]
     fn secret_hash() {
-        let db = Database::new(".db").unwrap();
+        let db = TempDatabase::new();
         db.put_secret_hash("ddddd").unwrap();
         let option = db.get_secret_hash().unwrap().unwrap();
         let result = String::from_utf8(option).unwrap();
@@ -189,7 +421,7 @@ mod tests {
     #[test]
     fn db_test() {
         //  let db = DB::open_default(".db").unwrap();
-        let database = Database::new(".db").unwrap();
+        let database = TempDatabase::new();
         database.put("account", "password").unwrap();
         let data = database.get("account").unwrap().unwrap();
         println!("{:?}", String::from_utf8(data));
@@ -0,0 +1,225 @@
+use anyhow::{bail, Result};
+use rand::Rng;
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use super::secret::SecretBuf;
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&*+-=?@^_~.,:;()[]{}<>/|";
+// easy to mix up when read or typed by hand
+const AMBIGUOUS: &str = "Il1|O0oS5Z2B8`'\".,:;";
+const CONSONANTS: &str = "bcdfghjklmnprstvwz";
+const VOWELS: &str = "aeiou";
+
+pub const MAX_LENGTH: usize = 128;
+pub const MAX_WORDS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Mode {
+    // characters drawn from the enabled classes
+    Random,
+    // consonant vowel syllables
+    Pronounceable,
+    // diceware words from the EFF large wordlist
+    Passphrase,
+}
+
+/// How secrets are generated, the last used one is saved in the vault.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Policy {
+    pub mode: Mode,
+    pub length: usize,
+    pub lowercase: bool,
+    pub uppercase: bool,
+    pub digits: bool,
+    pub symbols: bool,
+    pub exclude_ambiguous: bool,
+    // random mode only
+    pub min_per_class: usize,
+    pub words: usize,
+    pub separator: String,
+    pub capitalize: bool,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            mode: Mode::Random,
+            length: 20,
+            lowercase: true,
+            uppercase: true,
+            digits: true,
+            symbols: true,
+            exclude_ambiguous: true,
+            min_per_class: 1,
+            words: 6,
+            separator: "-".to_string(),
+            capitalize: false,
+        }
+    }
+}
+
+impl Policy {
+    fn allowed(&self, chars: &str) -> Vec<char> {
+        chars.chars().filter(|c| !self.exclude_ambiguous || !AMBIGUOUS.contains(*c)).collect()
+    }
+
+    fn classes(&self) -> Vec<Vec<char>> {
+        let classes = [
+            (self.lowercase, LOWERCASE),
+            (self.uppercase, UPPERCASE),
+            (self.digits, DIGITS),
+            (self.symbols, SYMBOLS),
+        ];
+        classes.iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, chars)| self.allowed(chars))
+            .collect()
+    }
+}
+
+pub fn generate(policy: &Policy) -> Result<SecretBuf> {
+    let secret = match policy.mode {
+        Mode::Random => random_chars(policy)?,
+        Mode::Pronounceable => pronounceable(policy)?,
+        Mode::Passphrase => passphrase(policy)?,
+    };
+    SecretBuf::from_vec(secret.into_bytes())
+}
+
+fn pick(chars: &[char]) -> char {
+    chars[OsRng.gen_range(0..chars.len())]
+}
+
+fn random_chars(policy: &Policy) -> Result<String> {
+    let classes = policy.classes();
+    if classes.is_empty() {
+        bail!("choose at least one character class");
+    }
+    if policy.length == 0 || policy.length > MAX_LENGTH {
+        bail!("length must be 1 to {}", MAX_LENGTH);
+    }
+    // a loaded policy can hold any count
+    if policy.min_per_class.checked_mul(classes.len()).map_or(true, |needed| needed > policy.length) {
+        bail!("length is too short for {} of each class", policy.min_per_class);
+    }
+    let all: Vec<char> = classes.concat();
+    let mut chars = Vec::with_capacity(policy.length);
+    for class in &classes {
+        for _ in 0..policy.min_per_class {
+            chars.push(pick(class));
+        }
+    }
+    while chars.len() < policy.length {
+        chars.push(pick(&all));
+    }
+    chars.shuffle(&mut OsRng);
+    Ok(chars.into_iter().collect())
+}
+
+fn pronounceable(policy: &Policy) -> Result<String> {
+    if policy.length < 4 || policy.length > MAX_LENGTH {
+        bail!("length must be 4 to {}", MAX_LENGTH);
+    }
+    // one of each other enabled class goes at the end, the letters before it
+    let extras: Vec<Vec<char>> = [(policy.digits, DIGITS), (policy.symbols, SYMBOLS)].iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, class)| policy.allowed(class))
+        .collect();
+    let letters = policy.length - extras.len();
+    // a letter may be uppercased later, so both cases have to be unambiguous
+    let unambiguous = |c: &char| !policy.exclude_ambiguous || !AMBIGUOUS.contains(c.to_ascii_uppercase());
+    let consonants: Vec<char> = policy.allowed(CONSONANTS).into_iter().filter(unambiguous).collect();
+    let vowels: Vec<char> = policy.allowed(VOWELS).into_iter().filter(unambiguous).collect();
+    let mut chars: Vec<char> = (0..letters)
+        .map(|i| if i % 2 == 0 { pick(&consonants) } else { pick(&vowels) })
+        .collect();
+    if policy.uppercase {
+        let i = OsRng.gen_range(0..letters);
+        chars[i] = chars[i].to_ascii_uppercase();
+    }
+    chars.extend(extras.iter().map(|class| pick(class)));
+    Ok(chars.into_iter().collect())
+}
+
+fn passphrase(policy: &Policy) -> Result<String> {
+    if policy.words < 3 || policy.words > MAX_WORDS {
+        bail!("words must be 3 to {}", MAX_WORDS);
+    }
+    let list = &eff_wordlist::large::LIST;
+    let words: Vec<String> = (0..policy.words)
+        .map(|_| {
+            let word = list[OsRng.gen_range(0..list.len())].1;
+            if policy.capitalize {
+                word[..1].to_uppercase() + &word[1..]
+            } else {
+                word.to_string()
+            }
+        })
+        .collect();
+    let mut phrase = words.join(&policy.separator);
+    if policy.digits {
+        phrase.push_str(&policy.separator);
+        phrase.push(pick(&policy.allowed(DIGITS)));
+    }
+    Ok(phrase)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn random_policy() {
+        let policy = Policy { length: 12, min_per_class: 3, ..Default::default() };
+        for _ in 0..50 {
+            let secret = generate(&policy).unwrap();
+            let secret = secret.as_str().unwrap();
+            assert_eq!(secret.chars().count(), 12);
+            for class in [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS] {
+                assert!(secret.chars().filter(|c| class.contains(*c)).count() >= 3);
+            }
+            assert!(!secret.chars().any(|c| AMBIGUOUS.contains(c)));
+        }
+        assert_ne!(generate(&policy).unwrap(), generate(&policy).unwrap());
+
+        let digits = Policy { lowercase: false, uppercase: false, symbols: false, exclude_ambiguous: false, ..Default::default() };
+        assert!(generate(&digits).unwrap().iter().all(|b| b.is_ascii_digit()));
+        assert!(generate(&Policy { length: 7, min_per_class: 2, ..Default::default() }).is_err());
+        assert!(generate(&Policy { length: 12, min_per_class: usize::MAX, ..Default::default() }).is_err());
+        assert!(generate(&Policy { length: 0, ..Default::default() }).is_err());
+        let none = Policy { lowercase: false, uppercase: false, digits: false, symbols: false, ..Default::default() };
+        assert!(generate(&none).is_err());
+    }
+
+    #[test]
+    fn pronounceable_policy() {
+        let policy = Policy { mode: Mode::Pronounceable, length: 10, symbols: false, ..Default::default() };
+        let secret = generate(&policy).unwrap();
+        let secret = secret.as_str().unwrap();
+        assert_eq!(secret.len(), 10);
+        assert!(secret[9..].chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(secret.chars().filter(|c| c.is_ascii_uppercase()).count(), 1);
+        assert!(secret[..9].chars().all(|c| c.is_ascii_alphabetic()));
+        assert!(!secret.chars().any(|c| AMBIGUOUS.contains(c) || "lo".contains(c)));
+        assert!(generate(&Policy { length: 3, ..policy }).is_err());
+    }
+
+    #[test]
+    fn passphrase_policy() {
+        let policy = Policy { mode: Mode::Passphrase, words: 5, digits: false, capitalize: true, separator: " ".to_string(), ..Default::default() };
+        let phrase = generate(&policy).unwrap();
+        let words: Vec<&str> = phrase.as_str().unwrap().split(' ').collect();
+        assert_eq!(words.len(), 5);
+        for word in words {
+            assert!(word.starts_with(|c: char| c.is_ascii_uppercase()));
+            assert!(eff_wordlist::large::LIST.iter().any(|(_, w)| w.eq_ignore_ascii_case(word)));
+        }
+        assert!(generate(&Policy { words: 2, ..policy }).is_err());
+    }
+}
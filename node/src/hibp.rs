@@ -0,0 +1,224 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use memmap2::Mmap;
+use sha1::{Digest, Sha1};
+
+use super::item;
+use super::types::{Entry, ItemStatus};
+
+// binary index: magic, then sorted records of sha1 and little endian breach count
+const INDEX_MAGIC: &[u8; 8] = b"CPHIBP1\0";
+const HASH_NUM: usize = 20;
+const RECORD_NUM: usize = HASH_NUM + 4;
+const PROGRESS_RECORDS: u64 = 1 << 20;
+// lines from the top of a text file checked for order when it is opened
+const ORDER_CHECK_LINES: usize = 64;
+const NOT_SORTED: &str = "the HIBP file must be the version ordered by hash";
+
+/// A local copy of the Have I Been Pwned password list, either the sorted
+/// "SHA1:count" text download or an index built from it. It is only ever read from
+/// disk, nothing goes over the network.
+pub struct BreachList {
+    map: Mmap,
+    binary: bool,
+}
+
+impl BreachList {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        // the file is only read and is not expected to change while it is open
+        let map = unsafe { Mmap::map(&file)? };
+        let binary = map.starts_with(INDEX_MAGIC);
+        if binary && (map.len() - INDEX_MAGIC.len()) % RECORD_NUM != 0 {
+            bail!("breach index is truncated");
+        }
+        let list = BreachList { map, binary };
+        if !binary {
+            list.check_text_order()?;
+        }
+        Ok(list)
+    }
+
+    // the download ordered by prevalence can't be searched, it would find nothing; the first
+    // lines must go up and come before the last one, build_index checks every line
+    fn check_text_order(&self) -> Result<()> {
+        let not_blank = |line: &&[u8]| line.iter().any(|b| !b.is_ascii_whitespace());
+        let first = self.map.split(|b| *b == b'\n').filter(not_blank).take(ORDER_CHECK_LINES)
+            .map(line_hash)
+            .collect::<Result<Vec<String>>>()?;
+        let last = self.map.rsplit(|b| *b == b'\n').find(not_blank).map(line_hash).transpose()?;
+        if first.windows(2).any(|pair| pair[0] >= pair[1]) {
+            bail!(NOT_SORTED);
+        }
+        if let (Some(first), Some(last)) = (first.first(), last) {
+            if *first > last {
+                bail!(NOT_SORTED);
+            }
+        }
+        Ok(())
+    }
+
+    // how many breaches the password showed up in, 0 when never
+    pub fn count(&self, password: &[u8]) -> Result<u32> {
+        let hash: [u8; HASH_NUM] = Sha1::digest(password).into();
+        if self.binary {
+            Ok(self.binary_count(&hash))
+        } else {
+            self.text_count(&hex::encode_upper(hash))
+        }
+    }
+
+    fn binary_count(&self, hash: &[u8; HASH_NUM]) -> u32 {
+        let records = &self.map[INDEX_MAGIC.len()..];
+        let (mut low, mut high) = (0, records.len() / RECORD_NUM);
+        while low < high {
+            let mid = (low + high) / 2;
+            let record = &records[mid * RECORD_NUM..(mid + 1) * RECORD_NUM];
+            match record[..HASH_NUM].cmp(&hash[..]) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return u32::from_le_bytes(record[HASH_NUM..].try_into().unwrap()),
+            }
+        }
+        0
+    }
+
+    // binary search over byte offsets, each probe moves back to the start of its line
+    fn text_count(&self, hash: &str) -> Result<u32> {
+        let data = &self.map[..];
+        let (mut low, mut high) = (0, data.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            let start = data[low..mid].iter().rposition(|b| *b == b'\n').map(|i| low + i + 1).unwrap_or(low);
+            let end = data[start..].iter().position(|b| *b == b'\n').map(|i| start + i).unwrap_or(data.len());
+            let line = std::str::from_utf8(&data[start..end])?.trim_end();
+            let (line_hash, count) = line.split_once(':').ok_or_else(|| anyhow!("not a HIBP line: {}", line))?;
+            match line_hash.to_ascii_uppercase().as_str().cmp(hash) {
+                std::cmp::Ordering::Less => low = end + 1,
+                std::cmp::Ordering::Greater => high = start,
+                std::cmp::Ordering::Equal => return Ok(count.trim().parse()?),
+            }
+        }
+        Ok(0)
+    }
+}
+
+fn line_hash(line: &[u8]) -> Result<String> {
+    let line = std::str::from_utf8(line)?.trim();
+    let (hash, _) = line.split_once(':').ok_or_else(|| anyhow!("not a HIBP line: {}", line))?;
+    Ok(hash.to_ascii_uppercase())
+}
+
+// converts the sorted text download into the binary index, about half the size
+pub fn build_index<P: AsRef<Path>, Q: AsRef<Path>>(text_path: P, index_path: Q, mut progress: impl FnMut(u64)) -> Result<u64> {
+    let mut reader = BufReader::new(File::open(text_path)?);
+    // bytes read so far, handed to progress every PROGRESS_RECORDS lines
+    let mut read = 0;
+    let mut buf = String::new();
+    let mut writer = BufWriter::new(File::create(index_path)?);
+    writer.write_all(INDEX_MAGIC)?;
+    let mut last = [0u8; HASH_NUM];
+    let mut records = 0;
+    loop {
+        buf.clear();
+        let len = reader.read_line(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        read += len as u64;
+        let line = buf.trim();
+        if line == "" {
+            continue;
+        }
+        let (hash, count) = line.split_once(':').ok_or_else(|| anyhow!("not a HIBP line: {}", line))?;
+        let hash: [u8; HASH_NUM] = hex::decode(hash)?.try_into().map_err(|_| anyhow!("not a sha1 hash: {}", hash))?;
+        if records > 0 && hash <= last {
+            bail!(NOT_SORTED);
+        }
+        let count: u32 = count.trim().parse()?;
+        writer.write_all(&hash)?;
+        writer.write_all(&count.to_le_bytes())?;
+        last = hash;
+        records += 1;
+        if records % PROGRESS_RECORDS == 0 {
+            progress(read);
+        }
+    }
+    progress(read);
+    writer.flush()?;
+    Ok(records)
+}
+
+// (index into entries, breach count) of every item whose secret is breached
+pub fn check_entries(list: &BreachList, key: &[u8], entries: &[Entry]) -> Result<Vec<(usize, u32)>> {
+    let mut breached = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let secret = match item::open_secret(key, &entry.item) {
+            Ok(secret) => secret,
+            Err(e) if e.is::<item::TamperError>() => continue,
+            Err(e) => return Err(e),
+        };
+        if secret.is_empty() || !entry.item.kind.has_password() || entry.item.status == ItemStatus::Trashed {
+            continue;
+        }
+        let count = list.count(&secret)?;
+        if count > 0 {
+            breached.push((index, count));
+        }
+    }
+    Ok(breached)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn hibp_line(password: &str, count: u32) -> String {
+        format!("{}:{}", hex::encode_upper(Sha1::digest(password.as_bytes())), count)
+    }
+
+    #[test]
+    fn count_breaches() {
+        let dir = std::env::temp_dir().join(format!("cpandas-hibp-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut lines: Vec<String> = (0..200).map(|i| hibp_line(&format!("password{}", i), i + 1)).collect();
+        lines.push(hibp_line("hunter2", 17043));
+        lines.sort();
+        let text_path = dir.join("pwned.txt");
+        std::fs::write(&text_path, lines.join("\r\n")).unwrap();
+        let index_path = dir.join("pwned.idx");
+        let mut read = 0;
+        assert_eq!(build_index(&text_path, &index_path, |bytes| read = bytes).unwrap(), 201);
+        assert_eq!(read, std::fs::metadata(&text_path).unwrap().len());
+
+        for path in [&text_path, &index_path] {
+            let list = BreachList::open(path).unwrap();
+            assert_eq!(list.count(b"hunter2").unwrap(), 17043);
+            for i in 0..200 {
+                assert_eq!(list.count(format!("password{}", i).as_bytes()).unwrap(), i + 1);
+            }
+            assert_eq!(list.count(b"correct horse battery staple").unwrap(), 0);
+        }
+
+        let key = crate::utils::aes256_key().unwrap();
+        let entries: Vec<Entry> = ["hunter2", "tX9#qv!Lm2$wR8zK", "password7"].iter()
+            .map(|secret| item::open_entry(&key, &item::new_item(&key, "alice", secret, "").unwrap()).unwrap())
+            .collect();
+        let list = BreachList::open(&index_path).unwrap();
+        assert_eq!(check_entries(&list, &key, &entries).unwrap(), vec![(0, 17043), (2, 8)]);
+
+        lines.reverse();
+        std::fs::write(&text_path, lines.join("\n")).unwrap();
+        assert!(build_index(&text_path, &index_path, |_| {}).is_err());
+        assert!(BreachList::open(&text_path).is_err());
+        // ordered by prevalence: the top lines go down
+        lines.sort_by_key(|line| std::cmp::Reverse(line.split_once(':').unwrap().1.parse::<u32>().unwrap()));
+        std::fs::write(&text_path, lines.join("\n")).unwrap();
+        assert!(BreachList::open(&text_path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
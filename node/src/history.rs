@@ -0,0 +1,218 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use super::cipher::{DEFAULT_SUITE, Envelope};
+use super::db::Database;
+use super::item::{self, ACCOUNT_FIELD, DESC_FIELD, DETAILS_FIELD, OTP_FIELD, SECRET_FIELD};
+use super::secret::SecretBuf;
+use super::utils;
+use super::types::{FieldKind, FieldVersion, Item, Settings};
+
+const DAY_SECS: u64 = 24 * 60 * 60;
+// every field that keeps a history, in the order they are compared
+pub const HISTORY_FIELDS: [&str; 5] = [ACCOUNT_FIELD, SECRET_FIELD, DESC_FIELD, OTP_FIELD, DETAILS_FIELD];
+// custom field versions are named by the field's id and keep its kind and name too
+const CUSTOM_FIELD_PREFIX: &str = "field:";
+
+#[derive(Deserialize, Serialize)]
+struct CustomVersion {
+    kind: FieldKind,
+    name: String,
+    value: String,
+}
+
+fn version_aad(id: &str, field: &str, replaced: u64) -> Vec<u8> {
+    format!("cpandas:history:{}:{}:{}", id, field, replaced).into_bytes()
+}
+
+fn seal_version(key: &[u8], id: &str, field: &str, value: &[u8], replaced: u64) -> Result<FieldVersion> {
+    let envelope = Envelope::seal(DEFAULT_SUITE, key, value, &version_aad(id, field, replaced))?;
+    Ok(FieldVersion { field: field.to_string(), value: envelope.to_hex(), replaced })
+}
+
+// a version moved to another item, field or time doesn't open
+pub fn open_version(key: &[u8], id: &str, version: &FieldVersion) -> Result<SecretBuf> {
+    let aad = version_aad(id, &version.field, version.replaced);
+    Envelope::from_hex(&version.value)?.open(key, &aad)
+        .map_err(|_| item::TamperError { id: id.to_string(), field: format!("history {}", version.field) }.into())
+}
+
+// the old values of every field that differs between old and new
+pub fn record(key: &[u8], old: &Item, new: &Item, now: u64) -> Result<Vec<FieldVersion>> {
+    let mut versions = Vec::new();
+    for field in HISTORY_FIELDS {
+        let old_value = item::open_named_field(key, old, field)?;
+        if old_value != item::open_named_field(key, new, field)? {
+            versions.push(seal_version(key, &old.id, field, &old_value, now)?);
+        }
+    }
+    // changed or removed custom fields
+    for field in &old.fields {
+        let old_field = item::open_custom_field(key, old, &field.id)?;
+        if old_field != item::open_custom_field(key, new, &field.id)? {
+            if let Some((kind, name, value)) = old_field {
+                let mut custom = CustomVersion { kind, name, value: value.as_str()?.to_string() };
+                let data = SecretBuf::from_vec(serde_json::to_vec(&custom)?)?;
+                custom.value.zeroize();
+                versions.push(seal_version(key, &old.id, &format!("{}{}", CUSTOM_FIELD_PREFIX, field.id), &data, now)?);
+            }
+        }
+    }
+    Ok(versions)
+}
+
+/// Drops versions older than max_days, then all but the newest max_count of each
+/// field. Versions are kept oldest first.
+pub fn prune(versions: &mut Vec<FieldVersion>, max_count: usize, max_days: u64, now: u64) {
+    if max_days > 0 {
+        versions.retain(|version| now.saturating_sub(version.replaced) <= max_days * DAY_SECS);
+    }
+    let mut fields: Vec<String> = versions.iter().map(|version| version.field.clone()).collect();
+    fields.sort();
+    fields.dedup();
+    for field in fields {
+        let count = versions.iter().filter(|version| version.field == field).count();
+        let mut extra = count.saturating_sub(max_count);
+        versions.retain(|version| {
+            if version.field == field && extra > 0 {
+                extra -= 1;
+                return false;
+            }
+            true
+        });
+    }
+}
+
+// saves new over old, with the replaced values added to the item's history
+pub fn save_change(db: &Database, key: &[u8], old: &Item, new: &Item, settings: &Settings, now: u64) -> Result<()> {
+    let mut versions = db.get_history(&old.id)?;
+    versions.extend(record(key, old, new, now)?);
+    prune(&mut versions, settings.history_count, settings.history_days, now);
+    db.put_item_history(new, &versions)
+}
+
+// the item's history with expired versions already dropped from the vault
+pub fn load(db: &Database, id: &str, settings: &Settings, now: u64) -> Result<Vec<FieldVersion>> {
+    let mut versions = db.get_history(id)?;
+    let count = versions.len();
+    prune(&mut versions, settings.history_count, settings.history_days, now);
+    if versions.len() != count {
+        db.put_history(id, &versions)?;
+    }
+    Ok(versions)
+}
+
+// how the timeline names the version's field
+pub fn label(version: &FieldVersion) -> &str {
+    if version.field.starts_with(CUSTOM_FIELD_PREFIX) {
+        return "custom field";
+    }
+    &version.field
+}
+
+// the old value as the timeline shows it, name: value for a custom field
+pub fn reveal(key: &[u8], id: &str, version: &FieldVersion) -> Result<SecretBuf> {
+    let value = open_version(key, id, version)?;
+    if !version.field.starts_with(CUSTOM_FIELD_PREFIX) {
+        return Ok(value);
+    }
+    let mut custom: CustomVersion = serde_json::from_slice(&value)?;
+    // "name: value" put together in secret memory, not in a formatted String
+    let label = format!("{}: ", custom.name);
+    let text = SecretBuf::new(label.len() + custom.value.len()).map(|mut text| {
+        let (start, end) = text.as_mut_bytes().split_at_mut(label.len());
+        start.copy_from_slice(label.as_bytes());
+        end.copy_from_slice(custom.value.as_bytes());
+        text
+    });
+    custom.value.zeroize();
+    text
+}
+
+// the item with the field set back to the version's value, a removed custom field added again
+pub fn restore(key: &[u8], item: &Item, version: &FieldVersion) -> Result<Item> {
+    if let Some(field_id) = version.field.strip_prefix(CUSTOM_FIELD_PREFIX) {
+        let value = open_version(key, &item.id, version)?;
+        let mut custom: CustomVersion = serde_json::from_slice(&value)?;
+        let mut new_item = item.clone();
+        new_item.updated = utils::now();
+        let result = item::put_custom_field(key, &mut new_item, field_id, custom.kind, &custom.name, custom.value.as_bytes());
+        custom.value.zeroize();
+        result?;
+        return Ok(new_item);
+    }
+    if !HISTORY_FIELDS.contains(&version.field.as_str()) {
+        bail!("unknown item field {}", version.field);
+    }
+    let value = open_version(key, &item.id, version)?;
+    item::set_named_field(key, item, &version.field, &value)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn record_and_restore() {
+        let key = utils::aes256_key().unwrap();
+        let first = item::new_item(&key, "alice", "hunter2", "email").unwrap();
+        let second = item::update_item(&key, &first, "alice", "correct horse", "mail").unwrap();
+        let now = first.created + 10;
+        let versions = record(&key, &first, &second, now).unwrap();
+        let fields: Vec<&str> = versions.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, vec![SECRET_FIELD, DESC_FIELD]);
+        assert!(!versions[0].value.contains(&hex::encode("hunter2")));
+        assert_eq!(open_version(&key, &first.id, &versions[0]).unwrap().as_bytes(), b"hunter2");
+
+        // a version doesn't open for another item or time
+        let other = item::new_item(&key, "bob", "secret", "").unwrap();
+        assert!(open_version(&key, &other.id, &versions[0]).unwrap_err().is::<item::TamperError>());
+        let moved = FieldVersion { replaced: now + 1, ..versions[0].clone() };
+        assert!(open_version(&key, &first.id, &moved).is_err());
+
+        let restored = restore(&key, &second, &versions[0]).unwrap();
+        assert_eq!(item::open_secret(&key, &restored).unwrap().as_bytes(), b"hunter2");
+        assert_eq!(item::open_entry(&key, &restored).unwrap().desc, "mail");
+        assert!(restored.secret_changed >= second.secret_changed);
+        assert_eq!(record(&key, &second, &second, now).unwrap(), vec![]);
+
+        // a removed custom field comes back with its kind and name
+        let mut with_field = second.clone();
+        item::add_field(&key, &mut with_field, FieldKind::Concealed, "pin", "1234").unwrap();
+        let field_id = with_field.fields[0].id.clone();
+        let versions = record(&key, &with_field, &second, now).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(label(&versions[0]), "custom field");
+        assert_eq!(reveal(&key, &second.id, &versions[0]).unwrap().as_bytes(), b"pin: 1234");
+        let restored = restore(&key, &second, &versions[0]).unwrap();
+        let (kind, name, value) = item::open_custom_field(&key, &restored, &field_id).unwrap().unwrap();
+        assert_eq!((kind, name.as_str(), value.as_bytes()), (FieldKind::Concealed, "pin", &b"1234"[..]));
+    }
+
+    #[test]
+    fn prune_versions() {
+        let version = |field: &str, replaced: u64| FieldVersion { field: field.to_string(), value: "".to_string(), replaced };
+        let now = 1000 * DAY_SECS;
+        let mut versions = vec![
+            version(SECRET_FIELD, now - 400 * DAY_SECS),
+            version(SECRET_FIELD, now - 3 * DAY_SECS),
+            version(DESC_FIELD, now - 3 * DAY_SECS),
+            version(SECRET_FIELD, now - 2 * DAY_SECS),
+            version(SECRET_FIELD, now - DAY_SECS),
+        ];
+        prune(&mut versions, 10, 0, now);
+        assert_eq!(versions.len(), 5);
+        prune(&mut versions, 10, 365, now);
+        assert_eq!(versions.len(), 4);
+        prune(&mut versions, 2, 365, now);
+        assert_eq!(versions, vec![
+            version(DESC_FIELD, now - 3 * DAY_SECS),
+            version(SECRET_FIELD, now - 2 * DAY_SECS),
+            version(SECRET_FIELD, now - DAY_SECS),
+        ]);
+        prune(&mut versions, 0, 0, now);
+        assert!(versions.is_empty());
+    }
+}
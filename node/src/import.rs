@@ -0,0 +1,250 @@
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::percent_decode_str;
+
+use super::item;
+use super::otp::{Algorithm, OtpAuth, OtpKind};
+use super::secret::SecretBuf;
+use super::types::Item;
+
+const MIGRATION_SCHEME: &str = "otpauth-migration://offline?";
+
+// every otpauth:// or otpauth-migration:// uri in text, one per line
+pub fn parse_text(text: &str) -> Result<Vec<OtpAuth>> {
+    let mut list = Vec::new();
+    for line in text.lines().map(|line| line.trim()).filter(|line| *line != "") {
+        if line.starts_with("otpauth-migration:") {
+            list.extend(parse_migration(line)?);
+        } else {
+            list.push(OtpAuth::parse(line)?);
+        }
+    }
+    Ok(list)
+}
+
+// the QR codes of a png file, e.g. a screenshot of an export screen
+pub fn read_qr_png(path: &Path) -> Result<Vec<OtpAuth>> {
+    let image = image::open(path).map_err(|e| anyhow!("{}",e))?.to_luma8();
+    let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+        image.width() as usize, image.height() as usize, |x, y| image.get_pixel(x as u32, y as u32).0[0]);
+    let grids = prepared.detect_grids();
+    if grids.is_empty() {
+        bail!("no QR code in {}", path.display());
+    }
+    let mut list = Vec::new();
+    for grid in grids {
+        let (_, content) = grid.decode().map_err(|e| anyhow!("{}",e))?;
+        let content = SecretBuf::from_vec(content.into_bytes())?;
+        list.extend(parse_text(content.as_str()?)?);
+    }
+    Ok(list)
+}
+
+// otpauth-migration://offline?data=<base64 MigrationPayload protobuf>
+pub fn parse_migration(uri: &str) -> Result<Vec<OtpAuth>> {
+    let query = match uri.trim().strip_prefix(MIGRATION_SCHEME) {
+        Some(query) => query,
+        None => bail!("not an otpauth-migration://offline uri"),
+    };
+    let data = query.split('&')
+        .find_map(|pair| pair.strip_prefix("data="))
+        .ok_or_else(|| anyhow!("migration uri without data"))?;
+    let data = SecretBuf::from_vec(percent_decode_str(data).collect())?;
+    let payload = SecretBuf::from_vec(base64::decode(&*data).map_err(|e| anyhow!("{}",e))?)?;
+
+    let mut list = Vec::new();
+    let mut reader = ProtoReader { data: &payload, pos: 0 };
+    while let Some((field, value)) = reader.next_field()? {
+        // 1: repeated OtpParameters, the batch fields don't matter here
+        if let (1, ProtoValue::Bytes(bytes)) = (field, value) {
+            list.push(parse_otp_parameters(bytes)?);
+        }
+    }
+    Ok(list)
+}
+
+fn parse_otp_parameters(data: &[u8]) -> Result<OtpAuth> {
+    let mut secret = None;
+    let mut label = String::new();
+    let mut issuer = String::new();
+    let mut algorithm = Algorithm::Sha1;
+    let mut digits = 6;
+    let mut hotp = false;
+    let mut counter = 0;
+    let mut reader = ProtoReader { data, pos: 0 };
+    while let Some((field, value)) = reader.next_field()? {
+        match (field, value) {
+            (1, ProtoValue::Bytes(bytes)) => secret = Some(SecretBuf::from_slice(bytes)?),
+            (2, ProtoValue::Bytes(bytes)) => label = String::from_utf8(bytes.to_vec())?,
+            (3, ProtoValue::Bytes(bytes)) => issuer = String::from_utf8(bytes.to_vec())?,
+            (4, ProtoValue::Varint(value)) => algorithm = match value {
+                0 | 1 => Algorithm::Sha1,
+                2 => Algorithm::Sha256,
+                3 => Algorithm::Sha512,
+                _ => bail!("unsupported otp algorithm in migration data"),
+            },
+            (5, ProtoValue::Varint(value)) => digits = if value == 2 { 8 } else { 6 },
+            (6, ProtoValue::Varint(value)) => hotp = value == 1,
+            (7, ProtoValue::Varint(value)) => counter = value,
+            _ => {}
+        }
+    }
+    let kind = if hotp { OtpKind::Hotp { counter } } else { OtpKind::Totp { period: 30 } };
+    let otp = OtpAuth {
+        kind,
+        label,
+        issuer,
+        secret: secret.filter(|secret| !secret.is_empty()).ok_or_else(|| anyhow!("migration entry without a secret"))?,
+        algorithm,
+        digits,
+    };
+    otp.code(0)?;
+    Ok(otp)
+}
+
+enum ProtoValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+// just enough protobuf wire format for the migration payload
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or_else(|| anyhow!("truncated migration data"))?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("bad varint in migration data")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.data.len())
+            .ok_or_else(|| anyhow!("truncated migration data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, ProtoValue<'a>)>> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x07 {
+            0 => ProtoValue::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                ProtoValue::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                ProtoValue::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                ProtoValue::Fixed
+            }
+            _ => bail!("unsupported wire type in migration data"),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+// one authenticator item per entry, account is the label and desc the issuer
+pub fn otp_items(key: &[u8], list: &[OtpAuth]) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    for otp in list {
+        let mut it = item::new_item(key, &otp.label, "", &otp.issuer)?;
+        item::seal_otp(key, &mut it, otp.to_uri()?.as_str()?)?;
+        items.push(it);
+    }
+    Ok(items)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        put_varint(out, field << 3 | 2);
+        put_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    fn put_int(out: &mut Vec<u8>, field: u64, value: u64) {
+        put_varint(out, field << 3);
+        put_varint(out, value);
+    }
+
+    #[test]
+    fn import_migration() {
+        let mut totp = Vec::new();
+        put_bytes(&mut totp, 1, b"12345678901234567890");
+        put_bytes(&mut totp, 2, "ACME Co:alice@example.com".as_bytes());
+        put_bytes(&mut totp, 3, b"ACME Co");
+        put_int(&mut totp, 4, 1);
+        put_int(&mut totp, 5, 2);
+        put_int(&mut totp, 6, 2);
+        let mut hotp = Vec::new();
+        put_bytes(&mut hotp, 1, b"12345678901234567890");
+        put_bytes(&mut hotp, 2, b"bob");
+        put_int(&mut hotp, 6, 1);
+        put_int(&mut hotp, 7, 1);
+        let mut payload = Vec::new();
+        put_bytes(&mut payload, 1, &totp);
+        put_bytes(&mut payload, 1, &hotp);
+        put_int(&mut payload, 2, 1);
+        put_int(&mut payload, 3, 1);
+        let data = percent_encoding::utf8_percent_encode(&base64::encode(&payload), percent_encoding::NON_ALPHANUMERIC).to_string();
+        let uri = format!("otpauth-migration://offline?data={}", data);
+
+        let list = parse_migration(&uri).unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].label, "ACME Co:alice@example.com");
+        assert_eq!(list[0].issuer, "ACME Co");
+        assert_eq!(list[0].kind, OtpKind::Totp { period: 30 });
+        assert_eq!(list[0].code(59).unwrap(), "94287082");
+        assert_eq!(list[1].kind, OtpKind::Hotp { counter: 1 });
+        assert_eq!(list[1].code(0).unwrap(), "287082");
+
+        let text = format!("{}\n\n  otpauth://totp/carol?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ \n", uri);
+        let list = parse_text(&text).unwrap();
+        assert_eq!(list.len(), 3);
+        assert_eq!(list[2].label, "carol");
+
+        assert!(parse_text("otpauth://totp/carol").is_err());
+        assert!(parse_migration("otpauth-migration://offline?data=").unwrap().is_empty());
+        assert!(parse_migration(&format!("otpauth-migration://offline?data={}", base64::encode(&payload[..10]))).is_err());
+    }
+
+    #[test]
+    fn import_items() {
+        let key = crate::utils::aes256_key().unwrap();
+        let list = parse_text("otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1&issuer=ACME").unwrap();
+        let mut items = otp_items(&key, &list).unwrap();
+        let entry = item::open_entry(&key, &items[0]).unwrap();
+        assert_eq!((entry.account.as_str(), entry.desc.as_str()), ("bob", "ACME"));
+        assert_eq!(item::take_otp_code(&key, &mut items[0], 0).unwrap().unwrap(), "287082");
+    }
+}
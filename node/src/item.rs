@@ -0,0 +1,760 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use uuid::Uuid;
+
+use super::cipher::{DEFAULT_SUITE, Envelope};
+use super::db::Database;
+use super::otp::{OtpAuth, OtpKind};
+use super::secret::{SecretBuf, SecretString};
+use super::types::{Attachment, CustomField, Entry, FieldKind, InputField, Item, ItemStatus};
+use super::utils;
+
+// 0: plaintext account and desc, secret and nonce hex apart
+// 1: account, secret and desc each sealed with the vault key
+// 2: sealed with the item id, field name and version as associated data
+// 3: each field a hex cipher Envelope, nonce is no longer used
+pub const ITEM_VERSION: u32 = 3;
+
+pub const ACCOUNT_FIELD: &str = "account";
+pub const SECRET_FIELD: &str = "secret";
+pub const DESC_FIELD: &str = "desc";
+pub const OTP_FIELD: &str = "otp";
+pub const DETAILS_FIELD: &str = "details";
+pub const TAGS_FIELD: &str = "tags";
+pub const FOLDER_FIELD: &str = "folder";
+
+/// A field that doesn't authenticate for its item: the ciphertext was changed or
+/// moved from another item or field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TamperError {
+    pub id: String,
+    pub field: String,
+}
+
+impl Display for TamperError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "item {} field {} failed authentication, it may have been tampered with", self.id, self.field)
+    }
+}
+
+impl std::error::Error for TamperError {}
+
+pub fn new_item(key: &[u8], account: &str, secret: &str, desc: &str) -> Result<Item> {
+    let now = utils::now();
+    let mut item = Item {
+        id: Uuid::new_v4().to_string(),
+        version: ITEM_VERSION,
+        status: ItemStatus::Active,
+        secret_changed: now,
+        created: now,
+        updated: now,
+        ..Default::default()
+    };
+    item.account = seal_field(&item, ACCOUNT_FIELD, account.as_bytes(), key)?;
+    item.secret = seal_field(&item, SECRET_FIELD, secret.as_bytes(), key)?;
+    item.desc = seal_field(&item, DESC_FIELD, desc.as_bytes(), key)?;
+    Ok(item)
+}
+
+fn field_aad(item: &Item, field: &str) -> Vec<u8> {
+    format!("cpandas:item:v{}:{}:{}", item.version, item.id, field).into_bytes()
+}
+
+fn seal_field(item: &Item, field: &str, value: &[u8], key: &[u8]) -> Result<String> {
+    let envelope = Envelope::seal(DEFAULT_SUITE, key, value, &field_aad(item, field))?;
+    Ok(envelope.to_hex())
+}
+
+fn open_field(item: &Item, field: &str, value: &str, key: &[u8]) -> Result<SecretBuf> {
+    let result = match item.version {
+        0 => bail!("item {} field {} is not sealed", item.id, field),
+        1 => utils::aes256_open(&hex::decode(value)?, key),
+        2 => utils::aes256_open_aad(&hex::decode(value)?, key, &field_aad(item, field)),
+        _ => Envelope::from_hex(value)?.open(key, &field_aad(item, field)),
+    };
+    result.map_err(|_| TamperError { id: item.id.clone(), field: field.to_string() }.into())
+}
+
+pub fn open_secret(key: &[u8], item: &Item) -> Result<SecretBuf> {
+    if item.version == 0 {
+        return utils::aes256_decode(&hex::decode(&item.secret)?, key, &hex::decode(&item.nonce)?)
+            .map_err(|_| TamperError { id: item.id.clone(), field: SECRET_FIELD.to_string() }.into());
+    }
+    open_field(item, SECRET_FIELD, &item.secret, key)
+}
+
+pub fn open_entry(key: &[u8], item: &Item) -> Result<Entry> {
+    if item.version == 0 {
+        return Ok(Entry { item: item.clone(), account: item.account.clone(), desc: item.desc.clone(), ..Default::default() });
+    }
+    let mut entry = Entry {
+        item: item.clone(),
+        account: open_field(item, ACCOUNT_FIELD, &item.account, key)?.as_str()?.to_string(),
+        desc: open_field(item, DESC_FIELD, &item.desc, key)?.as_str()?.to_string(),
+        ..Default::default()
+    };
+    if item.tags != "" {
+        entry.tags = serde_json::from_slice(&open_field(item, TAGS_FIELD, &item.tags, key)?)?;
+    }
+    if item.folder != "" {
+        entry.folder = open_field(item, FOLDER_FIELD, &item.folder, key)?.as_str()?.to_string();
+    }
+    Ok(entry)
+}
+
+// tags and folder as organize::parse_tags and parse_folder give them, empty ones are not sealed
+pub fn seal_organize(key: &[u8], item: &mut Item, tags: &[String], folder: &str) -> Result<()> {
+    item.tags = if tags.is_empty() { "".to_string() } else { seal_field(item, TAGS_FIELD, &serde_json::to_vec(tags)?, key)? };
+    item.folder = if folder == "" { "".to_string() } else { seal_field(item, FOLDER_FIELD, folder.as_bytes(), key)? };
+    Ok(())
+}
+
+// the item with new account, secret and desc, id and otp stay
+pub fn update_item(key: &[u8], item: &Item, account: &str, secret: &str, desc: &str) -> Result<Item> {
+    let mut new_item = reencrypt(item, key, key)?;
+    new_item.updated = utils::now();
+    if open_secret(key, item)?.as_bytes() != secret.as_bytes() {
+        new_item.secret_changed = new_item.updated;
+    }
+    new_item.account = seal_field(&new_item, ACCOUNT_FIELD, account.as_bytes(), key)?;
+    new_item.secret = seal_field(&new_item, SECRET_FIELD, secret.as_bytes(), key)?;
+    new_item.desc = seal_field(&new_item, DESC_FIELD, desc.as_bytes(), key)?;
+    Ok(new_item)
+}
+
+// the plaintext of one field by name, an item without otp has an empty one
+pub fn open_named_field(key: &[u8], item: &Item, field: &str) -> Result<SecretBuf> {
+    match field {
+        SECRET_FIELD => open_secret(key, item),
+        OTP_FIELD => match open_otp(key, item)? {
+            Some(otp) => otp.to_uri(),
+            None => SecretBuf::new(0),
+        },
+        DETAILS_FIELD if item.details == "" => SecretBuf::new(0),
+        DETAILS_FIELD => open_field(item, DETAILS_FIELD, &item.details, key),
+        ACCOUNT_FIELD => SecretBuf::from_vec(open_entry(key, item)?.account.into_bytes()),
+        DESC_FIELD => SecretBuf::from_vec(open_entry(key, item)?.desc.into_bytes()),
+        _ => bail!("unknown item field {}", field),
+    }
+}
+
+// the item with one field set to value, the others stay
+pub fn set_named_field(key: &[u8], item: &Item, field: &str, value: &[u8]) -> Result<Item> {
+    let mut new_item = reencrypt(item, key, key)?;
+    new_item.updated = utils::now();
+    match field {
+        SECRET_FIELD => {
+            if open_secret(key, item)?.as_bytes() != value {
+                new_item.secret_changed = new_item.updated;
+            }
+            new_item.secret = seal_field(&new_item, SECRET_FIELD, value, key)?;
+        }
+        OTP_FIELD => seal_otp(key, &mut new_item, std::str::from_utf8(value)?)?,
+        DETAILS_FIELD if value.is_empty() => new_item.details = "".to_string(),
+        DETAILS_FIELD => new_item.details = seal_field(&new_item, DETAILS_FIELD, value, key)?,
+        ACCOUNT_FIELD => new_item.account = seal_field(&new_item, ACCOUNT_FIELD, value, key)?,
+        DESC_FIELD => new_item.desc = seal_field(&new_item, DESC_FIELD, value, key)?,
+        _ => bail!("unknown item field {}", field),
+    }
+    Ok(new_item)
+}
+
+// makes item an authenticator for the otpauth:// uri, an empty uri removes it
+pub fn seal_otp(key: &[u8], item: &mut Item, uri: &str) -> Result<()> {
+    if uri.trim() == "" {
+        item.otp = "".to_string();
+        return Ok(());
+    }
+    let otp = OtpAuth::parse(uri)?;
+    item.otp = seal_field(item, OTP_FIELD, &otp.to_uri()?, key)?;
+    Ok(())
+}
+
+pub fn open_otp(key: &[u8], item: &Item) -> Result<Option<OtpAuth>> {
+    if item.otp == "" {
+        return Ok(None);
+    }
+    let uri = open_field(item, OTP_FIELD, &item.otp, key)?;
+    Ok(Some(OtpAuth::parse(uri.as_str()?)?))
+}
+
+// the code to use at time; a hotp counter moves on with every code taken, so the
+// item has to be saved afterwards
+pub fn take_otp_code(key: &[u8], item: &mut Item, time: u64) -> Result<Option<String>> {
+    let mut otp = match open_otp(key, item)? {
+        Some(otp) => otp,
+        None => return Ok(None),
+    };
+    let code = otp.code(time)?;
+    if let OtpKind::Hotp { .. } = otp.kind {
+        otp.next_counter();
+        item.otp = seal_field(item, OTP_FIELD, &otp.to_uri()?, key)?;
+    }
+    Ok(Some(code))
+}
+
+// the item sealed again with new_key at the current version, any version can come in
+pub fn reencrypt(item: &Item, old_key: &[u8], new_key: &[u8]) -> Result<Item> {
+    let entry = open_entry(old_key, item)?;
+    let secret = open_secret(old_key, item)?;
+    let mut new_item = item.clone();
+    new_item.version = ITEM_VERSION;
+    new_item.nonce = "".to_string();
+    new_item.account = seal_field(&new_item, ACCOUNT_FIELD, entry.account.as_bytes(), new_key)?;
+    new_item.secret = seal_field(&new_item, SECRET_FIELD, &secret, new_key)?;
+    new_item.desc = seal_field(&new_item, DESC_FIELD, entry.desc.as_bytes(), new_key)?;
+    if item.otp != "" {
+        let uri = open_field(item, OTP_FIELD, &item.otp, old_key)?;
+        new_item.otp = seal_field(&new_item, OTP_FIELD, &uri, new_key)?;
+    }
+    new_item.details = reseal(item, &new_item, DETAILS_FIELD, &item.details, old_key, new_key)?;
+    new_item.tags = reseal(item, &new_item, TAGS_FIELD, &item.tags, old_key, new_key)?;
+    new_item.folder = reseal(item, &new_item, FOLDER_FIELD, &item.folder, old_key, new_key)?;
+    let mut fields = Vec::new();
+    for field in &item.fields {
+        let (name, value) = open_custom(old_key, item, field)?;
+        fields.push(seal_custom(new_key, &new_item, &field.id, field.kind, name.as_bytes(), &value)?);
+    }
+    new_item.fields = fields;
+    let mut attachments = Vec::new();
+    for attachment in &item.attachments {
+        let (name, data_map) = open_attachment(old_key, item, attachment)?;
+        attachments.push(seal_attachment(new_key, &new_item, &attachment.id, &name, &data_map, attachment.size, attachment.added)?);
+    }
+    new_item.attachments = attachments;
+    Ok(new_item)
+}
+
+pub fn seal_attachment(key: &[u8], item: &Item, id: &str, name: &str, data_map: &[u8], size: u64, added: u64) -> Result<Attachment> {
+    Ok(Attachment {
+        id: id.to_string(),
+        name: seal_field(item, &format!("attachment:{}:name", id), name.as_bytes(), key)?,
+        data_map: seal_field(item, &format!("attachment:{}:map", id), data_map, key)?,
+        size,
+        added,
+    })
+}
+
+// (file name, data map json) of an attachment
+pub fn open_attachment(key: &[u8], item: &Item, attachment: &Attachment) -> Result<(String, SecretBuf)> {
+    let name = open_field(item, &format!("attachment:{}:name", attachment.id), &attachment.name, key)?;
+    let data_map = open_field(item, &format!("attachment:{}:map", attachment.id), &attachment.data_map, key)?;
+    Ok((name.as_str()?.to_string(), data_map))
+}
+
+// an optional field of item sealed again for new_item, empty stays empty
+fn reseal(item: &Item, new_item: &Item, field: &str, value: &str, old_key: &[u8], new_key: &[u8]) -> Result<String> {
+    if value == "" {
+        return Ok("".to_string());
+    }
+    seal_field(new_item, field, &open_field(item, field, value, old_key)?, new_key)
+}
+
+fn custom_name(field_id: &str) -> String {
+    format!("field:{}:name", field_id)
+}
+
+fn custom_value(field_id: &str) -> String {
+    format!("field:{}:value", field_id)
+}
+
+fn seal_custom(key: &[u8], item: &Item, field_id: &str, kind: FieldKind, name: &[u8], value: &[u8]) -> Result<CustomField> {
+    Ok(CustomField {
+        id: field_id.to_string(),
+        kind,
+        name: seal_field(item, &custom_name(field_id), name, key)?,
+        value: seal_field(item, &custom_value(field_id), value, key)?,
+    })
+}
+
+// (name, value) of a custom field
+fn open_custom(key: &[u8], item: &Item, field: &CustomField) -> Result<(String, SecretBuf)> {
+    let name = open_field(item, &custom_name(&field.id), &field.name, key)?;
+    let value = open_field(item, &custom_value(&field.id), &field.value, key)?;
+    Ok((name.as_str()?.to_string(), value))
+}
+
+/// Checks a custom field for its kind and returns the value to store: trimmed, and
+/// a bare TOTP secret made into an otpauth:// uri named after the field.
+pub fn check_field(kind: FieldKind, name: &str, value: &str) -> Result<SecretBuf> {
+    let value = value.trim();
+    if name.trim() == "" {
+        bail!("field name can`t empty");
+    }
+    if value == "" {
+        return SecretBuf::new(0);
+    }
+    match kind {
+        FieldKind::Text | FieldKind::Concealed => {}
+        FieldKind::Url => {
+            let (scheme, rest) = value.split_once("://").ok_or_else(|| anyhow!("{} is not a url", name))?;
+            if scheme == "" || !scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) || rest == "" {
+                bail!("{} is not a url", name);
+            }
+        }
+        FieldKind::Email => {
+            let (user, domain) = value.split_once('@').ok_or_else(|| anyhow!("{} is not an email address", name))?;
+            if user == "" || !domain.contains('.') || domain.starts_with('.') || domain.ends_with('.') || value.contains(char::is_whitespace) {
+                bail!("{} is not an email address", name);
+            }
+        }
+        FieldKind::Phone => {
+            let digits = value.chars().filter(|c| c.is_ascii_digit()).count();
+            let number = value.strip_prefix('+').unwrap_or(value);
+            if digits < 3 || !number.chars().all(|c| c.is_ascii_digit() || " -().".contains(c)) {
+                bail!("{} is not a phone number", name);
+            }
+        }
+        FieldKind::Date => {
+            utils::parse_date(value)?;
+        }
+        FieldKind::Totp => {
+            let uri = if value.to_ascii_lowercase().starts_with("otpauth://") {
+                value.to_string()
+            } else {
+                format!("otpauth://totp/{}?secret={}", utf8_percent_encode(name.trim(), NON_ALPHANUMERIC), value)
+            };
+            return OtpAuth::parse(&uri)?.to_uri();
+        }
+    }
+    SecretBuf::from_slice(value.as_bytes())
+}
+
+// replaces the item's custom fields with the checked and sealed form fields
+pub fn seal_fields(key: &[u8], item: &mut Item, fields: &[InputField]) -> Result<()> {
+    let mut sealed = Vec::new();
+    for field in fields {
+        let value = check_field(field.kind, &field.name, field.value.as_str())?;
+        let id = if field.id == "" { Uuid::new_v4().to_string() } else { field.id.clone() };
+        sealed.push(seal_custom(key, item, &id, field.kind, field.name.trim().as_bytes(), &value)?);
+    }
+    item.fields = sealed;
+    Ok(())
+}
+
+// the kind's slots, checked with kind::check first; none leaves details empty
+pub fn seal_details(key: &[u8], item: &mut Item, details: &BTreeMap<String, String>) -> Result<()> {
+    if details.is_empty() {
+        item.details = "".to_string();
+        return Ok(());
+    }
+    let json = SecretBuf::from_vec(serde_json::to_vec(details)?)?;
+    item.details = seal_field(item, DETAILS_FIELD, &json, key)?;
+    Ok(())
+}
+
+pub fn open_details(key: &[u8], item: &Item) -> Result<BTreeMap<String, String>> {
+    if item.details == "" {
+        return Ok(BTreeMap::new());
+    }
+    let json = open_field(item, DETAILS_FIELD, &item.details, key)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+// appends one custom field, the ones already there stay sealed as they are
+pub fn add_field(key: &[u8], item: &mut Item, kind: FieldKind, name: &str, value: &str) -> Result<()> {
+    let value = check_field(kind, name, value)?;
+    let field = seal_custom(key, item, &Uuid::new_v4().to_string(), kind, name.trim().as_bytes(), &value)?;
+    item.fields.push(field);
+    Ok(())
+}
+
+// kind, name and value of the custom field with this id, None when the item has none
+pub fn open_custom_field(key: &[u8], item: &Item, id: &str) -> Result<Option<(FieldKind, String, SecretBuf)>> {
+    match item.fields.iter().find(|field| field.id == id) {
+        Some(field) => {
+            let (name, value) = open_custom(key, item, field)?;
+            Ok(Some((field.kind, name, value)))
+        }
+        None => Ok(None),
+    }
+}
+
+// sets the custom field with this id, added at the end when the item doesn't have it
+pub fn put_custom_field(key: &[u8], item: &mut Item, id: &str, kind: FieldKind, name: &str, value: &[u8]) -> Result<()> {
+    let field = seal_custom(key, item, id, kind, name.as_bytes(), value)?;
+    match item.fields.iter().position(|field| field.id == id) {
+        Some(i) => item.fields[i] = field,
+        None => item.fields.push(field),
+    }
+    Ok(())
+}
+
+pub fn open_fields(key: &[u8], item: &Item) -> Result<Vec<InputField>> {
+    let mut fields = Vec::new();
+    for field in &item.fields {
+        let (name, value) = open_custom(key, item, field)?;
+        fields.push(InputField { id: field.id.clone(), kind: field.kind, name, value: SecretString::from(value.as_str()?) });
+    }
+    Ok(fields)
+}
+
+// items stored before timestamps were kept take the best time known
+fn backfill_times(item: &mut Item, now: u64) {
+    let known = if item.secret_changed > 0 { item.secret_changed } else { now };
+    item.created = known;
+    if item.updated == 0 {
+        item.updated = known;
+    }
+    if item.secret_changed == 0 {
+        item.secret_changed = known;
+    }
+}
+
+/// Why an item needs attention: past its expiry, or its secret is older than the
+/// rotation interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Due {
+    Expired,
+    Rotate,
+}
+
+// rotation_days 0 turns rotation reminders off
+pub fn due(item: &Item, now: u64, rotation_days: u64) -> Option<Due> {
+    if item.expires.map(|expires| expires <= now).unwrap_or(false) {
+        return Some(Due::Expired);
+    }
+    if rotation_due(item, now, rotation_days).is_some() {
+        return Some(Due::Rotate);
+    }
+    None
+}
+
+// days since the secret was set when that is more than rotation_days, 0 turns it off
+pub fn rotation_due(item: &Item, now: u64, rotation_days: u64) -> Option<u64> {
+    let age = now.saturating_sub(item.secret_changed);
+    if rotation_days > 0 && item.secret_changed > 0 && age > rotation_days * 24 * 60 * 60 {
+        return Some(age / (24 * 60 * 60));
+    }
+    None
+}
+
+// archives, trashes or restores the item, remembering when it went to the trash
+pub fn set_status(item: &mut Item, status: ItemStatus, now: u64) {
+    if status == ItemStatus::Trashed && item.status != ItemStatus::Trashed {
+        item.trashed_from = item.status;
+    }
+    item.status = status;
+    item.trashed = if status == ItemStatus::Trashed { now } else { 0 };
+    item.updated = now;
+}
+
+// takes the item out of the trash, back to where it was
+pub fn restore(item: &mut Item, now: u64) {
+    let status = item.trashed_from;
+    set_status(item, status, now);
+    item.trashed_from = ItemStatus::Active;
+}
+
+// in the trash longer than days, never when days is 0
+pub fn purge_due(item: &Item, days: u64, now: u64) -> bool {
+    item.status == ItemStatus::Trashed && days > 0 && now.saturating_sub(item.trashed) > days * 24 * 60 * 60
+}
+
+// seal the plaintext fields of older items and backfill their times, all in one batch;
+// a tampered item stays at its version and is listed as such
+pub fn upgrade_items(db: &Database, key: &[u8]) -> Result<()> {
+    let items = db.get_item_list()?.unwrap_or_default();
+    let now = utils::now();
+    let mut upgraded = Vec::new();
+    for item in items.iter().filter(|item| item.version < ITEM_VERSION || item.created == 0) {
+        let mut item = if item.version < ITEM_VERSION {
+            match reencrypt(item, key, key) {
+                Ok(item) => item,
+                Err(e) if e.is::<TamperError>() => {
+                    log::error!("skip upgrade: {}", e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+        } else {
+            item.clone()
+        };
+        if item.created == 0 {
+            backfill_times(&mut item, now);
+        }
+        upgraded.push(item);
+    }
+    if !upgraded.is_empty() {
+        log::info!("upgrade {} items to version {}", upgraded.len(), ITEM_VERSION);
+        db.put_items(&upgraded)?;
+    }
+    Ok(())
+}
+
+// a tampered item is still listed, with the error as its desc
+pub fn open_entries(db: &Database, key: &[u8]) -> Result<Vec<Entry>> {
+    let items = db.get_item_list()?.unwrap_or_default();
+    let mut entries = Vec::new();
+    for item in &items {
+        match open_entry(key, item) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if e.is::<TamperError>() => {
+                log::error!("{}", e);
+                entries.push(Entry { item: item.clone(), account: "<tampered>".to_string(), desc: e.to_string(), ..Default::default() });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(entries)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kind::ItemKind;
+
+    #[test]
+    fn seal_and_open() {
+        let key = utils::aes256_key().unwrap();
+        let item = new_item(&key, "alice@example.com", "hunter2", "email").unwrap();
+        assert_eq!(item.version, ITEM_VERSION);
+        assert!(!item.account.contains("alice"));
+        assert!(!item.desc.contains("email"));
+
+        let entry = open_entry(&key, &item).unwrap();
+        assert_eq!(entry.account, "alice@example.com");
+        assert_eq!(entry.desc, "email");
+        assert_eq!(open_secret(&key, &item).unwrap().as_bytes(), b"hunter2");
+
+        let other_key = utils::aes256_key().unwrap();
+        assert!(open_entry(&other_key, &item).is_err());
+        assert!(open_secret(&other_key, &item).is_err());
+    }
+
+    #[test]
+    fn times_and_due() {
+        let key = utils::aes256_key().unwrap();
+        let mut item = new_item(&key, "alice", "hunter2", "email").unwrap();
+        let now = item.created;
+        assert!(now > 0);
+        assert_eq!((item.updated, item.secret_changed, item.last_used, item.expires), (now, now, 0, None));
+        assert_eq!(due(&item, now, 90), None);
+        assert_eq!(due(&item, now + 91 * 24 * 60 * 60, 90), Some(Due::Rotate));
+        assert_eq!(due(&item, now + 91 * 24 * 60 * 60, 0), None);
+        item.expires = Some(now + 10);
+        assert_eq!(due(&item, now + 10, 0), Some(Due::Expired));
+
+        let mut legacy = Item { created: 0, updated: 0, ..item.clone() };
+        backfill_times(&mut legacy, now + 5);
+        assert_eq!((legacy.created, legacy.updated), (now, now));
+        let mut unknown = Item { created: 0, updated: 0, secret_changed: 0, ..item.clone() };
+        backfill_times(&mut unknown, now + 5);
+        assert_eq!((unknown.created, unknown.updated, unknown.secret_changed), (now + 5, now + 5, now + 5));
+
+        // records written before the times and kinds load with zeroes and as logins
+        let json = r#"{"id":"1","version":3,"account":"","secret":"","desc":"","status":0}"#;
+        let old: Item = serde_json::from_str(json).unwrap();
+        assert_eq!((old.created, old.expires, old.kind), (0, None, ItemKind::Login));
+    }
+
+    #[test]
+    fn item_status() {
+        let key = utils::aes256_key().unwrap();
+        let mut item = new_item(&key, "alice", "hunter2", "email").unwrap();
+        let now = item.created;
+        set_status(&mut item, ItemStatus::Trashed, now);
+        assert_eq!(item.trashed, now);
+        assert!(!purge_due(&item, 30, now + 30 * 24 * 60 * 60));
+        assert!(purge_due(&item, 30, now + 31 * 24 * 60 * 60));
+        assert!(!purge_due(&item, 0, now + 3650 * 24 * 60 * 60));
+        // stored as the number older vaults wrote
+        let json = serde_json::to_string(&item).unwrap();
+        assert!(json.contains(r#""status":2"#));
+        assert_eq!(serde_json::from_str::<Item>(&json).unwrap().status, ItemStatus::Trashed);
+
+        set_status(&mut item, ItemStatus::Archived, now + 1);
+        assert_eq!((item.trashed, item.updated), (0, now + 1));
+        assert!(!purge_due(&item, 30, now + 31 * 24 * 60 * 60));
+        // trashed from the archive goes back there
+        set_status(&mut item, ItemStatus::Trashed, now + 2);
+        restore(&mut item, now + 3);
+        assert_eq!((item.status, item.trashed), (ItemStatus::Archived, 0));
+        let json = r#"{"id":"1","version":3,"account":"","secret":"","desc":"","status":7}"#;
+        assert_eq!(serde_json::from_str::<Item>(json).unwrap().status, ItemStatus::Active);
+    }
+
+    #[test]
+    fn update_fields() {
+        let key = utils::aes256_key().unwrap();
+        let mut item = new_item(&key, "alice", "hunter2", "email").unwrap();
+        seal_otp(&key, &mut item, "otpauth://totp/alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        let updated = update_item(&key, &item, "alice@example.com", "correct horse", "mail").unwrap();
+        assert_eq!(updated.id, item.id);
+        assert!(updated.secret_changed >= item.secret_changed);
+        let mut unknown = item.clone();
+        unknown.secret_changed = 0;
+        assert_eq!(update_item(&key, &unknown, "bob", "hunter2", "").unwrap().secret_changed, 0);
+        assert_ne!(update_item(&key, &unknown, "bob", "hunter3", "").unwrap().secret_changed, 0);
+        let entry = open_entry(&key, &updated).unwrap();
+        assert_eq!((entry.account.as_str(), entry.desc.as_str()), ("alice@example.com", "mail"));
+        assert_eq!(open_secret(&key, &updated).unwrap().as_bytes(), b"correct horse");
+        assert_eq!(open_otp(&key, &updated).unwrap(), open_otp(&key, &item).unwrap());
+    }
+
+    #[test]
+    fn custom_fields() {
+        let key = utils::aes256_key().unwrap();
+        let mut item = new_item(&key, "alice", "hunter2", "bank").unwrap();
+        let field = |kind, name: &str, value: &str| InputField { kind, name: name.to_string(), value: SecretString::from(value), ..Default::default() };
+        let fields = vec![
+            field(FieldKind::Url, "site", " https://bank.example.com "),
+            field(FieldKind::Concealed, "pin", "0000"),
+            field(FieldKind::Totp, "2fa", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"),
+        ];
+        seal_fields(&key, &mut item, &fields).unwrap();
+        assert!(!item.fields[1].value.contains(&hex::encode("0000")));
+        let opened = open_fields(&key, &item).unwrap();
+        assert_eq!(opened[0].value.as_str(), "https://bank.example.com");
+        assert_eq!(opened[1].name, "pin");
+        let otp = OtpAuth::parse(opened[2].value.as_str()).unwrap();
+        assert_eq!((otp.label.as_str(), otp.code(59).unwrap().as_str()), ("2fa", "287082"));
+
+        // moved fields still open, fields moved to another item don't
+        item.fields.swap(0, 1);
+        add_field(&key, &mut item, FieldKind::Email, "recovery", "alice@example.com").unwrap();
+        let names: Vec<String> = open_fields(&key, &item).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["pin", "site", "2fa", "recovery"]);
+        let mut other = new_item(&key, "bob", "secret", "").unwrap();
+        other.fields = item.fields.clone();
+        assert!(open_fields(&key, &other).unwrap_err().is::<TamperError>());
+        let new_key = utils::aes256_key().unwrap();
+        assert_eq!(open_fields(&new_key, &reencrypt(&item, &key, &new_key).unwrap()).unwrap().len(), 4);
+
+        assert!(check_field(FieldKind::Text, " ", "value").is_err());
+        assert!(check_field(FieldKind::Url, "site", "bank.example.com").is_err());
+        assert!(check_field(FieldKind::Email, "mail", "alice@example").is_err());
+        assert!(check_field(FieldKind::Phone, "phone", "+1 (555) 010-9999").is_ok());
+        assert!(check_field(FieldKind::Phone, "phone", "call me").is_err());
+        assert!(check_field(FieldKind::Date, "born", "1987-02-30").is_err());
+        assert!(check_field(FieldKind::Totp, "2fa", "not base32!").is_err());
+        assert!(check_field(FieldKind::Date, "born", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn kind_details() {
+        let key = utils::aes256_key().unwrap();
+        let mut item = new_item(&key, "visa", "4111 1111 1111 1111", "").unwrap();
+        item.kind = ItemKind::Card;
+        assert_eq!(open_details(&key, &item).unwrap(), BTreeMap::new());
+        let details = BTreeMap::from([("cvv".to_string(), "123".to_string()), ("expiry".to_string(), "08/31".to_string())]);
+        seal_details(&key, &mut item, &details).unwrap();
+        assert!(!item.details.contains(&hex::encode("08/31")));
+        assert_eq!(open_details(&key, &item).unwrap(), details);
+        let json = serde_json::to_string(&item).unwrap();
+        assert_eq!(serde_json::from_str::<Item>(&json).unwrap().kind, ItemKind::Card);
+
+        let new_key = utils::aes256_key().unwrap();
+        assert_eq!(open_details(&new_key, &reencrypt(&item, &key, &new_key).unwrap()).unwrap(), details);
+        let mut other = new_item(&key, "bob", "secret", "").unwrap();
+        other.details = item.details.clone();
+        assert!(open_details(&key, &other).unwrap_err().is::<TamperError>());
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let key = utils::aes256_key().unwrap();
+        let first = new_item(&key, "alice", "first secret", "email").unwrap();
+        let second = new_item(&key, "bob", "second secret", "bank").unwrap();
+
+        // secrets swapped between items
+        let mut swapped = second.clone();
+        swapped.secret = first.secret.clone();
+        let e = open_secret(&key, &swapped).unwrap_err();
+        assert_eq!(e.downcast_ref::<TamperError>(),
+                   Some(&TamperError { id: second.id.clone(), field: SECRET_FIELD.to_string() }));
+
+        // fields swapped inside one item
+        let mut moved = first.clone();
+        moved.desc = first.account.clone();
+        assert!(open_entry(&key, &moved).unwrap_err().is::<TamperError>());
+
+        // a downgraded version doesn't open either
+        let mut downgraded = first.clone();
+        downgraded.version = 1;
+        assert!(open_secret(&key, &downgraded).unwrap_err().is::<TamperError>());
+
+        assert_eq!(open_secret(&key, &first).unwrap().as_bytes(), b"first secret");
+    }
+
+    #[test]
+    fn upgrade_plain_item() {
+        let key = utils::aes256_key().unwrap();
+        let (ciphertext, nonce) = utils::aes256_encode(b"hunter2", &key).unwrap();
+        let item = Item {
+            id: Uuid::new_v4().to_string(),
+            account: "alice".to_string(),
+            secret: hex::encode(ciphertext),
+            desc: "email".to_string(),
+            nonce: hex::encode(nonce),
+            ..Default::default()
+        };
+        // records written before Item.version load as version 0
+        let json = r#"{"id":"1","account":"alice","secret":"00","desc":"email","status":0,"nonce":"00"}"#;
+        assert_eq!(serde_json::from_str::<Item>(json).unwrap().version, 0);
+
+        assert_eq!(open_entry(&key, &item).unwrap().account, "alice");
+        let v1 = Item {
+            version: 1,
+            account: hex::encode(utils::aes256_seal(b"alice", &key).unwrap()),
+            secret: hex::encode(utils::aes256_seal(b"hunter2", &key).unwrap()),
+            desc: hex::encode(utils::aes256_seal(b"email", &key).unwrap()),
+            nonce: "".to_string(),
+            ..item.clone()
+        };
+        let upgraded = reencrypt(&v1, &key, &key).unwrap();
+        assert_eq!(upgraded.version, ITEM_VERSION);
+        assert_eq!(open_secret(&key, &upgraded).unwrap().as_bytes(), b"hunter2");
+        let envelope = Envelope::from_hex(&upgraded.secret).unwrap();
+        assert_eq!(envelope.suite, DEFAULT_SUITE);
+
+        let upgraded = reencrypt(&item, &key, &key).unwrap();
+        assert_eq!(upgraded.version, ITEM_VERSION);
+        assert_eq!(upgraded.id, item.id);
+        assert_eq!(upgraded.nonce, "");
+        assert!(!upgraded.account.contains("alice"));
+        let entry = open_entry(&key, &upgraded).unwrap();
+        assert_eq!(entry.account, "alice");
+        assert_eq!(entry.desc, "email");
+        assert_eq!(open_secret(&key, &upgraded).unwrap().as_bytes(), b"hunter2");
+    }
+
+    #[test]
+    fn seal_and_open_otp() {
+        let key = utils::aes256_key().unwrap();
+        let uri = "otpauth://totp/ACME:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=ACME";
+        let mut item = new_item(&key, "alice", "hunter2", "2fa").unwrap();
+        assert_eq!(open_otp(&key, &item).unwrap(), None);
+        assert!(seal_otp(&key, &mut item, "otpauth://totp/bad").is_err());
+
+        seal_otp(&key, &mut item, uri).unwrap();
+        assert!(!item.otp.contains("GEZDGNBV"));
+        let otp = open_otp(&key, &item).unwrap().unwrap();
+        assert_eq!(otp, OtpAuth::parse(uri).unwrap());
+        assert_eq!(otp.code(59).unwrap(), "287082");
+
+        let new_key = utils::aes256_key().unwrap();
+        let moved = reencrypt(&item, &key, &new_key).unwrap();
+        assert_eq!(open_otp(&new_key, &moved).unwrap(), Some(otp));
+
+        let mut swapped = new_item(&key, "bob", "secret", "").unwrap();
+        swapped.otp = item.otp.clone();
+        assert!(open_otp(&key, &swapped).unwrap_err().is::<TamperError>());
+
+        let mut hotp = new_item(&key, "bob", "secret", "").unwrap();
+        seal_otp(&key, &mut hotp, "otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1").unwrap();
+        assert_eq!(take_otp_code(&key, &mut hotp, 0).unwrap().unwrap(), "287082");
+        assert_eq!(take_otp_code(&key, &mut hotp, 0).unwrap().unwrap(), "359152");
+        let sealed = item.otp.clone();
+        assert_eq!(take_otp_code(&key, &mut item, 59).unwrap().unwrap(), "287082");
+        assert_eq!(item.otp, sealed);
+
+        seal_otp(&key, &mut item, "").unwrap();
+        assert_eq!(item.otp, "");
+        assert_eq!(take_otp_code(&key, &mut item, 59).unwrap(), None);
+    }
+}
@@ -6,7 +6,7 @@ use eframe::{CreationContext, egui, Storage};
 use eframe::egui::{Button, Frame, Hyperlink, Label, Layout, Separator, TextStyle, TopBottomPanel, Ui};
 use egui::RichText;
 use once_cell::sync::Lazy;
-use uuid::Uuid;
+use zeroize::{Zeroize, Zeroizing};
 
 use types::{*};
 use types::Item;
@@ -15,6 +15,15 @@ use crate::constants::BASE_FONT_SIZE;
 use crate::db::Database;
 use crate::egui::{Align, Color32, ScrollArea};
 use crate::egui::WidgetText;
+use crate::generator::Policy;
+use crate::audit::Finding;
+use crate::hibp::BreachList;
+use crate::secret::{SecretBuf, SecretString, SessionKey};
+use crate::shamir::Share;
+use crate::strength::Hashing;
+
+// up to 255 shares of about 130 chars each
+const SHARES_TEXT_CAPACITY: usize = 64 * 1024;
 
 static DB: Lazy<Database> = Lazy::new(|| {
     let database = Database::new(".db").unwrap();
@@ -28,42 +37,190 @@ mod db;
 mod constants;
 mod font;
 mod page;
+mod vault;
+mod item;
+mod cipher;
+mod secret;
+mod shamir;
+mod recovery;
+mod otp;
+mod import;
+mod kind;
+mod organize;
+pub mod generator;
+pub mod strength;
+mod hibp;
+mod history;
+mod attachment;
+pub mod audit;
+pub mod cli;
 
 #[derive(Debug, PartialEq)]
 enum State {
     Guild,
     Home,
     New,
+    Edit(usize),
     Detail(usize),
+    ChangePassword,
+    Keys,
+    Shares,
+    Recovery,
+    ResetPassword,
+    Import,
+    Settings,
+    Breaches,
+    Audit,
+    Trash,
 }
 
 pub struct CPandas {
-    items: Vec<Item>,
+    items: Vec<Entry>,
     input_secret: String,
     input_secret_tips: String,
+    input_keyfile: String,
+    // recovery shares pasted on the guild page, one per line
+    input_recovery_shares: SecretString,
+    // only set while the vault is unlocked
+    session: Option<SessionKey>,
     new_temp_item: InputItem,
+    input_password: InputPassword,
+    input_key_slot: InputKeySlot,
+    input_shares: InputShares,
+    input_recovery: InputRecovery,
+    input_import: InputImport,
+    // timeline of the item in the detail view
+    input_history: InputHistory,
+    // custom field added from the detail view
+    input_field: InputField,
+    field_revealed: Option<String>,
+    // custom field waiting for its removal to be confirmed
+    field_removing: Option<String>,
+    field_tips: String,
+    input_attachment: InputAttachment,
+    input_trash: InputTrash,
+    // loaded from the vault on unlock, saved on every Generate
+    generator_policy: Policy,
+    settings: Settings,
+    settings_tips: String,
+    // opened from settings.hibp_path
+    breach_list: Option<BreachList>,
+    breach_report: Vec<(usize, u32)>,
+    breach_tips: String,
+    // (sealed secret, breach count) of the item in the detail view
+    detail_breaches: Option<(String, Result<u32, String>)>,
+    // HIBP index being built by a worker thread
+    index_build: Option<page::settings::IndexBuild>,
+    audit_findings: Vec<Finding>,
+    audit_tips: String,
+    home_filter: HomeFilter,
+    // expired and due items, shown on the home page after unlock
+    due_tips: String,
+    // the meter under the password and secret inputs
+    strength_cache: page::strength::StrengthCache,
+    // why the last Copy Code or Edit failed
+    detail_tips: String,
+    // a new recovery key, only kept until it has been written down
+    recovery_key: Option<SecretBuf>,
     state: State,
 
 }
 
 impl CPandas {
     pub fn new(ctx: &CreationContext<'_>) -> Self {
-        let tmp_items = DB.get_item_list();
-        let mut items: Vec<Item> = Vec::new();
-        if let Ok(item_list) = tmp_items {
-            if let Some(list) = item_list {
-                items = list
-            }
-        };
         font::FontManager::load_custom_fonts(&ctx.egui_ctx, "././fonts/icons.ttf".to_string());
         Self {
-            items,
+            items: Vec::new(),
             input_secret: "".to_string(),
             input_secret_tips: "".to_string(),
+            input_keyfile: "".to_string(),
+            input_recovery_shares: SecretString::with_capacity(SHARES_TEXT_CAPACITY),
+            session: None,
             new_temp_item: Default::default(),
+            input_password: Default::default(),
+            input_key_slot: Default::default(),
+            input_shares: Default::default(),
+            input_recovery: InputRecovery { create: true, ..Default::default() },
+            input_import: Default::default(),
+            input_history: Default::default(),
+            input_field: Default::default(),
+            field_revealed: None,
+            field_removing: None,
+            field_tips: "".to_string(),
+            input_attachment: Default::default(),
+            input_trash: Default::default(),
+            generator_policy: Default::default(),
+            settings: DB.get_settings().unwrap().unwrap_or_default(),
+            settings_tips: "".to_string(),
+            breach_list: None,
+            breach_report: Vec::new(),
+            breach_tips: "".to_string(),
+            detail_breaches: None,
+            index_build: None,
+            audit_findings: Vec::new(),
+            audit_tips: "".to_string(),
+            due_tips: "".to_string(),
+            detail_tips: "".to_string(),
+            strength_cache: Default::default(),
+            home_filter: Default::default(),
+            recovery_key: None,
             state: State::Guild,
         }
     }
+
+    fn with_key<R>(&self, f: impl FnOnce(&[u8]) -> anyhow::Result<R>) -> anyhow::Result<R> {
+        match &self.session {
+            Some(session) => session.with_key(f)?,
+            None => anyhow::bail!("vault is locked"),
+        }
+    }
+
+    // a missing or broken file only turns the breach check off
+    fn open_breach_list(&mut self) {
+        self.breach_list = None;
+        self.detail_breaches = None;
+        if self.settings.hibp_path == "" {
+            return;
+        }
+        match BreachList::open(&self.settings.hibp_path) {
+            Ok(list) => self.breach_list = Some(list),
+            Err(e) => log::error!("open HIBP file {} failed: {}", self.settings.hibp_path, e),
+        }
+    }
+
+    // drop the session key and everything decrypted with it
+    fn lock(&mut self) {
+        self.session = None;
+        for entry in &mut self.items {
+            entry.account.zeroize();
+            entry.desc.zeroize();
+        }
+        self.items.clear();
+        self.input_secret.zeroize();
+        self.new_temp_item.clear();
+        self.input_password.clear();
+        self.input_key_slot.clear();
+        self.input_shares.clear();
+        self.input_recovery.clear();
+        self.input_import.clear();
+        self.input_history.clear();
+        self.input_field.clear();
+        self.field_revealed = None;
+        self.field_removing = None;
+        self.field_tips = "".to_string();
+        self.input_attachment.clear();
+        self.input_trash.clear();
+        self.recovery_key = None;
+        self.breach_report.clear();
+        self.detail_breaches = None;
+        self.audit_findings.clear();
+        self.due_tips = "".to_string();
+        self.detail_tips = "".to_string();
+        self.strength_cache.clear();
+        self.home_filter.clear();
+        self.input_secret_tips = "vault locked".to_string();
+        self.state = State::Guild;
+    }
 }
 
 impl eframe::App for CPandas {
@@ -75,18 +232,34 @@ impl eframe::App for CPandas {
                 State::Guild => { guild_view(self, ctx, ui) }
                 State::Home => { home_view(self, ctx, ui) }
                 State::New => { new_view(self, ctx, ui) }
+                State::Edit(index) => { edit_view(self, ctx, ui, index) }
                 State::Detail(index) => { detail_view(self, ctx, ui, index) }
+                State::ChangePassword => { change_password_view(self, ctx, ui) }
+                State::Keys => { page::keys::keys_view(self, ctx, ui) }
+                State::Shares => { page::shares::shares_view(self, ctx, ui) }
+                State::Recovery => { page::recovery::recovery_view(self, ctx, ui) }
+                State::ResetPassword => { page::recovery::reset_password_view(self, ctx, ui) }
+                State::Import => { page::import::import_view(self, ctx, ui) }
+                State::Settings => { page::settings::settings_view(self, ctx, ui) }
+                State::Breaches => { page::breaches::breaches_view(self, ctx, ui) }
+                State::Audit => { page::audit::audit_view(self, ctx, ui) }
+                State::Trash => { page::trash::trash_view(self, ctx, ui) }
             }
         });
         render_bottom_panel(ctx)
     }
+
+    fn on_exit_event(&mut self) -> bool {
+        self.lock();
+        true
+    }
 }
 
 
 fn guild_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
     ui.add_space(10.);
 
-    let secret_hash_opt = DB.get_secret_hash().unwrap();
+    let vault_created = vault::is_created(&DB).unwrap();
     if cp.input_secret_tips == "" {
         cp.input_secret_tips = "please input  password".to_string();
     }
@@ -97,84 +270,371 @@ fn guild_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
         ui.label(RichText::new("Input Password:").size(BASE_FONT_SIZE));
         ui.text_edit_singleline(&mut cp.input_secret);
     });
+    if vault_created {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Or Keyfile Path:").size(BASE_FONT_SIZE));
+            ui.text_edit_singleline(&mut cp.input_keyfile);
+        });
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Or Recovery Shares:").size(BASE_FONT_SIZE));
+            ui.add(egui::TextEdit::multiline(&mut cp.input_recovery_shares).password(true));
+        });
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Or Recovery Key:").size(BASE_FONT_SIZE));
+            ui.add(egui::TextEdit::singleline(&mut cp.input_recovery.key_value).password(true));
+        });
+    } else {
+        if cp.input_secret != "" {
+            page::strength::strength_meter(ui, cp.strength_cache.estimate(&cp.input_secret, &[], Hashing::Slow));
+        }
+        ui.checkbox(&mut cp.input_recovery.create, "Generate a recovery key for a lost password");
+    }
     ui.add_space(10.);
 
     ui.vertical_centered(|ui| {
         if ui.button(RichText::new("Confirm").size(BASE_FONT_SIZE).color(Color32::BLUE)).clicked() {
             log::debug!("confirm submit");
-            if cp.input_secret == "" {
-                cp.input_secret_tips = "password can`t empty".to_string();
-                return;
-            }
-            let secret_hash_opt = DB.get_secret_hash().unwrap();
-            let secret_key = utils::get_valid_aes_key(cp.input_secret.clone()).unwrap();
-            cp.input_secret = secret_key.clone();
-            let input_secret_hash = utils::sha256(secret_key.as_bytes()).unwrap();
-            if let Some(secret_hash) = secret_hash_opt {
-                if input_secret_hash == String::from_utf8(secret_hash).unwrap() {
+            let recover = vault_created && cp.input_recovery.key_value.as_str().trim() != "";
+            let result = if recover {
+                vault::unlock_with_recovery_key(&DB, cp.input_recovery.key_value.as_str())
+            } else if vault_created && cp.input_recovery_shares.as_str().trim() != "" {
+                cp.input_recovery_shares.as_str().lines()
+                    .filter(|line| line.trim() != "")
+                    .map(Share::from_text)
+                    .collect::<anyhow::Result<Vec<Share>>>()
+                    .and_then(|shares| vault::unlock_with_shares(&DB, &shares))
+            } else {
+                let secret = match read_unlock_secret(cp, vault_created) {
+                    Some(secret) => secret,
+                    None => return,
+                };
+                vault::unlock(&DB, &secret)
+            };
+            // account and desc are only decrypted in memory
+            let result = result.and_then(|secret_key| match secret_key {
+                Some(secret_key) => {
+                    let policy = DB.get_generator_policy()?.unwrap_or_default();
+                    Ok(Some((item::open_entries(&DB, &secret_key)?, SessionKey::new(secret_key)?, policy)))
+                }
+                None => Ok(None),
+            });
+            match result {
+                Ok(Some((items, session, policy))) => {
+                    cp.items = items;
+                    cp.session = Some(session);
+                    cp.generator_policy = policy;
+                    cp.open_breach_list();
+                    page::trash::purge_expired(cp);
+                    cp.due_tips = due_summary(cp);
+                    if let Err(e) = cp.with_key(|key| organize::rebuild(&DB, key, &cp.items)) {
+                        log::error!("rebuild index failed: {}", e);
+                    }
                     cp.state = State::Home;
-                } else {
-                    cp.input_secret_tips = "password not correct".to_string();
-                    cp.input_secret = "".to_string();
+                    if recover {
+                        cp.state = State::ResetPassword;
+                    } else if !vault_created && cp.input_recovery.create {
+                        page::recovery::new_recovery_key(cp);
+                    }
+                }
+                Ok(None) => {
+                    cp.input_secret_tips = "password, shares or recovery key not correct".to_string();
+                }
+                Err(e) => {
+                    log::error!("unlock error: {}", e);
+                    cp.input_secret_tips = format!("unlock failed: {}", e);
                 }
-            } else {
-                DB.put_secret_hash(input_secret_hash).unwrap();
-                cp.state = State::Home;
             }
+            cp.input_secret.zeroize();
+            cp.input_keyfile = "".to_string();
+            cp.input_recovery_shares.wipe();
+            cp.input_recovery.key_value.wipe();
         }
     });
 }
 
+// the keyfile when a path is given, else the password; None after setting the tips
+fn read_unlock_secret(cp: &mut CPandas, vault_created: bool) -> Option<Zeroizing<Vec<u8>>> {
+    if vault_created && cp.input_keyfile != "" {
+        return match std::fs::read(&cp.input_keyfile) {
+            Ok(data) => Some(Zeroizing::new(data)),
+            Err(e) => {
+                cp.input_secret_tips = format!("read keyfile failed: {}", e);
+                None
+            }
+        };
+    }
+    if cp.input_secret == "" {
+        cp.input_secret_tips = "password can`t empty".to_string();
+        return None;
+    }
+    if !vault_created {
+        if let Some(tips) = weak_master_password(cp, &cp.input_secret) {
+            cp.input_secret_tips = tips;
+            return None;
+        }
+    }
+    Some(Zeroizing::new(cp.input_secret.as_bytes().to_vec()))
+}
+
+// why the password can't be the master password, None when it is strong enough
+fn weak_master_password(cp: &CPandas, password: &str) -> Option<String> {
+    let strength = strength::estimate(password, &[], Hashing::Slow);
+    if strength.score >= cp.settings.min_password_score {
+        return None;
+    }
+    let reason = if strength.warning != "" { strength.warning.clone() } else { strength.suggestions.join(", ") };
+    Some(format!("password too weak, score {} of {} needed: {}", strength.score, cp.settings.min_password_score, reason))
+}
+
+// how many items are expired or due for rotation, empty when none
+fn due_summary(cp: &CPandas) -> String {
+    let now = utils::now();
+    let due: Vec<item::Due> = cp.items.iter()
+        .filter(|entry| entry.item.status == ItemStatus::Active)
+        .filter_map(|entry| item::due(&entry.item, now, cp.settings.rotation_days))
+        .collect();
+    let expired = due.iter().filter(|due| **due == item::Due::Expired).count();
+    let rotate = due.len() - expired;
+    if due.is_empty() {
+        return "".to_string();
+    }
+    format!("{} items expired, {} items due for rotation", expired, rotate)
+}
+
+// viewing the secret or copying a code counts as a use
+fn mark_used(cp: &mut CPandas, index: usize) {
+    let it = &mut cp.items[index].item;
+    it.last_used = utils::now();
+    // only a time, the view opens even when it isn't saved
+    if let Err(e) = DB.put_item(it) {
+        log::error!("save last used of {} failed: {}", it.id, e);
+    }
+}
+
+// keeps the tag, folder and favorite index up with a changed item
+fn reindex(cp: &mut CPandas, id: &str, old: Option<&Entry>, new: Option<&Entry>) {
+    if let Err(e) = cp.with_key(|key| organize::reindex(&DB, key, id, old, new)) {
+        log::error!("index {} failed: {}", id, e);
+    }
+    cp.home_filter.stale = true;
+}
+
+fn toggle_favorite(cp: &mut CPandas, index: usize) {
+    let old = cp.items[index].clone();
+    let entry = &mut cp.items[index];
+    entry.item.favorite = !entry.item.favorite;
+    if let Err(e) = DB.put_item(&entry.item) {
+        log::error!("save favorite of {} failed: {}", entry.item.id, e);
+        entry.item.favorite = !entry.item.favorite;
+        return;
+    }
+    let new = entry.clone();
+    reindex(cp, &new.item.id, Some(&old), Some(&new));
+}
+
+fn set_status(cp: &mut CPandas, index: usize, status: ItemStatus) {
+    let it = &mut cp.items[index].item;
+    let mut new = it.clone();
+    item::set_status(&mut new, status, utils::now());
+    match DB.put_item(&new) {
+        Ok(()) => *it = new,
+        Err(e) => log::error!("save status of {} failed: {}", new.id, e),
+    }
+    cp.home_filter.stale = true;
+}
+
+enum RowAction {
+    View,
+    CopyCode,
+    Favorite,
+}
+
+fn item_row(cp: &CPandas, ui: &mut Ui, index: usize, now: u64) -> Option<RowAction> {
+    let entry = &cp.items[index];
+    let mut action = None;
+    ui.horizontal(|ui| {
+        ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+            ui.add_space(10.);
+            ui.add(Label::new(format!("index: {}", index)));
+            ui.add(Label::new(format!("Name: {}", &entry.account)));
+            ui.add(Label::new(format!("Desc: {}", &entry.desc)));
+            if entry.item.kind != kind::ItemKind::Login {
+                ui.label(entry.item.kind.label());
+            }
+            if entry.folder != "" || !entry.tags.is_empty() {
+                let tags: Vec<String> = entry.tags.iter().map(|tag| format!("#{}", tag)).collect();
+                ui.label(format!("{} {}", &entry.folder, tags.join(" ")));
+            }
+            match item::due(&entry.item, now, cp.settings.rotation_days) {
+                Some(item::Due::Expired) => {
+                    ui.label(RichText::new("⚠ expired").color(Color32::RED));
+                }
+                Some(item::Due::Rotate) => {
+                    ui.label(RichText::new("⟳ due for rotation").color(Color32::YELLOW));
+                }
+                None => {}
+            }
+            ui.add_space(10.);
+        });
+        // controls
+        ui.with_layout(Layout::right_to_left(), |ui| {
+            if ui.add(Button::new("View")).clicked() {
+                action = Some(RowAction::View);
+            }
+            if entry.item.otp != "" && ui.add(Button::new("Copy Code")).clicked() {
+                action = Some(RowAction::CopyCode);
+            }
+            let star = if entry.item.favorite { "★" } else { "☆" };
+            if ui.add(Button::new(star)).clicked() {
+                action = Some(RowAction::Favorite);
+            }
+        });
+    });
+    action
+}
+
 fn home_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
     navigate_menu_view(cp, ui, ctx);
+    if cp.due_tips != "" {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(format!("⚠ {}", &cp.due_tips)).size(BASE_FONT_SIZE).color(Color32::RED));
+            if ui.button("Dismiss").clicked() {
+                cp.due_tips = "".to_string();
+            }
+        });
+    }
+    if cp.detail_tips != "" {
+        ui.label(RichText::new(format!("tips: {}", &cp.detail_tips)).color(Color32::RED));
+    }
+    if cp.home_filter.stale {
+        page::organize::apply_filter(cp);
+    }
+    egui::SidePanel::left("home_sidebar").resizable(false).show_inside(ui, |ui| {
+        page::organize::sidebar(cp, ui);
+    });
+    let now = utils::now();
+    let mut action = None;
+    // favorites are pinned above the rest
+    let (favorites, others): (Vec<usize>, Vec<usize>) = (0..cp.items.len())
+        .filter(|index| cp.home_filter.shows(&cp.items[*index]))
+        .partition(|index| cp.items[*index].item.favorite);
     ScrollArea::vertical().show(ui, |ui| {
-        let result = DB.get_item_list().unwrap();
-        for index in 0..cp.items.len() {
-            let item = cp.items.get(index).unwrap();
-            log::debug!("{:?}, {:?}",index,item.id);
-            ui.horizontal(|ui| {
-                ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
-                    ui.add_space(10.);
-                    ui.add(Label::new(format!("index: {}", index)));
-                    ui.add(Label::new(format!("Name: {}", &item.account)));
-                    ui.add(Hyperlink::new(format!("Secret: {}", &item.secret)));
-                    ui.add(Label::new(format!("Desc: {}", &item.desc)));
-                    ui.add_space(10.);
-                });
-                // controls
-                ui.with_layout(Layout::right_to_left(), |ui| {
-                    // let delete_bt = Button::new("Delete");
-                    // if ui.add(delete_bt).clicked() {
-                    //     DB.del_item(&item.id).unwrap();
-                    // }
-                    if ui.add(Button::new("View")).clicked() {
-                        cp.state = State::Detail(index);
-                    }
-                });
-            });
+        if !favorites.is_empty() && !cp.home_filter.favorites {
+            ui.label(RichText::new("★ Favorites").size(BASE_FONT_SIZE));
+        }
+        for index in favorites.iter().chain(&others) {
+            if *index == others.first().copied().unwrap_or(usize::MAX) && !favorites.is_empty() {
+                ui.separator();
+            }
+            if let Some(row) = item_row(cp, ui, *index, now) {
+                action = Some((row, *index));
+            }
         }
         ui.add_space(80.);
     });
+    match action {
+        Some((RowAction::CopyCode, index)) => copy_otp_code(cp, index),
+        Some((RowAction::View, index)) => {
+            mark_used(cp, index);
+            cp.state = State::Detail(index);
+        }
+        Some((RowAction::Favorite, index)) => toggle_favorite(cp, index),
+        None => {}
+    }
 }
 
 
 fn detail_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui, index: usize) {
-    let item = cp.items.get(index).unwrap();
-    let password = utils::aes256_decode(&hex::decode(&item.secret).unwrap(),
-                                        cp.input_secret.clone().as_bytes(),
-                                        &hex::decode(&item.nonce).unwrap()).unwrap();
-
-    let decode_value = String::from_utf8(password).unwrap();
+    let entry = cp.items.get(index).unwrap();
+    let kind = entry.item.kind;
+    let secret = cp.with_key(|key| item::open_secret(key, &entry.item));
     ui.label(format!("index: {:?}", index));
-    ui.label(format!("Name: {}", &item.account));
-    ui.label(format!("Secret: {}", decode_value));
-    ui.label(format!("Desc: {}", &item.desc));
+    ui.label(format!("Kind: {}", kind.label()));
+    ui.label(format!("{}: {}", kind.account_label(), &entry.account));
+    match &secret {
+        // the plaintext stays in its SecretBuf, only the label gets a copy
+        Ok(password) => page::kinds::secret_detail(cp, ui, kind, password),
+        // tampered or a record that doesn't parse, either way the rest still shows
+        Err(e) => {
+            log::error!("{}", e);
+            ui.label(format!("{}: ⚠ {}", kind.secret_label(), e));
+        }
+    };
+    page::kinds::details_view(cp, ui, index);
+    let entry = &cp.items[index];
+    ui.label(format!("Desc: {}", &entry.desc));
+    let times = &entry.item;
+    ui.label(format!("Created: {}  Updated: {}", utils::format_date(times.created), utils::format_date(times.updated)));
+    if times.last_used > 0 {
+        ui.label(format!("Last Used: {}", utils::format_date(times.last_used)));
+    }
+    if let Some(expires) = times.expires {
+        ui.label(format!("Expires: {}", utils::format_date(expires)));
+    }
+    if let (Some(list), Ok(password), true) = (&cp.breach_list, &secret, kind.has_password()) {
+        // looked up once per sealed secret, not every frame
+        let cached = matches!(&cp.detail_breaches, Some((sealed, _)) if *sealed == entry.item.secret);
+        if !cached {
+            let count = list.count(password).map_err(|e| e.to_string());
+            cp.detail_breaches = Some((entry.item.secret.clone(), count));
+        }
+        match &cp.detail_breaches.as_ref().unwrap().1 {
+            Ok(0) => ui.label("Breaches: none known"),
+            Ok(count) => ui.label(RichText::new(format!("Breaches: ⚠ seen {} times, change it", count)).color(Color32::RED)),
+            Err(e) => ui.label(format!("Breaches: check failed: {}", e)),
+        };
+    }
+    match cp.with_key(|key| item::open_otp(key, &entry.item)) {
+        Ok(Some(otp)) => {
+            let now = utils::now();
+            // drawn every frame, so the code is labelled as is and wiped after
+            let code = Zeroizing::new(otp.code(now).unwrap_or_else(|e| e.to_string()));
+            ui.horizontal(|ui| {
+                ui.label("Code: ");
+                ui.label(code.as_str());
+                match otp.remaining(now) {
+                    Some(remaining) => {
+                        ui.label(format!("({}s)", remaining));
+                        // keep the countdown running
+                        ctx.request_repaint();
+                    }
+                    None => {
+                        ui.label("(counter based)");
+                    }
+                }
+            });
+        }
+        Ok(None) => {}
+        Err(e) => {
+            log::error!("{}", e);
+            ui.label(format!("Code: ⚠ {}", e));
+        }
+    }
 
-    let id = &item.id.clone();
+    let has_otp = entry.item.otp != "";
+    if cp.detail_tips != "" {
+        ui.label(RichText::new(format!("tips: {}", &cp.detail_tips)).color(Color32::RED));
+    }
+    page::fields::fields_detail(cp, ctx, ui, index);
+    page::attachments::attachments_view(cp, ui, index);
+    page::history::history_view(cp, ui, index);
     ui.horizontal(|ui| {
+        if has_otp && ui.button("Copy Code").clicked() {
+            copy_otp_code(cp, index);
+        }
+        if ui.button("Edit").clicked() {
+            start_edit(cp, index);
+        }
+        let archived = cp.items[index].item.status == ItemStatus::Archived;
+        if ui.button(if archived { "Unarchive" } else { "Archive" }).clicked() {
+            set_status(cp, index, if archived { ItemStatus::Active } else { ItemStatus::Archived });
+            cp.state = State::Home;
+        }
+        // deleting only moves it to the trash, purging is done there
         if ui.button("Delete").clicked() {
-            DB.del_item(id).unwrap();
-            cp.items.remove(index);
+            set_status(cp, index, ItemStatus::Trashed);
+            cp.input_history.clear();
+            cp.input_attachment.clear();
             cp.state = State::Home;
         }
         if ui.button("Close").clicked() {
@@ -184,41 +644,307 @@ fn detail_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui, index: usize)
 }
 
 
+// copies the item's one-time code to the clipboard, a used hotp code moves the counter on
+fn copy_otp_code(cp: &mut CPandas, index: usize) {
+    let id = cp.items[index].item.id.clone();
+    cp.detail_tips = match take_otp_code(cp, index) {
+        Ok(()) => "".to_string(),
+        Err(e) => {
+            log::error!("otp code of {} failed: {}", id, e);
+            format!("copy code failed: {}", e)
+        }
+    };
+}
+
+// the moved counter is only saved once the code is on the clipboard
+fn take_otp_code(cp: &mut CPandas, index: usize) -> anyhow::Result<()> {
+    let mut clipboard = ClipboardContext::new().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let mut it = cp.items[index].item.clone();
+    let code = match cp.with_key(|key| item::take_otp_code(key, &mut it, utils::now()))? {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+    clipboard.set_contents(code).map_err(|e| anyhow::anyhow!("{}", e))?;
+    it.last_used = utils::now();
+    DB.put_item(&it)?;
+    cp.items[index].item = it;
+    Ok(())
+}
+
+
 fn new_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    item_form(cp, ui);
     ui.horizontal(|ui| {
-        ui.label("Input Account: ");
+        if ui.button("Close").clicked() {
+            log::debug!("close");
+            cp.new_temp_item.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Submit").clicked() {
+            log::debug!("new submit");
+            let input = &cp.new_temp_item;
+            let result = cp.with_key(|key| {
+                let mut item = item::new_item(key, &input.account_value, input.secret_value.as_str(), &input.desc_value)?;
+                seal_input(key, &mut item, input)?;
+                Ok(item)
+            });
+            let item = match result {
+                Ok(item) => item,
+                Err(e) => {
+                    cp.new_temp_item.tips = format!("add failed: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = DB.put_item(&item) {
+                log::error!("save item {} failed: {}", item.id, e);
+                cp.new_temp_item.tips = format!("add failed: {}", e);
+                return;
+            }
+            let entry = Entry {
+                item,
+                account: input.account_value.clone(),
+                desc: input.desc_value.clone(),
+                tags: organize::parse_tags(&input.tags_value),
+                folder: organize::parse_folder(&input.folder_value),
+            };
+            reindex(cp, &entry.item.id.clone(), None, Some(&entry));
+            cp.items.push(entry);
+        }
+    });
+}
+
+
+// fills the form with the item's current fields
+// false when the item can't be edited, the reason is in detail_tips
+fn start_edit(cp: &mut CPandas, index: usize) -> bool {
+    let entry = &cp.items[index];
+    // a secret the form can't hold whole is not edited, Submit would save the cut copy
+    let result = cp.with_key(|key| {
+        let secret = SecretString::from_secret(&item::open_secret(key, &entry.item)?)
+            .map_err(|e| anyhow::anyhow!("{} can't be edited here: {}", entry.item.kind.secret_label(), e))?;
+        let details = item::open_details(key, &entry.item)?;
+        for (name, value) in &details {
+            SecretString::try_from_str(value).map_err(|e| anyhow::anyhow!("{} can't be edited here: {}", name, e))?;
+        }
+        for field in &entry.item.fields {
+            if let Some((_, name, value)) = item::open_custom_field(key, &entry.item, &field.id)? {
+                SecretString::from_secret(&value).map_err(|e| anyhow::anyhow!("{} can't be edited here: {}", name, e))?;
+            }
+        }
+        let otp = match item::open_otp(key, &entry.item)? {
+            Some(otp) => Some(SecretString::from_secret(&otp.to_uri()?)?),
+            None => None,
+        };
+        Ok((secret, otp, item::open_fields(key, &entry.item)?, details))
+    });
+    match result {
+        Ok((secret, otp, fields, details)) => {
+            let input = &mut cp.new_temp_item;
+            input.clear();
+            input.account_value = entry.account.clone();
+            input.secret_value = secret;
+            input.desc_value = entry.desc.clone();
+            input.expires_value = entry.item.expires.map(utils::format_date).unwrap_or_default();
+            input.fields = fields;
+            input.kind = entry.item.kind;
+            input.tags_value = entry.tags.join(", ");
+            input.folder_value = entry.folder.clone();
+            input.favorite = entry.item.favorite;
+            input.details = details.iter().map(|(name, value)| (name.clone(), SecretString::from(value.as_str()))).collect();
+            if let Some(otp) = otp {
+                input.otp_value = otp;
+            }
+            cp.detail_tips = "".to_string();
+            cp.state = State::Edit(index);
+            true
+        }
+        Err(e) => {
+            log::error!("edit {} failed: {}", entry.item.id, e);
+            cp.detail_tips = format!("edit failed: {}", e);
+            false
+        }
+    }
+}
+
+
+fn edit_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui, index: usize) {
+    item_form(cp, ui);
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.new_temp_item.clear();
+            cp.state = State::Detail(index);
+        }
+        if ui.button("Submit").clicked() {
+            log::debug!("edit submit");
+            let input = &cp.new_temp_item;
+            let entry = &cp.items[index];
+            let result = cp.with_key(|key| {
+                let mut item = item::update_item(key, &entry.item, &input.account_value, input.secret_value.as_str(), &input.desc_value)?;
+                seal_input(key, &mut item, input)?;
+                // the replaced values go to the item's history in the same write
+                history::save_change(&DB, key, &entry.item, &item, &cp.settings, utils::now())?;
+                Ok(item)
+            });
+            let item = match result {
+                Ok(item) => item,
+                Err(e) => {
+                    cp.new_temp_item.tips = format!("save failed: {}", e);
+                    return;
+                }
+            };
+            cp.input_history.clear();
+            let entry = Entry {
+                item,
+                account: input.account_value.clone(),
+                desc: input.desc_value.clone(),
+                tags: organize::parse_tags(&input.tags_value),
+                folder: organize::parse_folder(&input.folder_value),
+            };
+            let old = std::mem::replace(&mut cp.items[index], entry.clone());
+            reindex(cp, &entry.item.id, Some(&old), Some(&entry));
+            cp.new_temp_item.clear();
+            cp.state = State::Detail(index);
+        }
+    });
+}
+
+
+// the fields shared by the new and edit views
+fn item_form(cp: &mut CPandas, ui: &mut Ui) {
+    if cp.new_temp_item.tips != "" {
+        ui.label(RichText::new(format!("tips: {}", &cp.new_temp_item.tips)).size(BASE_FONT_SIZE));
+    }
+    ui.horizontal(|ui| {
+        ui.label("Kind: ");
+        page::kinds::kind_combo(ui, &mut cp.new_temp_item.kind);
+    });
+    let kind = cp.new_temp_item.kind;
+    ui.horizontal(|ui| {
+        ui.label(format!("Input {}: ", kind.account_label()));
         ui.text_edit_singleline(&mut cp.new_temp_item.account_value);
     });
     ui.horizontal(|ui| {
-        ui.label("Input Secret: ");
-        ui.text_edit_singleline(&mut cp.new_temp_item.secret_value);
+        ui.label(format!("Input {}: ", kind.secret_label()));
+        if kind.multiline_secret() {
+            ui.add(egui::TextEdit::multiline(&mut cp.new_temp_item.secret_value).desired_rows(4));
+        } else {
+            ui.text_edit_singleline(&mut cp.new_temp_item.secret_value);
+        }
     });
+    let input = &cp.new_temp_item;
+    if kind.has_password() {
+        if input.secret_value.as_str() != "" {
+            let strength = cp.strength_cache.estimate(input.secret_value.as_str(), &[&input.account_value, &input.desc_value], Hashing::Fast);
+            page::strength::strength_meter(ui, strength);
+        }
+        page::generator::generator_view(cp, ui);
+    }
+    page::kinds::slots_form(ui, &mut cp.new_temp_item);
     ui.horizontal(|ui| {
         ui.label("Input Desc: ");
         ui.text_edit_singleline(&mut cp.new_temp_item.desc_value);
     });
+    if kind.has_password() {
+        ui.horizontal(|ui| {
+            ui.label("Input OTP URI: ");
+            ui.add(egui::TextEdit::singleline(&mut cp.new_temp_item.otp_value).password(true));
+        });
+    }
+    ui.horizontal(|ui| {
+        ui.label("Expires (YYYY-MM-DD): ");
+        ui.text_edit_singleline(&mut cp.new_temp_item.expires_value);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Folder (Work/Mail): ");
+        ui.text_edit_singleline(&mut cp.new_temp_item.folder_value);
+        ui.checkbox(&mut cp.new_temp_item.favorite, "Favorite");
+    });
+    ui.horizontal(|ui| {
+        ui.label("Tags (comma separated): ");
+        ui.text_edit_singleline(&mut cp.new_temp_item.tags_value);
+    });
+    page::fields::fields_form(ui, &mut cp.new_temp_item.fields);
+}
+
+// everything of the form but account, secret and desc
+fn seal_input(key: &[u8], item: &mut Item, input: &InputItem) -> anyhow::Result<()> {
+    let details = input.details.iter().map(|(name, value)| (name.clone(), value.as_str().to_string())).collect();
+    let details = kind::check(input.kind, input.secret_value.as_str(), &details)?;
+    item.kind = input.kind;
+    item::seal_details(key, item, &details)?;
+    // only logins have codes, a login turned into another kind loses its code
+    let otp = if input.kind.has_password() { input.otp_value.as_str() } else { "" };
+    item::seal_otp(key, item, otp)?;
+    item::seal_fields(key, item, &input.fields)?;
+    item::seal_organize(key, item, &organize::parse_tags(&input.tags_value), &organize::parse_folder(&input.folder_value))?;
+    item.favorite = input.favorite;
+    item.expires = read_expires(input)?;
+    Ok(())
+}
+
+// empty never expires
+fn read_expires(input: &InputItem) -> anyhow::Result<Option<u64>> {
+    if input.expires_value.trim() == "" {
+        return Ok(None);
+    }
+    Ok(Some(utils::parse_date(&input.expires_value)?))
+}
+
+
+fn change_password_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_password.tips == "" {
+        cp.input_password.tips = "the vault key will be wrapped with the new password".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_password.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(5.);
+    ui.horizontal(|ui| {
+        ui.label("Old Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_password.old_value).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("New Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_password.new_value).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Confirm Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_password.confirm_value).password(true));
+    });
+    ui.add_space(10.);
     ui.horizontal(|ui| {
         if ui.button("Close").clicked() {
-            log::debug!("close");
-            cp.new_temp_item.clear();
+            cp.input_password.clear();
             cp.state = State::Home;
         }
         if ui.button("Submit").clicked() {
-            log::debug!("new submit");
-            let uuid = Uuid::new_v4().to_string();
-            let (ciphertext, nonce) = utils::aes256_encode(
-                cp.new_temp_item.secret_value.as_bytes(),
-                cp.input_secret.as_bytes()).unwrap();
-            let item = Item {
-                id: uuid.clone(),
-                account: cp.new_temp_item.account_value.clone(),
-                secret: hex::encode(ciphertext),
-                desc: cp.new_temp_item.desc_value.clone(),
-                status: 0,
-                nonce: hex::encode(nonce),
-            };
-            DB.put_item(&item).unwrap();
-            cp.items.push(item);
+            log::debug!("change password submit");
+            let input = &mut cp.input_password;
+            if input.new_value.as_str() == "" {
+                input.tips = "new password can`t empty".to_string();
+                return;
+            }
+            if input.new_value.as_str() != input.confirm_value.as_str() {
+                input.tips = "new passwords not match".to_string();
+                return;
+            }
+            if let Some(tips) = weak_master_password(cp, cp.input_password.new_value.as_str()) {
+                cp.input_password.tips = tips;
+                return;
+            }
+            let input = &mut cp.input_password;
+            match vault::change_password(&DB, input.old_value.as_str(), input.new_value.as_str()) {
+                Ok(true) => {
+                    cp.input_password.clear();
+                    cp.state = State::Home;
+                }
+                Ok(false) => {
+                    input.tips = "old password not correct".to_string();
+                }
+                Err(e) => {
+                    log::error!("change password error: {}", e);
+                    input.tips = format!("change password failed: {}", e);
+                }
+            }
         }
     });
 }
@@ -236,29 +962,54 @@ fn navigate_menu_view(cp: &mut CPandas, ui: &mut Ui, ctx: &egui::Context) {
         ui.with_layout(Layout::right_to_left(), |ui| {
             let export_bt = ui.button("Export").on_hover_text("Export to Clipboard");
             if export_bt.clicked() {
-                let result = DB.get_item_list().unwrap();
-                if let Some(list) = result {
-                    let bakup_res = serde_json::to_string(&list).unwrap();
-                    let hex_bak = hex::encode(bakup_res);
-                    let export = Export::new("1".to_string(), hex_bak, "".to_string());
-                    let export = serde_json::to_string(&export).unwrap();
-                    let mut ctx = ClipboardContext::new().unwrap();
-                    ctx.set_contents(export).unwrap();
-                }
+                cp.detail_tips = match export_clipboard() {
+                    Ok(count) => format!("{} items exported to clipboard", count),
+                    Err(e) => format!("export failed: {}", e),
+                };
             }
             let import_bt = ui.button("Import").on_hover_text("Import from Clipboard");
 
             if import_bt.clicked() {
-                let mut ctx = ClipboardContext::new().unwrap();
-                let content = ctx.get_contents().unwrap();
-                let export: Export = serde_json::from_slice(content.as_bytes()).unwrap();
-                let hex_data = hex::decode(export.content).unwrap();
-                let list: Vec<Item> = serde_json::from_slice(&hex_data).unwrap();
-                for item in list {
-                    DB.put_item(&item).unwrap();
-                    cp.items.push(item)
-                }
-                log::debug!("import ok")
+                cp.detail_tips = match import_clipboard(cp) {
+                    Ok(tips) => tips,
+                    Err(e) => format!("import failed: {}", e),
+                };
+            }
+            let otp_bt = ui.button("2FA").on_hover_text("Import authenticator codes");
+            if otp_bt.clicked() {
+                cp.state = State::Import;
+            }
+            let lock_bt = ui.button("Lock").on_hover_text("Lock the vault");
+            if lock_bt.clicked() {
+                cp.lock();
+                return;
+            }
+            let shares_bt = ui.button("Shares").on_hover_text("Split the vault key for recovery");
+            if shares_bt.clicked() {
+                cp.state = State::Shares;
+            }
+            let keys_bt = ui.button("Keys").on_hover_text("Manage key slots");
+            if keys_bt.clicked() {
+                page::keys::load_slots(cp);
+                cp.state = State::Keys;
+            }
+            let audit_bt = ui.button("Audit").on_hover_text("Find weak, reused and old secrets");
+            if audit_bt.clicked() {
+                page::audit::run_audit(cp);
+                cp.state = State::Audit;
+            }
+            let breaches_bt = ui.button("Breaches").on_hover_text("Check secrets against the local HIBP list");
+            if breaches_bt.clicked() {
+                page::breaches::check_vault(cp);
+                cp.state = State::Breaches;
+            }
+            let settings_bt = ui.button("Settings").on_hover_text("Vault settings");
+            if settings_bt.clicked() {
+                cp.state = State::Settings;
+            }
+            let password_bt = ui.button("Password").on_hover_text("Change master password");
+            if password_bt.clicked() {
+                cp.state = State::ChangePassword;
             }
             let new_bt = ui.button("New ✚").on_hover_text("Add new Secret");
             if new_bt.clicked() {
@@ -270,6 +1021,77 @@ fn navigate_menu_view(cp: &mut CPandas, ui: &mut Ui, ctx: &egui::Context) {
     ui.add_space(10.);
 }
 
+// items stay sealed with the vault key, the export is tagged with the vault id
+fn export_clipboard() -> anyhow::Result<usize> {
+    let header = DB.get_vault_header()?.ok_or_else(|| anyhow::anyhow!("vault header missing"))?;
+    let list = DB.get_item_list()?.unwrap_or_default();
+    let hex_bak = hex::encode(serde_json::to_string(&list)?);
+    let export = Export::new(EXPORT_VERSION.to_string(), header.id, hex_bak, "".to_string());
+    let mut clipboard = ClipboardContext::new().map_err(|e| anyhow::anyhow!("{}", e))?;
+    clipboard.set_contents(serde_json::to_string(&export)?).map_err(|e| anyhow::anyhow!("{}", e))?;
+    Ok(list.len())
+}
+
+// only exports of this vault can be opened with its key, others are refused as a whole
+fn import_clipboard(cp: &mut CPandas) -> anyhow::Result<String> {
+    let mut clipboard = ClipboardContext::new().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let content = clipboard.get_contents().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let export: Export = serde_json::from_slice(content.as_bytes())?;
+    if export.version != EXPORT_VERSION {
+        anyhow::bail!("export version {} is not supported, export it again from its vault", export.version);
+    }
+    let header = DB.get_vault_header()?.ok_or_else(|| anyhow::anyhow!("vault header missing"))?;
+    if export.vault != header.id {
+        anyhow::bail!("export is from another vault, its items can`t be opened with this vault key");
+    }
+    let list: Vec<Item> = serde_json::from_slice(&hex::decode(export.content)?)?;
+    let total = list.len();
+    let count = import_items(cp, list);
+    if count < total {
+        return Ok(format!("{} items imported, {} skipped (already in the vault or can`t be opened)", count, total - count));
+    }
+    Ok(format!("{} items imported", count))
+}
+
+// saves the items this vault key can open, returns how many
+fn import_items(cp: &mut CPandas, list: Vec<Item>) -> usize {
+    let mut sealed = Vec::new();
+    for it in list {
+        match cp.with_key(|key| item::reencrypt(&it, key, key)) {
+            Ok(it) => sealed.push(it),
+            Err(e) => log::warn!("skip item {}: {}", it.id, e),
+        }
+    }
+    save_new_items(cp, sealed)
+}
+
+// saves items already sealed with the vault key, returns how many; existing ids are left alone
+fn save_new_items(cp: &mut CPandas, list: Vec<Item>) -> usize {
+    let mut count = 0;
+    for it in list {
+        if cp.items.iter().any(|entry| entry.item.id == it.id) {
+            log::warn!("skip item {}: already in the vault", it.id);
+            continue;
+        }
+        let result = cp.with_key(|key| item::open_entry(key, &it));
+        let entry = match result {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("skip item {}: {}", it.id, e);
+                continue;
+            }
+        };
+        if let Err(e) = DB.put_item(&it) {
+            log::error!("save item {} failed: {}", it.id, e);
+            continue;
+        }
+        reindex(cp, &it.id, None, Some(&entry));
+        cp.items.push(entry);
+        count += 1;
+    }
+    count
+}
+
 fn render_top_panel(ctx: &egui::Context) {
     TopBottomPanel::top("top_panel").show(ctx, |ui| {
         ui.add_space(30.);
@@ -1,9 +1,18 @@
 use eframe::egui::Vec2;
 use log::LevelFilter;
 use CPandas::CPandas;
+use ::CPandas::cli;
 
 fn main() {
     env_logger::builder().filter(Some("node"),LevelFilter::Trace).init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
     let mut options = eframe::NativeOptions::default();
     options.initial_window_size = Some(Vec2::new(540., 960.));
     eframe::run_native(
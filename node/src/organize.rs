@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashSet};
+
+use anyhow::{anyhow, Result};
+use hmac::{Mac, SimpleHmac};
+use sha2::Sha256;
+
+use super::db::Database;
+use super::types::Entry;
+use super::utils;
+
+// the index only holds hashes of tags and folders keyed with this subkey, never the names
+const INDEX_HASH_CONTEXT: &[u8] = b"cpandas:index";
+pub const FAVORITE_TERM: &str = "favorite";
+
+// comma separated, trimmed and lowercased, each tag once
+pub fn parse_tags(text: &str) -> Vec<String> {
+    let mut tags: Vec<String> = text.split(',')
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| tag != "")
+        .collect();
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+// "Work / Mail/" is Work/Mail
+pub fn parse_folder(text: &str) -> String {
+    text.split('/').map(|part| part.trim()).filter(|part| *part != "").collect::<Vec<_>>().join("/")
+}
+
+// Work/Mail is in Work and in Work/Mail
+pub fn folder_prefixes(folder: &str) -> Vec<String> {
+    let parts: Vec<&str> = folder.split('/').filter(|part| *part != "").collect();
+    (1..=parts.len()).map(|len| parts[..len].join("/")).collect()
+}
+
+fn blind(key: &[u8], kind: &str, value: &str) -> Result<String> {
+    let index_key = utils::subkey(key, INDEX_HASH_CONTEXT)?;
+    let mut mac = <SimpleHmac<Sha256> as Mac>::new_from_slice(&index_key).map_err(|e| anyhow!("{}",e))?;
+    mac.update(kind.as_bytes());
+    mac.update(b":");
+    mac.update(value.as_bytes());
+    Ok(format!("{}:{}", kind, hex::encode(mac.finalize().into_bytes())))
+}
+
+pub fn tag_term(key: &[u8], tag: &str) -> Result<String> {
+    blind(key, "tag", tag)
+}
+
+pub fn folder_term(key: &[u8], folder: &str) -> Result<String> {
+    blind(key, "folder", folder)
+}
+
+// every index term an entry is found under
+pub fn terms(key: &[u8], entry: &Entry) -> Result<Vec<String>> {
+    let mut terms = Vec::new();
+    for tag in &entry.tags {
+        terms.push(tag_term(key, tag)?);
+    }
+    for folder in folder_prefixes(&entry.folder) {
+        terms.push(folder_term(key, &folder)?);
+    }
+    if entry.item.favorite {
+        terms.push(FAVORITE_TERM.to_string());
+    }
+    Ok(terms)
+}
+
+// moves an item in the index from its old entry to its new one, None when added or deleted
+pub fn reindex(db: &Database, key: &[u8], id: &str, old: Option<&Entry>, new: Option<&Entry>) -> Result<()> {
+    let old_terms = match old {
+        Some(entry) => terms(key, entry)?,
+        None => Vec::new(),
+    };
+    let new_terms = match new {
+        Some(entry) => terms(key, entry)?,
+        None => Vec::new(),
+    };
+    let removed: Vec<String> = old_terms.iter().filter(|term| !new_terms.contains(term)).cloned().collect();
+    let added: Vec<String> = new_terms.iter().filter(|term| !old_terms.contains(term)).cloned().collect();
+    if removed.is_empty() && added.is_empty() {
+        return Ok(());
+    }
+    db.update_index(id, &removed, &added)
+}
+
+// the whole index written again from the unlocked entries, so one left behind by a
+// failed write or an older version is fixed on the next unlock
+pub fn rebuild(db: &Database, key: &[u8], entries: &[Entry]) -> Result<()> {
+    let mut index: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in entries {
+        for term in terms(key, entry)? {
+            index.entry(term).or_default().push(entry.item.id.clone());
+        }
+    }
+    db.rebuild_index(&index)
+}
+
+// ids of the items in folder (subfolders included) and with tag, empty ones don't filter
+pub fn query(db: &Database, key: &[u8], folder: &str, tag: &str, favorites: bool) -> Result<Option<HashSet<String>>> {
+    let mut terms = Vec::new();
+    if folder != "" {
+        terms.push(folder_term(key, folder)?);
+    }
+    if tag != "" {
+        terms.push(tag_term(key, tag)?);
+    }
+    if favorites {
+        terms.push(FAVORITE_TERM.to_string());
+    }
+    let mut ids: Option<HashSet<String>> = None;
+    for term in terms {
+        let found: HashSet<String> = db.get_index(&term)?.into_iter().collect();
+        ids = Some(match ids {
+            Some(ids) => ids.intersection(&found).cloned().collect(),
+            None => found,
+        });
+    }
+    Ok(ids)
+}
+
+// every folder and its parents, sorted so a folder comes right after its parent
+pub fn folder_tree<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Vec<String> {
+    let mut folders: Vec<String> = entries.into_iter().flat_map(|entry| folder_prefixes(&entry.folder)).collect();
+    folders.sort();
+    folders.dedup();
+    folders
+}
+
+// (tag, item count) by tag
+pub fn tag_counts<'a>(entries: impl IntoIterator<Item = &'a Entry>) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for tag in entries.into_iter().flat_map(|entry| &entry.tags) {
+        *counts.entry(tag.clone()).or_default() += 1;
+    }
+    counts.into_iter().collect()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::TempDatabase;
+    use crate::{item, utils};
+
+    #[test]
+    fn index_entries() {
+        let db = TempDatabase::new();
+        let key = utils::aes256_key().unwrap();
+        let entry = |account: &str, tags: &str, folder: &str, favorite: bool| {
+            let mut it = item::new_item(&key, account, "secret", "").unwrap();
+            it.favorite = favorite;
+            item::seal_organize(&key, &mut it, &parse_tags(tags), &parse_folder(folder)).unwrap();
+            item::open_entry(&key, &it).unwrap()
+        };
+        let mut entries = vec![
+            entry("alice", "Mail, personal,mail", "Work / Mail/", true),
+            entry("bob", "bank", "Work", false),
+            entry("carol", "mail", "Home", false),
+        ];
+        assert_eq!(entries[0].tags, vec!["mail", "personal"]);
+        assert_eq!(entries[0].folder, "Work/Mail");
+        assert_eq!(folder_tree(&entries), vec!["Home", "Work", "Work/Mail"]);
+        assert_eq!(tag_counts(&entries), vec![("bank".to_string(), 1), ("mail".to_string(), 2), ("personal".to_string(), 1)]);
+        // terms don't give the names away
+        assert!(terms(&key, &entries[0]).unwrap().iter().all(|term| !term.contains("mail") && !term.contains("Work")));
+
+        rebuild(&db, &key, &entries).unwrap();
+        let ids = |folder: &str, tag: &str, favorites: bool| {
+            let mut ids: Vec<String> = query(&db, &key, folder, tag, favorites).unwrap().unwrap().into_iter().collect();
+            ids.sort();
+            ids
+        };
+        let id: Vec<String> = entries.iter().map(|entry| entry.item.id.clone()).collect();
+        let mut work = vec![id[0].clone(), id[1].clone()];
+        work.sort();
+        assert_eq!(ids("Work", "", false), work);
+        assert_eq!(ids("Work/Mail", "mail", false), vec![id[0].clone()]);
+        assert_eq!(ids("", "", true), vec![id[0].clone()]);
+        assert!(ids("Home", "bank", false).is_empty());
+        assert_eq!(query(&db, &key, "", "", false).unwrap(), None);
+
+        let mut moved = entries[2].clone();
+        moved.folder = "Work".to_string();
+        moved.tags.clear();
+        reindex(&db, &key, &moved.item.id, Some(&entries[2]), Some(&moved)).unwrap();
+        entries[2] = moved;
+        assert_eq!(ids("Work", "", false).len(), 3);
+        assert!(ids("Home", "", false).is_empty());
+        reindex(&db, &key, &id[2], Some(&entries[2]), None).unwrap();
+        assert_eq!(ids("Work", "", false), work);
+    }
+}
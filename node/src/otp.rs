@@ -0,0 +1,261 @@
+use anyhow::{anyhow, bail, Result};
+use base32::Alphabet;
+use hmac::{Mac, SimpleHmac};
+use hmac::digest::core_api::BlockSizeUser;
+use hmac::digest::Digest;
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use zeroize::Zeroize;
+
+use super::secret::SecretBuf;
+
+const OTPAUTH_SCHEME: &str = "otpauth://";
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+            Algorithm::Sha512 => "SHA512",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OtpKind {
+    // time based, RFC 6238
+    Totp { period: u64 },
+    // counter based, RFC 4226
+    Hotp { counter: u64 },
+}
+
+/// The content of an otpauth:// URI, the secret is the decoded base32 seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OtpAuth {
+    pub kind: OtpKind,
+    pub label: String,
+    pub issuer: String,
+    pub secret: SecretBuf,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+}
+
+fn hmac_digest<D: Digest + BlockSizeUser>(key: &[u8], msg: &[u8]) -> Result<SecretBuf> {
+    let mut mac = <SimpleHmac<D> as Mac>::new_from_slice(key).map_err(|e| anyhow!("{}",e))?;
+    mac.update(msg);
+    SecretBuf::from_slice(&mac.finalize().into_bytes())
+}
+
+pub fn hotp(secret: &[u8], counter: u64, algorithm: Algorithm, digits: u32) -> Result<String> {
+    if !(6..=8).contains(&digits) {
+        bail!("otp digits must be 6 to 8");
+    }
+    let msg = counter.to_be_bytes();
+    let hash = match algorithm {
+        Algorithm::Sha1 => hmac_digest::<Sha1>(secret, &msg)?,
+        Algorithm::Sha256 => hmac_digest::<Sha256>(secret, &msg)?,
+        Algorithm::Sha512 => hmac_digest::<Sha512>(secret, &msg)?,
+    };
+    // dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    let code = binary % 10u32.pow(digits);
+    Ok(format!("{:0width$}", code, width = digits as usize))
+}
+
+pub fn totp(secret: &[u8], time: u64, period: u64, algorithm: Algorithm, digits: u32) -> Result<String> {
+    if period == 0 {
+        bail!("otp period can`t be 0");
+    }
+    hotp(secret, time / period, algorithm, digits)
+}
+
+impl OtpAuth {
+    // otpauth://totp/Issuer:account?secret=BASE32&issuer=Issuer&algorithm=SHA1&digits=6&period=30
+    pub fn parse(uri: &str) -> Result<Self> {
+        let uri = uri.trim();
+        // get, as the scheme's length may end inside a character of other input
+        let rest = match uri.get(..OTPAUTH_SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(OTPAUTH_SCHEME) => &uri[OTPAUTH_SCHEME.len()..],
+            _ => bail!("not an otpauth:// uri"),
+        };
+        let (kind, rest) = rest.split_once('/').ok_or_else(|| anyhow!("otpauth uri without a label"))?;
+        let (label, query) = rest.split_once('?').unwrap_or((rest, ""));
+
+        let mut secret = None;
+        let mut issuer = String::new();
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = DEFAULT_DIGITS;
+        let mut period = DEFAULT_PERIOD;
+        let mut counter = None;
+        for pair in query.split('&').filter(|pair| *pair != "") {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode_str(value).decode_utf8()?;
+            match name.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(decode_secret(&value)?),
+                "issuer" => issuer = value.to_string(),
+                "algorithm" => algorithm = match value.to_ascii_uppercase().as_str() {
+                    "SHA1" => Algorithm::Sha1,
+                    "SHA256" => Algorithm::Sha256,
+                    "SHA512" => Algorithm::Sha512,
+                    _ => bail!("unsupported otp algorithm {}", value),
+                },
+                "digits" => digits = value.parse()?,
+                "period" => period = value.parse()?,
+                "counter" => counter = Some(value.parse()?),
+                _ => {}
+            }
+        }
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp { counter: counter.ok_or_else(|| anyhow!("hotp uri without a counter"))? },
+            _ => bail!("unknown otp type {}", kind),
+        };
+        let otp = OtpAuth {
+            kind,
+            label: percent_decode_str(label).decode_utf8()?.to_string(),
+            issuer,
+            secret: secret.ok_or_else(|| anyhow!("otpauth uri without a secret"))?,
+            algorithm,
+            digits,
+        };
+        // rejects bad digits and periods up front
+        otp.code(0)?;
+        Ok(otp)
+    }
+
+    pub fn to_uri(&self) -> Result<SecretBuf> {
+        let mut secret = base32::encode(Alphabet::RFC4648 { padding: false }, &self.secret);
+        let (kind, extra) = match self.kind {
+            OtpKind::Totp { period } => ("totp", format!("period={}", period)),
+            OtpKind::Hotp { counter } => ("hotp", format!("counter={}", counter)),
+        };
+        let mut uri = format!("{}{}/{}?secret={}", OTPAUTH_SCHEME, kind, utf8_percent_encode(&self.label, NON_ALPHANUMERIC), secret);
+        if self.issuer != "" {
+            uri.push_str(&format!("&issuer={}", utf8_percent_encode(&self.issuer, NON_ALPHANUMERIC)));
+        }
+        uri.push_str(&format!("&algorithm={}&digits={}&{}", self.algorithm.name(), self.digits, extra));
+        secret.zeroize();
+        SecretBuf::from_vec(uri.into_bytes())
+    }
+
+    // the current code: totp at time, hotp at its counter
+    pub fn code(&self, time: u64) -> Result<String> {
+        match self.kind {
+            OtpKind::Totp { period } => totp(&self.secret, time, period, self.algorithm, self.digits),
+            OtpKind::Hotp { counter } => hotp(&self.secret, counter, self.algorithm, self.digits),
+        }
+    }
+
+    // seconds the totp code at time stays valid
+    pub fn remaining(&self, time: u64) -> Option<u64> {
+        match self.kind {
+            OtpKind::Totp { period } => Some(period - time % period),
+            OtpKind::Hotp { .. } => None,
+        }
+    }
+
+    pub fn next_counter(&mut self) {
+        if let OtpKind::Hotp { counter } = &mut self.kind {
+            *counter += 1;
+        }
+    }
+}
+
+// base32 seeds come with any case, spaces and padding
+fn decode_secret(value: &str) -> Result<SecretBuf> {
+    let normalized: String = value.chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let normalized = SecretBuf::from_vec(normalized.into_bytes())?;
+    let secret = base32::decode(Alphabet::RFC4648 { padding: false }, normalized.as_str()?)
+        .ok_or_else(|| anyhow!("otp secret is not valid base32"))?;
+    if secret.is_empty() {
+        bail!("otp secret can`t empty");
+    }
+    SecretBuf::from_vec(secret)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHA1_SEED: &[u8] = b"12345678901234567890";
+    const SHA256_SEED: &[u8] = b"12345678901234567890123456789012";
+    const SHA512_SEED: &[u8] = b"1234567890123456789012345678901234567890123456789012345678901234";
+
+    #[test]
+    fn rfc4226_vectors() {
+        let expected = ["755224", "287082", "359152", "969429", "338314",
+            "254676", "287922", "162583", "399871", "520489"];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(SHA1_SEED, counter as u64, Algorithm::Sha1, 6).unwrap(), *code);
+        }
+    }
+
+    #[test]
+    fn rfc6238_vectors() {
+        let expected = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+        for (time, sha1, sha256, sha512) in expected {
+            assert_eq!(totp(SHA1_SEED, time, 30, Algorithm::Sha1, 8).unwrap(), sha1);
+            assert_eq!(totp(SHA256_SEED, time, 30, Algorithm::Sha256, 8).unwrap(), sha256);
+            assert_eq!(totp(SHA512_SEED, time, 30, Algorithm::Sha512, 8).unwrap(), sha512);
+        }
+    }
+
+    #[test]
+    fn parse_uri() {
+        let seed = base32::encode(Alphabet::RFC4648 { padding: true }, SHA256_SEED);
+        let uri = format!("otpauth://totp/ACME%20Co:alice@example.com?secret={}&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60",
+                          seed.to_lowercase());
+        let otp = OtpAuth::parse(&uri).unwrap();
+        assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+        assert_eq!(otp.label, "ACME Co:alice@example.com");
+        assert_eq!(otp.issuer, "ACME Co");
+        assert_eq!(otp.secret.as_bytes(), SHA256_SEED);
+        assert_eq!(otp.algorithm, Algorithm::Sha256);
+        assert_eq!(otp.code(59).unwrap(), totp(SHA256_SEED, 59, 60, Algorithm::Sha256, 8).unwrap());
+        assert_eq!(otp.remaining(59), Some(1));
+        assert_eq!(OtpAuth::parse(otp.to_uri().unwrap().as_str().unwrap()).unwrap(), otp);
+
+        let defaults = OtpAuth::parse("otpauth://totp/bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+        assert_eq!(defaults.kind, OtpKind::Totp { period: 30 });
+        assert_eq!((defaults.algorithm, defaults.digits), (Algorithm::Sha1, 6));
+        assert_eq!(defaults.code(59).unwrap(), "287082");
+
+        let mut hotp = OtpAuth::parse("otpauth://hotp/bob?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&counter=1").unwrap();
+        assert_eq!(hotp.code(0).unwrap(), "287082");
+        assert_eq!(hotp.remaining(0), None);
+        hotp.next_counter();
+        assert_eq!(hotp.code(0).unwrap(), "359152");
+        assert_eq!(OtpAuth::parse(hotp.to_uri().unwrap().as_str().unwrap()).unwrap(), hotp);
+
+        assert!(OtpAuth::parse("https://example.com").is_err());
+        assert!(OtpAuth::parse("otpauth:/é").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/bob").is_err());
+        assert!(OtpAuth::parse("otpauth://hotp/bob?secret=GEZDGNBV").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/bob?secret=GEZDGNBV&digits=10").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/bob?secret=GEZDGNBV&period=0").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/bob?secret=GEZDGNBV&algorithm=MD5").is_err());
+        assert!(OtpAuth::parse("otpauth://totp/bob?secret=1111").is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, DB, attachment, utils};
+use crate::types::Item;
+
+fn format_size(size: u64) -> String {
+    match size {
+        size if size >= 1024 * 1024 => format!("{:.1} MB", size as f64 / 1024. / 1024.),
+        size if size >= 1024 => format!("{:.1} KB", size as f64 / 1024.),
+        size => format!("{} B", size),
+    }
+}
+
+// the attachment names are sealed, so the list opens them only for this view
+fn names(cp: &CPandas, it: &Item) -> Vec<String> {
+    it.attachments.iter().map(|attachment| {
+        match cp.with_key(|key| crate::item::open_attachment(key, it, attachment)) {
+            Ok((name, _)) => name,
+            Err(e) => format!("⚠ {}", e),
+        }
+    }).collect()
+}
+
+/// Deletes the chunks no attachment in the vault refers to any more, run after an
+/// attachment or an item with attachments is removed.
+pub fn collect_garbage(cp: &CPandas) {
+    let items = match DB.get_item_list() {
+        Ok(items) => items.unwrap_or_default(),
+        Err(e) => {
+            log::error!("{}", e);
+            return;
+        }
+    };
+    match cp.with_key(|key| attachment::collect_garbage(&DB, key, &items)) {
+        Ok(count) => log::info!("{} unused chunks deleted", count),
+        Err(e) => log::error!("chunk gc failed: {}", e),
+    }
+}
+
+pub fn attachments_view(cp: &mut CPandas, ui: &mut Ui, index: usize) {
+    let it = cp.items[index].item.clone();
+    let names = names(cp, &it);
+    let mut removed = None;
+    ui.label("Attachments: ");
+    for (attachment, name) in it.attachments.iter().zip(&names) {
+        ui.horizontal(|ui| {
+            ui.label(name);
+            ui.label(format_size(attachment.size));
+            ui.label(utils::format_date(attachment.added));
+            if ui.small_button("Save Copy").clicked() {
+                let path = cp.input_attachment.save_path_value.trim().to_string();
+                if path == "" {
+                    cp.input_attachment.tips = "save path can`t empty".to_string();
+                } else {
+                    match cp.with_key(|key| attachment::save_copy(&DB, key, &it, &attachment.id, &path)) {
+                        Ok(target) => cp.input_attachment.tips = format!("saved to {}", target.display()),
+                        Err(e) => cp.input_attachment.tips = format!("save failed: {}", e),
+                    }
+                }
+            }
+            if cp.input_attachment.removing.as_ref() == Some(&attachment.id) {
+                ui.label(RichText::new("Remove for good?").color(egui::Color32::RED));
+                if ui.small_button("Yes").clicked() {
+                    removed = Some(attachment.id.clone());
+                }
+                if ui.small_button("No").clicked() {
+                    cp.input_attachment.removing = None;
+                }
+            } else if ui.small_button("Remove").clicked() {
+                cp.input_attachment.removing = Some(attachment.id.clone());
+            }
+        });
+    }
+    if let Some(id) = removed {
+        let mut it = it.clone();
+        it.attachments.retain(|attachment| attachment.id != id);
+        it.updated = utils::now();
+        match DB.put_item(&it) {
+            Ok(()) => {
+                cp.items[index].item = it;
+                cp.input_attachment.removing = None;
+                collect_garbage(cp);
+            }
+            Err(e) => cp.input_attachment.tips = format!("remove failed: {}", e),
+        }
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Save to: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_attachment.save_path_value).hint_text("file or directory"));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Attach: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_attachment.path_value).hint_text("file path"));
+        if ui.button("Attach File").clicked() {
+            let path = cp.input_attachment.path_value.trim().to_string();
+            if path == "" {
+                cp.input_attachment.tips = "file path can`t empty".to_string();
+                return;
+            }
+            let mut it = it.clone();
+            match cp.with_key(|key| attachment::attach_file(&DB, key, &mut it, &path)) {
+                Ok(()) => {
+                    it.updated = utils::now();
+                    match DB.put_item(&it) {
+                        Ok(()) => {
+                            cp.items[index].item = it;
+                            cp.input_attachment.clear();
+                        }
+                        Err(e) => cp.input_attachment.tips = format!("attach failed: {}", e),
+                    }
+                }
+                Err(e) => cp.input_attachment.tips = format!("attach failed: {}", e),
+            }
+        }
+    });
+    if cp.input_attachment.tips != "" {
+        ui.label(RichText::new(format!("tips: {}", &cp.input_attachment.tips)).color(egui::Color32::RED));
+    }
+}
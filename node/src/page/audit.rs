@@ -0,0 +1,70 @@
+use eframe::egui;
+use eframe::egui::{Button, Color32, RichText, ScrollArea, Ui};
+
+use crate::{CPandas, start_edit, State, utils};
+use crate::audit::{self, AuditOptions};
+use crate::constants::BASE_FONT_SIZE;
+
+pub fn run_audit(cp: &mut CPandas) {
+    let options = AuditOptions::from(&cp.settings);
+    let result = cp.with_key(|key| audit::audit(key, &cp.items, &options, cp.breach_list.as_ref(), utils::now()));
+    match result {
+        Ok(findings) => {
+            cp.audit_tips = format!("{} problems in {} items", findings.len(), cp.items.len());
+            cp.audit_findings = findings;
+        }
+        Err(e) => cp.audit_tips = format!("audit failed: {}", e),
+    }
+}
+
+pub fn audit_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    ui.label(RichText::new(format!("tips: {}", &cp.audit_tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.audit_findings.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Audit Again").clicked() {
+            run_audit(cp);
+        }
+        ui.label("Sort by: ");
+        if ui.button("Severity").clicked() {
+            audit::sort_by_severity(&mut cp.audit_findings);
+        }
+        if ui.button("Account").clicked() {
+            cp.audit_findings.sort_by(|a, b| a.account.cmp(&b.account));
+        }
+        if ui.button("Problem").clicked() {
+            cp.audit_findings.sort_by(|a, b| a.problem.describe().cmp(&b.problem.describe()));
+        }
+    });
+    ui.separator();
+    let mut edit = None;
+    ScrollArea::vertical().show(ui, |ui| {
+        for finding in &cp.audit_findings {
+            let color = match finding.problem.severity() {
+                4..=5 => Color32::RED,
+                2..=3 => Color32::YELLOW,
+                _ => Color32::GRAY,
+            };
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&finding.account).size(BASE_FONT_SIZE));
+                ui.label(RichText::new(finding.problem.describe()).color(color));
+                if ui.add(Button::new("Edit")).clicked() {
+                    edit = Some(finding.index);
+                }
+            });
+        }
+        ui.add_space(80.);
+    });
+    // the findings stay when the item can't be edited
+    if let Some(index) = edit {
+        if start_edit(cp, index) {
+            cp.audit_findings.clear();
+        } else {
+            cp.audit_tips = std::mem::take(&mut cp.detail_tips);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use eframe::egui;
+use eframe::egui::{Button, RichText, ScrollArea, Ui};
+
+use crate::{CPandas, hibp, State};
+use crate::constants::BASE_FONT_SIZE;
+
+// checks every item secret against the local breach list
+pub fn check_vault(cp: &mut CPandas) {
+    let list = match &cp.breach_list {
+        Some(list) => list,
+        None => {
+            cp.breach_tips = "set the HIBP file in Settings first".to_string();
+            cp.breach_report.clear();
+            return;
+        }
+    };
+    match cp.with_key(|key| hibp::check_entries(list, key, &cp.items)) {
+        Ok(report) => {
+            cp.breach_tips = format!("{} of {} secrets found in breaches", report.len(), cp.items.len());
+            cp.breach_report = report;
+        }
+        Err(e) => cp.breach_tips = format!("check failed: {}", e),
+    }
+}
+
+pub fn breaches_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    ui.label(RichText::new(format!("tips: {}", &cp.breach_tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.breach_report.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Check Again").clicked() {
+            check_vault(cp);
+        }
+    });
+    ui.separator();
+    let mut view = None;
+    ScrollArea::vertical().show(ui, |ui| {
+        for (index, count) in &cp.breach_report {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(&cp.items[*index].account).size(BASE_FONT_SIZE));
+                ui.label(RichText::new(format!("⚠ seen {} times", count)).color(egui::Color32::RED));
+                if ui.add(Button::new("View")).clicked() {
+                    view = Some(*index);
+                }
+            });
+        }
+        ui.add_space(80.);
+    });
+    if let Some(index) = view {
+        cp.breach_report.clear();
+        cp.state = State::Detail(index);
+    }
+}
@@ -0,0 +1,179 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+use zeroize::Zeroizing;
+
+use crate::{CPandas, DB, history, item, utils};
+use crate::otp::OtpAuth;
+use crate::types::{FieldKind, InputField, Item};
+
+fn kind_combo(ui: &mut Ui, id: impl std::hash::Hash, kind: &mut FieldKind) {
+    egui::ComboBox::from_id_source(id)
+        .selected_text(kind.label())
+        .show_ui(ui, |ui| {
+            for option in FieldKind::ALL {
+                ui.selectable_value(kind, option, option.label());
+            }
+        });
+}
+
+// custom fields of the new and edit form, ↑ ↓ move a field and ✖ removes it
+pub fn fields_form(ui: &mut Ui, fields: &mut Vec<InputField>) {
+    let count = fields.len();
+    let mut moved = None;
+    let mut removed = None;
+    for (i, field) in fields.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            kind_combo(ui, ("field kind", i), &mut field.kind);
+            ui.add(egui::TextEdit::singleline(&mut field.name).hint_text("name").desired_width(120.));
+            ui.add(egui::TextEdit::singleline(&mut field.value).hint_text("value").password(field.kind.is_hidden()));
+            if i > 0 && ui.small_button("↑").clicked() {
+                moved = Some(i);
+            }
+            if i + 1 < count && ui.small_button("↓").clicked() {
+                moved = Some(i + 1);
+            }
+            if ui.small_button("✖").clicked() {
+                removed = Some(i);
+            }
+        });
+    }
+    // moving a field down is moving the next one up
+    if let Some(i) = moved {
+        fields.swap(i - 1, i);
+    }
+    if let Some(i) = removed {
+        fields.remove(i).clear();
+    }
+    if ui.button("Add Field").clicked() {
+        fields.push(InputField::default());
+    }
+}
+
+// the current code of a TOTP field, or why there is none
+fn field_code(value: &str) -> Zeroizing<String> {
+    match OtpAuth::parse(value).and_then(|otp| otp.code(utils::now())) {
+        Ok(code) => Zeroizing::new(code),
+        Err(e) => Zeroizing::new(format!("⚠ {}", e)),
+    }
+}
+
+fn copy(text: String) {
+    let mut ctx = ClipboardContext::new().unwrap();
+    ctx.set_contents(text).unwrap();
+}
+
+fn save_item(cp: &mut CPandas, index: usize, mut it: Item) {
+    it.updated = utils::now();
+    match DB.put_item(&it) {
+        Ok(()) => cp.items[index].item = it,
+        Err(e) => cp.field_tips = format!("save failed: {}", e),
+    }
+}
+
+// custom fields in the detail view, changes are saved right away
+pub fn fields_detail(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui, index: usize) {
+    let it = cp.items[index].item.clone();
+    let fields = match cp.with_key(|key| item::open_fields(key, &it)) {
+        Ok(fields) => fields,
+        Err(e) => {
+            log::error!("{}", e);
+            ui.label(RichText::new(format!("Fields: ⚠ {}", e)).color(egui::Color32::RED));
+            return;
+        }
+    };
+    let mut moved = None;
+    let mut removed = None;
+    for (i, field) in fields.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.label(format!("{}: ", field.name));
+            let revealed = cp.field_revealed.as_ref() == Some(&field.id);
+            match field.kind {
+                FieldKind::Totp => {
+                    ui.label(field_code(field.value.as_str()).as_str());
+                    ctx.request_repaint();
+                }
+                FieldKind::Concealed if !revealed => {
+                    ui.label("******");
+                }
+                FieldKind::Url => {
+                    ui.hyperlink(field.value.as_str());
+                }
+                _ => {
+                    ui.label(field.value.as_str());
+                }
+            }
+            if field.kind == FieldKind::Concealed {
+                let text = if revealed { "Hide" } else { "Reveal" };
+                if ui.small_button(text).clicked() {
+                    cp.field_revealed = if revealed { None } else { Some(field.id.clone()) };
+                }
+            }
+            if field.kind.is_hidden() && ui.small_button("Copy").clicked() {
+                match field.kind {
+                    FieldKind::Totp => copy(field_code(field.value.as_str()).to_string()),
+                    _ => copy(field.value.as_str().to_string()),
+                }
+            }
+            if i > 0 && ui.small_button("↑").clicked() {
+                moved = Some(i);
+            }
+            if i + 1 < fields.len() && ui.small_button("↓").clicked() {
+                moved = Some(i + 1);
+            }
+            if cp.field_removing.as_ref() == Some(&field.id) {
+                ui.label(RichText::new("Remove?").color(egui::Color32::RED));
+                if ui.small_button("Yes").clicked() {
+                    removed = Some(i);
+                }
+                if ui.small_button("No").clicked() {
+                    cp.field_removing = None;
+                }
+            } else if ui.small_button("✖").clicked() {
+                cp.field_removing = Some(field.id.clone());
+            }
+        });
+    }
+    // fields are sealed by their own id, so moving one needs no key
+    if let Some(i) = moved {
+        let mut it = it.clone();
+        it.fields.swap(i - 1, i);
+        save_item(cp, index, it);
+    }
+    // a removed field goes to the history, so it can be restored from there
+    if let Some(i) = removed {
+        let mut new = it.clone();
+        new.fields.remove(i);
+        new.updated = utils::now();
+        let result = cp.with_key(|key| history::save_change(&DB, key, &it, &new, &cp.settings, utils::now()));
+        match result {
+            Ok(()) => {
+                cp.items[index].item = new;
+                cp.input_history.clear();
+                cp.field_removing = None;
+            }
+            Err(e) => cp.field_tips = format!("remove field failed: {}", e),
+        }
+    }
+
+    ui.horizontal(|ui| {
+        kind_combo(ui, "new field kind", &mut cp.input_field.kind);
+        ui.add(egui::TextEdit::singleline(&mut cp.input_field.name).hint_text("name").desired_width(120.));
+        ui.add(egui::TextEdit::singleline(&mut cp.input_field.value).hint_text("value").password(cp.input_field.kind.is_hidden()));
+        if ui.button("Add Field").clicked() {
+            let mut it = it.clone();
+            let input = &cp.input_field;
+            match cp.with_key(|key| item::add_field(key, &mut it, input.kind, &input.name, input.value.as_str())) {
+                Ok(()) => {
+                    cp.input_field.clear();
+                    cp.field_tips = "".to_string();
+                    save_item(cp, index, it);
+                }
+                Err(e) => cp.field_tips = format!("add field failed: {}", e),
+            }
+        }
+    });
+    if cp.field_tips != "" {
+        ui.label(RichText::new(format!("tips: {}", &cp.field_tips)).color(egui::Color32::RED));
+    }
+}
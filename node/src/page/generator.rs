@@ -0,0 +1,59 @@
+use eframe::egui;
+use eframe::egui::Ui;
+
+use crate::{CPandas, DB};
+use crate::generator::{self, MAX_LENGTH, MAX_WORDS, Mode};
+use crate::secret::SecretString;
+
+// generator options under the secret field, Generate fills the secret in
+pub fn generator_view(cp: &mut CPandas, ui: &mut Ui) {
+    egui::CollapsingHeader::new("Generator").show(ui, |ui| {
+        let policy = &mut cp.generator_policy;
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut policy.mode, Mode::Random, "Random");
+            ui.radio_value(&mut policy.mode, Mode::Pronounceable, "Pronounceable");
+            ui.radio_value(&mut policy.mode, Mode::Passphrase, "Passphrase");
+        });
+        if policy.mode == Mode::Passphrase {
+            ui.horizontal(|ui| {
+                ui.label("Words: ");
+                ui.add(egui::DragValue::new(&mut policy.words).clamp_range(3..=MAX_WORDS));
+                ui.label("Separator: ");
+                ui.add(egui::TextEdit::singleline(&mut policy.separator).desired_width(30.));
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut policy.capitalize, "Capitalize");
+                ui.checkbox(&mut policy.digits, "Add a digit");
+            });
+        } else {
+            ui.horizontal(|ui| {
+                ui.label("Length: ");
+                ui.add(egui::DragValue::new(&mut policy.length).clamp_range(4..=MAX_LENGTH));
+                if policy.mode == Mode::Random {
+                    ui.label("Min per class: ");
+                    ui.add(egui::DragValue::new(&mut policy.min_per_class).clamp_range(0..=8));
+                }
+            });
+            ui.horizontal(|ui| {
+                if policy.mode == Mode::Random {
+                    ui.checkbox(&mut policy.lowercase, "a-z");
+                }
+                ui.checkbox(&mut policy.uppercase, "A-Z");
+                ui.checkbox(&mut policy.digits, "0-9");
+                ui.checkbox(&mut policy.symbols, "!@#");
+                ui.checkbox(&mut policy.exclude_ambiguous, "No ambiguous");
+            });
+        }
+        if ui.button("Generate").clicked() {
+            match generator::generate(&cp.generator_policy) {
+                Ok(secret) => {
+                    cp.new_temp_item.secret_value = SecretString::from(secret.as_str().unwrap_or_default());
+                    if let Err(e) = DB.put_generator_policy(&cp.generator_policy) {
+                        log::error!("save generator policy failed: {}", e);
+                    }
+                }
+                Err(e) => cp.new_temp_item.tips = format!("generate failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,70 @@
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, DB, history, item, utils};
+
+// timeline of the item's old field values under the detail view, newest first
+pub fn history_view(cp: &mut CPandas, ui: &mut Ui, index: usize) {
+    let id = cp.items[index].item.id.clone();
+    if cp.input_history.id != id {
+        cp.input_history.versions.clear();
+        cp.input_history.revealed = None;
+        // another item's tips don't carry over, a restore's do
+        if cp.input_history.id != "" {
+            cp.input_history.tips = "".to_string();
+        }
+        match history::load(&DB, &id, &cp.settings, utils::now()) {
+            Ok(versions) => cp.input_history.versions = versions,
+            Err(e) => cp.input_history.tips = format!("load history failed: {}", e),
+        }
+        cp.input_history.id = id.clone();
+    }
+    let mut revealed = cp.input_history.revealed;
+    let mut restore = None;
+    egui::CollapsingHeader::new(format!("History ({})", cp.input_history.versions.len())).show(ui, |ui| {
+        if cp.input_history.tips != "" {
+            ui.label(&cp.input_history.tips);
+        }
+        for (i, version) in cp.input_history.versions.iter().enumerate().rev() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} {}: ", utils::format_date(version.replaced), history::label(version)));
+                if revealed == Some(i) {
+                    match cp.with_key(|key| history::reveal(key, &id, version)) {
+                        Ok(value) => ui.label(value.as_str().unwrap_or("<not utf8>")),
+                        Err(e) => ui.label(RichText::new(format!("⚠ {}", e)).color(egui::Color32::RED)),
+                    };
+                    if ui.button("Hide").clicked() {
+                        revealed = None;
+                    }
+                } else {
+                    ui.label("******");
+                    if ui.button("Reveal").clicked() {
+                        revealed = Some(i);
+                    }
+                }
+                if ui.button("Restore").clicked() {
+                    restore = Some(i);
+                }
+            });
+        }
+    });
+    cp.input_history.revealed = revealed;
+    if let Some(i) = restore {
+        let version = cp.input_history.versions[i].clone();
+        let old = cp.items[index].item.clone();
+        // the value restored over goes to the history like any other edit
+        let result = cp.with_key(|key| {
+            let new = history::restore(key, &old, &version)?;
+            history::save_change(&DB, key, &old, &new, &cp.settings, utils::now())?;
+            item::open_entry(key, &new)
+        });
+        match result {
+            Ok(entry) => {
+                cp.items[index] = entry;
+                cp.input_history.clear();
+                cp.input_history.tips = format!("{} restored from {}", history::label(&version), utils::format_date(version.replaced));
+            }
+            Err(e) => cp.input_history.tips = format!("restore failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use eframe::egui;
+use eframe::egui::{RichText, ScrollArea, Ui};
+
+use crate::{CPandas, import, save_new_items, State};
+use crate::constants::BASE_FONT_SIZE;
+use crate::otp::OtpKind;
+
+pub fn import_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_import.tips == "" {
+        cp.input_import.tips = "paste otpauth:// or otpauth-migration:// uris, or give QR code png files".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_import.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+
+    ui.label("URIs: ");
+    ui.add(egui::TextEdit::multiline(&mut cp.input_import.text_value).password(true).desired_rows(3));
+    ui.label("PNG Files: ");
+    ui.add(egui::TextEdit::multiline(&mut cp.input_import.path_value).desired_rows(2));
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.input_import.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Preview").clicked() {
+            preview(cp);
+        }
+        if !cp.input_import.preview.is_empty() && ui.button("Import").clicked() {
+            save(cp);
+        }
+    });
+    ui.separator();
+
+    ScrollArea::vertical().show(ui, |ui| {
+        for otp in &cp.input_import.preview {
+            let kind = match otp.kind {
+                OtpKind::Totp { period } => format!("totp {}s", period),
+                OtpKind::Hotp { counter } => format!("hotp #{}", counter),
+            };
+            ui.add_space(5.);
+            ui.label(RichText::new(&otp.label).size(BASE_FONT_SIZE));
+            ui.label(format!("{}  {}  {} digits", otp.issuer, kind, otp.digits));
+        }
+        ui.add_space(80.);
+    });
+}
+
+// parses everything given, nothing is saved yet
+fn preview(cp: &mut CPandas) {
+    let input = &mut cp.input_import;
+    let mut list = match import::parse_text(input.text_value.as_str()) {
+        Ok(list) => list,
+        Err(e) => {
+            input.tips = format!("parse uris failed: {}", e);
+            return;
+        }
+    };
+    for path in input.path_value.lines().map(|path| path.trim()).filter(|path| *path != "") {
+        match import::read_qr_png(Path::new(path)) {
+            Ok(found) => list.extend(found),
+            Err(e) => {
+                input.tips = format!("read {} failed: {}", path, e);
+                return;
+            }
+        }
+    }
+    input.tips = format!("{} codes found, check them and import", list.len());
+    input.preview = list;
+}
+
+fn save(cp: &mut CPandas) {
+    let result = cp.with_key(|key| import::otp_items(key, &cp.input_import.preview));
+    match result {
+        Ok(items) => {
+            // otp_items sealed them with the vault key just now
+            let count = save_new_items(cp, items);
+            cp.input_import.clear();
+            cp.input_import.tips = format!("{} codes imported", count);
+        }
+        Err(e) => cp.input_import.tips = format!("import failed: {}", e),
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::anyhow;
+use eframe::egui;
+use eframe::egui::{Label, Layout, RichText, ScrollArea, Ui};
+use zeroize::Zeroizing;
+
+use crate::{CPandas, DB, page, recovery, shamir, State, vault};
+use crate::constants::BASE_FONT_SIZE;
+use crate::egui::Align;
+use crate::shamir::Share;
+use crate::types::KeySlotKind;
+
+pub fn keys_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_key_slot.tips == "" {
+        cp.input_key_slot.tips = "any key slot can unlock the vault".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_key_slot.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+
+    ui.horizontal(|ui| {
+        ui.label("Label: ");
+        ui.text_edit_singleline(&mut cp.input_key_slot.label_value);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_key_slot.secret_value).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Or Keyfile Path: ");
+        ui.text_edit_singleline(&mut cp.input_key_slot.keyfile_value);
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.input_key_slot.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Add Key Slot").clicked() {
+            add_slot(cp);
+        }
+        if ui.button("New Recovery Key").clicked() {
+            page::recovery::new_recovery_key(cp);
+        }
+    });
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        ui.label("Test Password, Recovery Key Or Shares: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_key_slot.test_value).password(true));
+    });
+    ui.separator();
+
+    let slots = cp.input_key_slot.slots.clone();
+    ScrollArea::vertical().show(ui, |ui| {
+        for slot in &slots {
+            ui.horizontal(|ui| {
+                ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
+                    ui.add_space(10.);
+                    ui.add(Label::new(format!("Label: {}", &slot.label)));
+                    ui.add(Label::new(format!("Kind: {:?}", slot.kind)));
+                    ui.add(Label::new(format!("Time: {}", &slot.time)));
+                    ui.add_space(10.);
+                });
+                ui.with_layout(Layout::right_to_left(), |ui| {
+                    if cp.input_key_slot.revoking.as_ref() == Some(&slot.id) {
+                        if ui.button("No").clicked() {
+                            cp.input_key_slot.revoking = None;
+                        }
+                        if ui.button("Yes").clicked() {
+                            cp.input_key_slot.revoking = None;
+                            match vault::revoke_slot(&DB, &slot.id) {
+                                Ok(()) => {
+                                    load_slots(cp);
+                                    cp.input_key_slot.tips = format!("key slot {} revoked", &slot.label);
+                                }
+                                Err(e) => cp.input_key_slot.tips = format!("revoke failed: {}", e),
+                            }
+                        }
+                        ui.label(RichText::new("Revoke for good?").color(egui::Color32::RED));
+                    } else if ui.button("Revoke").clicked() {
+                        cp.input_key_slot.revoking = Some(slot.id.clone());
+                    }
+                    if ui.button("Test").clicked() {
+                        let result = read_test_secret(cp, slot.kind).and_then(|secret| vault::test_slot(&DB, &slot.id, &secret));
+                        cp.input_key_slot.tips = match result {
+                            Ok(true) => format!("key slot {} opens", &slot.label),
+                            Ok(false) => format!("key slot {} doesn`t open", &slot.label),
+                            Err(e) => format!("test failed: {}", e),
+                        };
+                    }
+                });
+            });
+        }
+        ui.add_space(80.);
+    });
+}
+
+// the slot list is read once here, not every frame
+pub fn load_slots(cp: &mut CPandas) {
+    match vault::list_slots(&DB) {
+        Ok(slots) => cp.input_key_slot.slots = slots,
+        Err(e) => {
+            log::error!("list key slots error: {}", e);
+            cp.input_key_slot.slots.clear();
+            cp.input_key_slot.tips = format!("load key slots failed: {}", e);
+        }
+    }
+}
+
+fn add_slot(cp: &mut CPandas) {
+    let input = &cp.input_key_slot;
+    if input.label_value == "" {
+        cp.input_key_slot.tips = "label can`t empty".to_string();
+        return;
+    }
+    let (kind, secret) = if input.keyfile_value != "" {
+        match std::fs::read(&input.keyfile_value) {
+            Ok(data) => (KeySlotKind::Keyfile, Zeroizing::new(data)),
+            Err(e) => {
+                cp.input_key_slot.tips = format!("read keyfile failed: {}", e);
+                return;
+            }
+        }
+    } else {
+        (KeySlotKind::Password, Zeroizing::new(input.secret_value.as_bytes().to_vec()))
+    };
+    let result = cp.with_key(|key| vault::add_slot(&DB, key, &input.label_value, kind, &secret));
+    match result {
+        Ok(slot) => {
+            load_slots(cp);
+            cp.input_key_slot.tips = format!("key slot {} added", slot.label);
+        }
+        Err(e) => cp.input_key_slot.tips = format!("add failed: {}", e),
+    }
+    let input = &mut cp.input_key_slot;
+    input.label_value = "".to_string();
+    input.secret_value.wipe();
+    input.keyfile_value = "".to_string();
+}
+
+// keyfile slots are tested with the file at the keyfile path
+fn read_test_secret(cp: &CPandas, kind: KeySlotKind) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    let secret = match kind {
+        KeySlotKind::Keyfile => std::fs::read(&cp.input_key_slot.keyfile_value).map_err(|e| anyhow!("read keyfile failed: {}", e))?,
+        KeySlotKind::Recovery => recovery::parse_key(cp.input_key_slot.test_value.as_str())?.to_vec(),
+        // the shares go in one line, apart by spaces
+        KeySlotKind::Shares => {
+            let shares = cp.input_key_slot.test_value.as_str().split_whitespace()
+                .map(Share::from_text)
+                .collect::<anyhow::Result<Vec<Share>>>()?;
+            shamir::combine(&shares)?.to_vec()
+        }
+        _ => cp.input_key_slot.test_value.as_bytes().to_vec(),
+    };
+    Ok(Zeroizing::new(secret))
+}
@@ -0,0 +1,136 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, item, utils};
+use crate::kind::{self, ItemKind};
+use crate::secret::SecretBuf;
+use crate::types::{FieldKind, InputItem};
+
+pub fn kind_combo(ui: &mut Ui, kind: &mut ItemKind) {
+    egui::ComboBox::from_id_source("item kind")
+        .selected_text(kind.label())
+        .show_ui(ui, |ui| {
+            for option in ItemKind::ALL {
+                ui.selectable_value(kind, option, option.label());
+            }
+        });
+}
+
+// the kind's own slots in the new and edit form
+pub fn slots_form(ui: &mut Ui, input: &mut InputItem) {
+    for slot in input.kind.slots() {
+        let value = input.details.entry(slot.name.to_string()).or_default();
+        ui.horizontal(|ui| {
+            ui.label(format!("Input {}: ", slot.label));
+            if slot.multiline {
+                ui.add(egui::TextEdit::multiline(value).desired_rows(3));
+            } else {
+                ui.add(egui::TextEdit::singleline(value).password(slot.kind.is_hidden()));
+            }
+        });
+    }
+}
+
+fn copy(text: &str) {
+    let mut ctx = ClipboardContext::new().unwrap();
+    ctx.set_contents(text.to_string()).unwrap();
+}
+
+// the secret the way its kind shows it, a card number masked and a private key summed up
+pub fn secret_detail(cp: &mut CPandas, ui: &mut Ui, kind: ItemKind, secret: &SecretBuf) {
+    let text = secret.as_str().unwrap_or("<not utf8>");
+    ui.horizontal(|ui| {
+        ui.label(format!("{}: ", kind.secret_label()));
+        match kind {
+            ItemKind::Card => {
+                let key = "secret".to_string();
+                let revealed = cp.field_revealed.as_ref() == Some(&key);
+                if revealed {
+                    ui.label(text);
+                } else {
+                    ui.label(format!("{} {}", kind::card_brand(text), kind::mask_card(text)));
+                }
+                if ui.small_button(if revealed { "Hide" } else { "Reveal" }).clicked() {
+                    cp.field_revealed = if revealed { None } else { Some(key) };
+                }
+            }
+            ItemKind::SshKey => {
+                match kind::parse_private_key(text) {
+                    Ok(info) => {
+                        let encrypted = if info.encrypted { ", passphrase protected" } else { "" };
+                        ui.label(format!("{} {}{}", info.key_type, info.fingerprint.unwrap_or_default(), encrypted));
+                    }
+                    Err(e) => {
+                        ui.label(format!("⚠ {}", e));
+                    }
+                }
+            }
+            _ => {
+                ui.label(text);
+            }
+        }
+        if kind != ItemKind::Login && ui.small_button("Copy").clicked() {
+            copy(text);
+        }
+    });
+}
+
+// the kind's slots in the detail view, concealed ones hidden until revealed
+pub fn details_view(cp: &mut CPandas, ui: &mut Ui, index: usize) {
+    let it = &cp.items[index].item;
+    let kind = it.kind;
+    if kind.slots().is_empty() {
+        return;
+    }
+    let details = match cp.with_key(|key| item::open_details(key, it)) {
+        Ok(details) => details,
+        Err(e) => {
+            log::error!("{}", e);
+            ui.label(RichText::new(format!("Details: ⚠ {}", e)).color(egui::Color32::RED));
+            return;
+        }
+    };
+    for slot in kind.slots() {
+        let value = match details.get(slot.name) {
+            Some(value) => value,
+            None => continue,
+        };
+        ui.horizontal(|ui| {
+            ui.label(format!("{}: ", slot.label));
+            let key = format!("slot:{}", slot.name);
+            let revealed = cp.field_revealed.as_ref() == Some(&key);
+            match slot.kind {
+                FieldKind::Concealed if !revealed => {
+                    ui.label("******");
+                }
+                FieldKind::Url => {
+                    ui.hyperlink(value);
+                }
+                _ => {
+                    ui.label(value);
+                }
+            }
+            if slot.name == "expiry" {
+                if let Ok(expiry) = kind::card_expiry(value) {
+                    if expiry <= utils::now() {
+                        ui.label(RichText::new("⚠ expired").color(egui::Color32::RED));
+                    }
+                }
+            }
+            if slot.name == "public_key" {
+                if let Ok(info) = kind::parse_public_key(value) {
+                    ui.label(info.fingerprint.unwrap_or_default());
+                }
+            }
+            if slot.kind == FieldKind::Concealed {
+                if ui.small_button(if revealed { "Hide" } else { "Reveal" }).clicked() {
+                    cp.field_revealed = if revealed { None } else { Some(key) };
+                }
+                if ui.small_button("Copy").clicked() {
+                    copy(value);
+                }
+            }
+        });
+    }
+}
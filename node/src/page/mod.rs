@@ -1 +1,17 @@
-mod address;
\ No newline at end of file
+mod address;
+pub mod attachments;
+pub mod audit;
+pub mod breaches;
+pub mod fields;
+pub mod generator;
+pub mod history;
+pub mod import;
+pub mod keys;
+pub mod organize;
+pub mod kinds;
+pub mod qr;
+pub mod recovery;
+pub mod settings;
+pub mod shares;
+pub mod strength;
+pub mod trash;
\ No newline at end of file
@@ -0,0 +1,69 @@
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, DB, organize, State};
+use crate::types::{Entry, ItemStatus};
+
+// looks the filter up in the index again
+pub fn apply_filter(cp: &mut CPandas) {
+    let filter = &cp.home_filter;
+    match cp.with_key(|key| organize::query(&DB, key, &filter.folder, &filter.tag, filter.favorites)) {
+        Ok(ids) => cp.home_filter.ids = ids,
+        Err(e) => log::error!("filter items failed: {}", e),
+    }
+    cp.home_filter.stale = false;
+}
+
+// folders and tags to filter the home list by, clicking a selected one again clears it
+pub fn sidebar(cp: &mut CPandas, ui: &mut Ui) {
+    let trashed = cp.items.iter().filter(|entry| entry.item.status == ItemStatus::Trashed).count();
+    // only folders and tags of the items the list can show
+    let status = if cp.home_filter.archived { ItemStatus::Archived } else { ItemStatus::Active };
+    let listed: Vec<&Entry> = cp.items.iter().filter(|entry| entry.item.status == status).collect();
+    let folders = organize::folder_tree(listed.iter().copied());
+    let tags = organize::tag_counts(listed.iter().copied());
+    let filter = &mut cp.home_filter;
+    let mut changed = false;
+    let all = filter.folder == "" && filter.tag == "" && !filter.favorites && !filter.archived;
+    if ui.selectable_label(all, "All Items").clicked() {
+        filter.clear();
+        changed = true;
+    }
+    if ui.selectable_label(filter.favorites, "★ Favorites").clicked() {
+        filter.favorites = !filter.favorites;
+        changed = true;
+    }
+    if ui.selectable_label(filter.archived, "Archive").clicked() {
+        filter.archived = !filter.archived;
+        changed = true;
+    }
+    let open_trash = ui.selectable_label(false, format!("🗑 Trash ({})", trashed)).clicked();
+    ui.add_space(10.);
+    ui.label(RichText::new("Folders").strong());
+    for folder in folders {
+        let depth = folder.matches('/').count();
+        let name = folder.rsplit('/').next().unwrap_or_default().to_string();
+        ui.horizontal(|ui| {
+            ui.add_space(depth as f32 * 12.);
+            if ui.selectable_label(filter.folder == folder, name).clicked() {
+                filter.folder = if filter.folder == folder { "".to_string() } else { folder.clone() };
+                changed = true;
+            }
+        });
+    }
+    ui.add_space(10.);
+    ui.label(RichText::new("Tags").strong());
+    ui.horizontal_wrapped(|ui| {
+        for (tag, count) in tags {
+            if ui.selectable_label(filter.tag == tag, format!("#{} ({})", tag, count)).clicked() {
+                filter.tag = if filter.tag == tag { "".to_string() } else { tag.clone() };
+                changed = true;
+            }
+        }
+    });
+    if changed {
+        apply_filter(cp);
+    }
+    if open_trash {
+        cp.state = State::Trash;
+    }
+}
@@ -0,0 +1,29 @@
+use eframe::egui::{Color32, Rect, Sense, Ui, vec2};
+use qrcode::{Color, QrCode};
+
+const MODULE_SIZE: f32 = 4.;
+// blank modules around the code, scanners need them
+const QUIET_ZONE: usize = 4;
+
+pub fn qr_code(ui: &mut Ui, data: &[u8]) {
+    let code = match QrCode::new(data) {
+        Ok(code) => code,
+        Err(e) => {
+            ui.label(format!("can`t render QR code: {}", e));
+            return;
+        }
+    };
+    let width = code.width();
+    let side = (width + QUIET_ZONE * 2) as f32 * MODULE_SIZE;
+    let (rect, _) = ui.allocate_exact_size(vec2(side, side), Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0., Color32::WHITE);
+    for (i, color) in code.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            let x = (i % width + QUIET_ZONE) as f32 * MODULE_SIZE;
+            let y = (i / width + QUIET_ZONE) as f32 * MODULE_SIZE;
+            let module = Rect::from_min_size(rect.min + vec2(x, y), vec2(MODULE_SIZE, MODULE_SIZE));
+            painter.rect_filled(module, 0., Color32::BLACK);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+use zeroize::Zeroizing;
+
+use crate::{CPandas, DB, recovery, State, utils, vault, weak_master_password};
+use crate::constants::BASE_FONT_SIZE;
+use crate::page::{keys, qr};
+
+// replaces the vault's recovery key and shows the new one
+pub fn new_recovery_key(cp: &mut CPandas) {
+    match cp.with_key(|key| recovery::format_key(&vault::new_recovery_key(&DB, key)?)) {
+        Ok(key) => {
+            keys::load_slots(cp);
+            cp.recovery_key = Some(key);
+            cp.input_recovery.tips = "write the recovery key down or save the emergency sheet, it is shown only once".to_string();
+            cp.state = State::Recovery;
+        }
+        Err(e) => {
+            log::error!("recovery key error: {}", e);
+            cp.recovery_key = None;
+            cp.input_recovery.tips = format!("recovery key failed: {}", e);
+            cp.state = State::Recovery;
+        }
+    }
+}
+
+pub fn recovery_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    ui.label(RichText::new(format!("tips: {}", &cp.input_recovery.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+    let key_text = match &cp.recovery_key {
+        Some(key) => Zeroizing::new(key.as_str().unwrap_or_default().to_string()),
+        None => Zeroizing::new("".to_string()),
+    };
+    ui.label(RichText::new(key_text.as_str()).monospace().size(BASE_FONT_SIZE));
+    qr::qr_code(ui, key_text.as_bytes());
+    ui.add_space(10.);
+    let has_key = cp.recovery_key.is_some();
+    if has_key {
+        ui.label(RichText::new("the sheet holds the recovery key in plain text, save it somewhere only you can read").color(egui::Color32::RED));
+        ui.horizontal(|ui| {
+            ui.label("Sheet Path: ");
+            ui.add(egui::TextEdit::singleline(&mut cp.input_recovery.path_value).hint_text("e.g. a usb stick, without extension"));
+        });
+    }
+    ui.horizontal(|ui| {
+        if ui.button("Done").clicked() {
+            cp.recovery_key = None;
+            cp.input_recovery.clear();
+            cp.state = State::Home;
+        }
+        if has_key && ui.button("Save Emergency Sheet").clicked() {
+            let path = cp.input_recovery.path_value.trim().to_string();
+            cp.input_recovery.tips = if path == "" {
+                "sheet path can`t empty".to_string()
+            } else {
+                match save_sheet(&path, &key_text) {
+                    Ok(()) => format!("saved {0}.txt and {0}.svg, print them and delete the files", path),
+                    Err(e) => format!("save failed: {}", e),
+                }
+            };
+        }
+    });
+}
+
+// the files are only readable by the owner and never overwrite anything
+fn save_sheet(path: &str, key_text: &str) -> anyhow::Result<()> {
+    let vault_id = vault::vault_id(&DB)?;
+    utils::write_private(Path::new(&format!("{}.txt", path)), recovery::emergency_sheet_text(&vault_id, key_text).as_bytes())?;
+    utils::write_private(Path::new(&format!("{}.svg", path)), recovery::emergency_sheet_svg(&vault_id, key_text)?.as_bytes())?;
+    Ok(())
+}
+
+// after unlocking with the recovery key there is no way on but a new master password
+pub fn reset_password_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_password.tips == "" {
+        cp.input_password.tips = "unlocked with the recovery key, choose a new master password".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_password.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(5.);
+    ui.horizontal(|ui| {
+        ui.label("New Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_password.new_value).password(true));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Confirm Password: ");
+        ui.add(egui::TextEdit::singleline(&mut cp.input_password.confirm_value).password(true));
+    });
+    ui.add_space(10.);
+    if ui.button("Submit").clicked() {
+        let input = &cp.input_password;
+        if input.new_value.as_str() == "" {
+            cp.input_password.tips = "new password can`t empty".to_string();
+            return;
+        }
+        if input.new_value.as_str() != input.confirm_value.as_str() {
+            cp.input_password.tips = "new passwords not match".to_string();
+            return;
+        }
+        if let Some(tips) = weak_master_password(cp, input.new_value.as_str()) {
+            cp.input_password.tips = tips;
+            return;
+        }
+        match cp.with_key(|key| vault::reset_master_password(&DB, key, input.new_value.as_bytes())) {
+            Ok(()) => {
+                cp.input_password.clear();
+                cp.state = State::Home;
+            }
+            Err(e) => {
+                log::error!("reset password error: {}", e);
+                cp.input_password.tips = format!("reset password failed: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, DB, hibp, State};
+use crate::constants::BASE_FONT_SIZE;
+use crate::strength::MAX_SCORE;
+
+/// A HIBP index built by a worker thread, the text download takes minutes to read.
+pub struct IndexBuild {
+    index_path: String,
+    // bytes of the text file read so far
+    read: Arc<AtomicU64>,
+    total: u64,
+    done: Receiver<anyhow::Result<u64>>,
+}
+
+fn start_index_build(text_path: &str) -> anyhow::Result<IndexBuild> {
+    let total = std::fs::metadata(text_path)?.len();
+    let index_path = format!("{}.idx", text_path);
+    let read = Arc::new(AtomicU64::new(0));
+    let (sender, done) = mpsc::channel();
+    let (text, index, progress) = (text_path.to_string(), index_path.clone(), read.clone());
+    thread::spawn(move || {
+        let result = hibp::build_index(&text, &index, |bytes| progress.store(bytes, Ordering::Relaxed));
+        let _ = sender.send(result);
+    });
+    Ok(IndexBuild { index_path, read, total, done })
+}
+
+// progress of a running build, its result once it is done
+fn index_build_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    let build = match &cp.index_build {
+        Some(build) => build,
+        None => return,
+    };
+    match build.done.try_recv() {
+        Ok(Ok(records)) => {
+            cp.settings.hibp_path = build.index_path.clone();
+            cp.settings_tips = format!("index of {} hashes built, save to use it", records);
+            cp.index_build = None;
+        }
+        Ok(Err(e)) => {
+            cp.settings_tips = format!("build index failed: {}", e);
+            cp.index_build = None;
+        }
+        Err(TryRecvError::Disconnected) => {
+            cp.settings_tips = "build index stopped".to_string();
+            cp.index_build = None;
+        }
+        Err(TryRecvError::Empty) => {
+            let read = build.read.load(Ordering::Relaxed);
+            ui.add(egui::ProgressBar::new(read as f32 / build.total.max(1) as f32).show_percentage());
+            // the worker doesn't wake the ui, so keep polling it
+            ctx.request_repaint();
+        }
+    }
+}
+
+pub fn settings_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.settings_tips == "" {
+        cp.settings_tips = "settings are kept in this vault".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.settings_tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        ui.label("Minimum master password score: ");
+        ui.add(egui::Slider::new(&mut cp.settings.min_password_score, 0..=MAX_SCORE));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Rotate secrets after days: ");
+        ui.add(egui::DragValue::new(&mut cp.settings.rotation_days).clamp_range(0..=3650));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Keep old values per field: ");
+        ui.add(egui::DragValue::new(&mut cp.settings.history_count).clamp_range(0..=100));
+        ui.label("for days (0 forever): ");
+        ui.add(egui::DragValue::new(&mut cp.settings.history_days).clamp_range(0..=3650));
+    });
+    ui.horizontal(|ui| {
+        ui.label("Purge trashed items after days (0 never): ");
+        ui.add(egui::DragValue::new(&mut cp.settings.trash_days).clamp_range(0..=3650));
+    });
+    ui.horizontal(|ui| {
+        ui.label("HIBP File: ");
+        ui.text_edit_singleline(&mut cp.settings.hibp_path);
+        // the text download is ~35GB, the index about half of it and faster to search
+        let building = cp.index_build.is_some();
+        if cp.settings.hibp_path != "" && !building && ui.button("Build Index").clicked() {
+            match start_index_build(&cp.settings.hibp_path) {
+                Ok(build) => {
+                    cp.index_build = Some(build);
+                    cp.settings_tips = "building the index, it takes a few minutes".to_string();
+                }
+                Err(e) => cp.settings_tips = format!("build index failed: {}", e),
+            }
+        }
+    });
+    index_build_view(cp, ctx, ui);
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            // drop unsaved changes, the page stays open when they can't be read back
+            match DB.get_settings() {
+                Ok(settings) => {
+                    cp.settings = settings.unwrap_or_default();
+                    cp.settings_tips = "".to_string();
+                    cp.state = State::Home;
+                }
+                Err(e) => cp.settings_tips = format!("read settings failed: {}", e),
+            }
+        }
+        if ui.button("Save").clicked() {
+            match DB.put_settings(&cp.settings) {
+                Ok(()) => {
+                    cp.open_breach_list();
+                    cp.settings_tips = "settings saved".to_string();
+                }
+                Err(e) => cp.settings_tips = format!("save settings failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,77 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+use eframe::egui;
+use eframe::egui::{RichText, ScrollArea, Ui};
+use zeroize::Zeroize;
+
+use crate::{CPandas, DB, State, vault};
+use crate::constants::BASE_FONT_SIZE;
+use crate::page::qr;
+use crate::secret::SecretString;
+
+pub fn shares_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_shares.tips == "" {
+        cp.input_shares.tips = "split a key to the vault, any threshold of the shares unlock it until the next split or a revoke".to_string();
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_shares.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+
+    ui.horizontal(|ui| {
+        ui.label("Threshold: ");
+        ui.text_edit_singleline(&mut cp.input_shares.threshold_value);
+    });
+    ui.horizontal(|ui| {
+        ui.label("Shares: ");
+        ui.text_edit_singleline(&mut cp.input_shares.count_value);
+    });
+    ui.horizontal(|ui| {
+        if ui.button("Close").clicked() {
+            cp.input_shares.clear();
+            cp.state = State::Home;
+        }
+        if ui.button("Split").clicked() {
+            split_key(cp);
+        }
+    });
+    ui.separator();
+
+    ScrollArea::vertical().show(ui, |ui| {
+        for text in &cp.input_shares.shares {
+            ui.add_space(10.);
+            ui.label(RichText::new(text.as_str()).monospace());
+            ui.horizontal(|ui| {
+                qr::qr_code(ui, text.as_bytes());
+                if ui.button("Copy").clicked() {
+                    let mut ctx = ClipboardContext::new().unwrap();
+                    ctx.set_contents(text.as_str().to_string()).unwrap();
+                }
+            });
+        }
+        ui.add_space(80.);
+    });
+}
+
+fn split_key(cp: &mut CPandas) {
+    let input = &cp.input_shares;
+    let (threshold, count) = match (input.threshold_value.trim().parse::<u8>(), input.count_value.trim().parse::<u8>()) {
+        (Ok(threshold), Ok(count)) => (threshold, count),
+        _ => {
+            cp.input_shares.tips = "threshold and shares must be numbers up to 255".to_string();
+            return;
+        }
+    };
+    let result = cp.with_key(|key| vault::split_key(&DB, key, threshold, count));
+    let input = &mut cp.input_shares;
+    match result {
+        Ok(shares) => {
+            input.shares = shares.iter().map(|share| {
+                let mut text = share.to_text();
+                let secret = SecretString::from(text.as_str());
+                text.zeroize();
+                secret
+            }).collect();
+            input.tips = format!("hand each share to a different person, any {} of them unlock the vault", threshold);
+        }
+        Err(e) => input.tips = format!("split failed: {}", e),
+    }
+}
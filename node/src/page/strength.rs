@@ -0,0 +1,55 @@
+use eframe::egui;
+use eframe::egui::{Color32, RichText, Ui};
+
+use crate::secret::SecretBuf;
+use crate::strength::{self, Hashing, MAX_SCORE, Strength};
+
+const SCORE_NAMES: [&str; 5] = ["too guessable", "very guessable", "somewhat guessable", "safely unguessable", "very unguessable"];
+
+/// The estimate of the last input, worked out again only when the input changes
+/// rather than on every frame.
+#[derive(Default)]
+pub struct StrengthCache {
+    // the password is None when no secret memory was left for it, so it is never a hit
+    last: Option<(Option<SecretBuf>, Vec<String>, Hashing, Strength)>,
+}
+
+impl StrengthCache {
+    pub fn estimate(&mut self, password: &str, user_inputs: &[&str], hashing: Hashing) -> &Strength {
+        let same = match &self.last {
+            Some((Some(last), inputs, last_hashing, _)) => last.as_bytes() == password.as_bytes()
+                && inputs.iter().map(String::as_str).eq(user_inputs.iter().copied())
+                && *last_hashing == hashing,
+            _ => false,
+        };
+        if !same {
+            let inputs = user_inputs.iter().map(|input| input.to_string()).collect();
+            self.last = Some((SecretBuf::from_slice(password.as_bytes()).ok(), inputs, hashing, strength::estimate(password, user_inputs, hashing)));
+        }
+        &self.last.as_ref().unwrap().3
+    }
+
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
+// a meter for the score with the estimated crack time and what to improve
+pub fn strength_meter(ui: &mut Ui, strength: &Strength) {
+    let color = match strength.score {
+        0 | 1 => Color32::RED,
+        2 => Color32::YELLOW,
+        _ => Color32::GREEN,
+    };
+    ui.horizontal(|ui| {
+        ui.add(egui::ProgressBar::new((strength.score + 1) as f32 / (MAX_SCORE + 1) as f32).desired_width(120.));
+        ui.label(RichText::new(SCORE_NAMES[strength.score as usize]).color(color));
+        ui.label(format!("cracked in {}", strength.crack_time()));
+    });
+    if strength.warning != "" {
+        ui.label(RichText::new(&strength.warning).color(color));
+    }
+    for suggestion in &strength.suggestions {
+        ui.label(format!("· {}", suggestion));
+    }
+}
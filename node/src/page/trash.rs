@@ -0,0 +1,130 @@
+use eframe::egui;
+use eframe::egui::{RichText, Ui};
+
+use crate::{CPandas, DB, item, page, reindex, State, utils};
+use crate::constants::BASE_FONT_SIZE;
+use crate::types::ItemStatus;
+
+// confirm value of Empty Trash
+pub const TRASH_ALL: &str = "*";
+
+/// Deletes the items for good, with their history, index entries and the chunks of
+/// attachments no other item shares.
+pub fn purge(cp: &mut CPandas, ids: &[String]) -> usize {
+    let mut count = 0;
+    let mut attachments = false;
+    for id in ids {
+        let index = match cp.items.iter().position(|entry| &entry.item.id == id) {
+            Some(index) => index,
+            None => continue,
+        };
+        if let Err(e) = DB.del_item(id) {
+            log::error!("purge {} failed: {}", id, e);
+            cp.input_trash.tips = format!("purge failed: {}", e);
+            continue;
+        }
+        let old = cp.items.remove(index);
+        reindex(cp, id, Some(&old), None);
+        attachments |= !old.item.attachments.is_empty();
+        count += 1;
+    }
+    if attachments {
+        page::attachments::collect_garbage(cp);
+    }
+    count
+}
+
+// purges what has been in the trash longer than the settings keep it, run on unlock
+pub fn purge_expired(cp: &mut CPandas) {
+    let now = utils::now();
+    let ids: Vec<String> = cp.items.iter()
+        .filter(|entry| item::purge_due(&entry.item, cp.settings.trash_days, now))
+        .map(|entry| entry.item.id.clone())
+        .collect();
+    if !ids.is_empty() {
+        log::info!("{} trashed items purged", purge(cp, &ids));
+    }
+}
+
+fn restore(cp: &mut CPandas, index: usize) {
+    let mut it = cp.items[index].item.clone();
+    item::restore(&mut it, utils::now());
+    match DB.put_item(&it) {
+        Ok(()) => {
+            cp.items[index].item = it;
+            cp.home_filter.stale = true;
+        }
+        Err(e) => cp.input_trash.tips = format!("restore failed: {}", e),
+    }
+}
+
+pub fn trash_view(cp: &mut CPandas, ctx: &egui::Context, ui: &mut Ui) {
+    ui.add_space(10.);
+    if cp.input_trash.tips == "" {
+        cp.input_trash.tips = match cp.settings.trash_days {
+            0 => "trashed items are kept until purged".to_string(),
+            days => format!("trashed items are purged after {} days", days),
+        };
+    }
+    ui.label(RichText::new(format!("tips: {}", &cp.input_trash.tips)).size(BASE_FONT_SIZE));
+    ui.add_space(10.);
+    let trashed: Vec<usize> = (0..cp.items.len())
+        .filter(|index| cp.items[*index].item.status == ItemStatus::Trashed)
+        .collect();
+    let mut restored = None;
+    let mut purged = None;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for index in &trashed {
+            let entry = &cp.items[*index];
+            ui.horizontal(|ui| {
+                ui.label(format!("{}: {}", entry.item.kind.label(), &entry.account));
+                ui.label(format!("trashed {}", utils::format_date(entry.item.trashed)));
+                if cp.input_trash.confirm.as_ref() == Some(&entry.item.id) {
+                    ui.label(RichText::new("Delete forever?").color(egui::Color32::RED));
+                    if ui.button("Yes").clicked() {
+                        purged = Some(vec![entry.item.id.clone()]);
+                    }
+                    if ui.button("No").clicked() {
+                        cp.input_trash.confirm = None;
+                    }
+                } else {
+                    if ui.button("Restore").clicked() {
+                        restored = Some(*index);
+                    }
+                    if ui.button("Delete Forever").clicked() {
+                        cp.input_trash.confirm = Some(entry.item.id.clone());
+                    }
+                }
+            });
+        }
+    });
+    if trashed.is_empty() {
+        ui.label("trash is empty");
+    }
+    ui.add_space(10.);
+    ui.horizontal(|ui| {
+        if cp.input_trash.confirm.as_deref() == Some(TRASH_ALL) {
+            ui.label(RichText::new(format!("Delete {} items forever?", trashed.len())).color(egui::Color32::RED));
+            if ui.button("Yes").clicked() {
+                purged = Some(trashed.iter().map(|index| cp.items[*index].item.id.clone()).collect());
+            }
+            if ui.button("No").clicked() {
+                cp.input_trash.confirm = None;
+            }
+        } else if !trashed.is_empty() && ui.button("Empty Trash").clicked() {
+            cp.input_trash.confirm = Some(TRASH_ALL.to_string());
+        }
+        if ui.button("Close").clicked() {
+            cp.input_trash.clear();
+            cp.state = State::Home;
+        }
+    });
+    if let Some(index) = restored {
+        restore(cp, index);
+    }
+    if let Some(ids) = purged {
+        let count = purge(cp, &ids);
+        cp.input_trash.confirm = None;
+        cp.input_trash.tips = format!("{} items deleted forever", count);
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{anyhow, bail, Result};
+use base32::Alphabet;
+use qrcode::QrCode;
+use qrcode::render::svg;
+use rand::Rng;
+
+use super::secret::SecretBuf;
+
+// 160 bits, 32 base32 chars
+const RECOVERY_KEY_NUM: usize = 20;
+const GROUP_NUM: usize = 4;
+const ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+pub fn new_recovery_key() -> Result<SecretBuf> {
+    let mut key = SecretBuf::new(RECOVERY_KEY_NUM)?;
+    rand::thread_rng().fill(key.as_mut_bytes());
+    Ok(key)
+}
+
+// base32 in groups of four, e.g. ABCD-EFGH-...
+pub fn format_key(key: &[u8]) -> Result<SecretBuf> {
+    let mut encoded = base32::encode(ALPHABET, key).into_bytes();
+    let groups: Vec<&[u8]> = encoded.chunks(GROUP_NUM).collect();
+    let grouped = SecretBuf::from_vec(groups.join(&b'-'));
+    zeroize::Zeroize::zeroize(&mut encoded);
+    grouped
+}
+
+// case, spaces and dashes don't matter
+pub fn parse_key(text: &str) -> Result<SecretBuf> {
+    let normalized: String = text.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let key = SecretBuf::from_vec(normalized.into_bytes())?;
+    let decoded = base32::decode(ALPHABET, key.as_str()?).ok_or_else(|| anyhow!("recovery key is not valid base32"))?;
+    let decoded = SecretBuf::from_vec(decoded)?;
+    if decoded.len() != RECOVERY_KEY_NUM {
+        bail!("recovery key must be {} characters", RECOVERY_KEY_NUM * 8 / 5);
+    }
+    Ok(decoded)
+}
+
+pub fn emergency_sheet_text(vault_id: &str, key_text: &str) -> String {
+    format!(
+        "CPandas Emergency Sheet\n\
+         =======================\n\n\
+         Vault: {}\n\
+         Created: {}\n\n\
+         Recovery Key:\n\n    {}\n\n\
+         Anyone holding this key can open the vault, keep the sheet offline and safe.\n\
+         To recover, enter the key under \"Or Recovery Key\" on the unlock screen,\n\
+         then choose a new master password.\n",
+        vault_id, chrono::Local::now().format("%Y-%m-%d"), key_text
+    )
+}
+
+// an A4 page with the same text and the key as a QR code
+pub fn emergency_sheet_svg(vault_id: &str, key_text: &str) -> Result<String> {
+    let code = QrCode::new(key_text.as_bytes()).map_err(|e| anyhow!("{}",e))?;
+    let qr = code.render::<svg::Color>().min_dimensions(200, 200).build();
+    let qr = match qr.find("<svg ") {
+        Some(start) => qr[start..].replacen("<svg ", r#"<svg x="60" y="330" "#, 1),
+        None => bail!("unexpected QR svg"),
+    };
+    let lines = [
+        (60, 80, 28, "CPandas Emergency Sheet".to_string()),
+        (60, 130, 14, format!("Vault: {}", vault_id)),
+        (60, 155, 14, format!("Created: {}", chrono::Local::now().format("%Y-%m-%d"))),
+        (60, 210, 14, "Recovery Key:".to_string()),
+        (60, 250, 20, key_text.to_string()),
+        (60, 600, 12, "Anyone holding this key can open the vault, keep the sheet offline and safe.".to_string()),
+        (60, 620, 12, "To recover, enter the key under \"Or Recovery Key\" on the unlock screen,".to_string()),
+        (60, 640, 12, "then choose a new master password.".to_string()),
+    ];
+    let mut sheet = String::from(r#"<?xml version="1.0" standalone="yes"?>"#);
+    sheet.push_str(r#"<svg xmlns="http://www.w3.org/2000/svg" version="1.1" width="595" height="842" viewBox="0 0 595 842">"#);
+    sheet.push_str(r##"<rect x="0" y="0" width="595" height="842" fill="#fff"/>"##);
+    for (x, y, size, text) in lines {
+        sheet.push_str(&format!(r#"<text x="{}" y="{}" font-family="monospace" font-size="{}">{}</text>"#,
+                                x, y, size, escape_xml(&text)));
+    }
+    sheet.push_str(&qr);
+    sheet.push_str("</svg>");
+    Ok(sheet)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_and_parse() {
+        let key = new_recovery_key().unwrap();
+        let text = format_key(&key).unwrap();
+        let text = text.as_str().unwrap();
+        assert_eq!(text.len(), 39);
+        assert_eq!(text.split('-').count(), 8);
+        assert_eq!(parse_key(text).unwrap(), key);
+        assert_eq!(parse_key(&format!(" {} ", text.to_lowercase().replace('-', " "))).unwrap(), key);
+
+        assert!(parse_key("ABCD-EFGH").is_err());
+        assert!(parse_key(&text.replace(&text[..1], "1")).is_err());
+        assert_ne!(new_recovery_key().unwrap(), key);
+    }
+
+    #[test]
+    fn emergency_sheet() {
+        let key = format_key(&new_recovery_key().unwrap()).unwrap();
+        let key = key.as_str().unwrap();
+        let text = emergency_sheet_text("3f0c", key);
+        assert!(text.contains("Vault: 3f0c"));
+        assert!(text.contains(key));
+
+        let sheet = emergency_sheet_svg("3f0c", key).unwrap();
+        assert!(sheet.starts_with("<?xml"));
+        assert!(sheet.ends_with("</svg></svg>"));
+        assert_eq!(sheet.matches("<?xml").count(), 1);
+        assert!(sheet.contains(key));
+        assert!(sheet.contains(r#"&quot;Or Recovery Key&quot;"#));
+    }
+}
@@ -0,0 +1,311 @@
+use std::alloc::Layout;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, Range};
+use std::ptr::NonNull;
+
+use anyhow::{anyhow, Result};
+use eframe::egui::TextBuffer;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use super::utils;
+
+// enough for any password or passphrase typed into a secret field
+const SECRET_STRING_CAPACITY: usize = 1024;
+
+/// Decrypted plaintext or key bytes. The memory is locked so it is never swapped out,
+/// sits between two no-access guard pages and is zeroed before it is freed.
+pub struct SecretBuf {
+    ptr: NonNull<[u8]>,
+}
+
+// the buffer is owned like a Box<[u8]>
+unsafe impl Send for SecretBuf {}
+
+unsafe impl Sync for SecretBuf {}
+
+impl SecretBuf {
+    // zero filled, fails when the guarded memory can't be mapped
+    pub fn new(len: usize) -> Result<Self> {
+        let ptr = unsafe { memsec::malloc_sized(len) }.ok_or_else(|| anyhow!("allocate {} bytes of secret memory failed", len))?;
+        let mut buf = SecretBuf { ptr };
+        buf.as_mut_bytes().fill(0);
+        Ok(buf)
+    }
+
+    pub fn from_slice(data: &[u8]) -> Result<Self> {
+        let mut buf = SecretBuf::new(data.len())?;
+        buf.as_mut_bytes().copy_from_slice(data);
+        Ok(buf)
+    }
+
+    // moves the bytes in and wipes the vec, also when it fails
+    pub fn from_vec(mut data: Vec<u8>) -> Result<Self> {
+        let buf = SecretBuf::from_slice(&data);
+        data.zeroize();
+        buf
+    }
+
+    // for Clone and the edit buffers, which have no way to return an error: running
+    // out of memory aborts the same as it does for a Vec
+    fn new_or_abort(len: usize) -> Self {
+        SecretBuf::new(len).unwrap_or_else(|_| std::alloc::handle_alloc_error(Layout::array::<u8>(len).unwrap_or(Layout::new::<u8>())))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        unsafe { self.ptr.as_mut() }
+    }
+
+    pub fn as_str(&self) -> Result<&str> {
+        std::str::from_utf8(self.as_bytes()).map_err(|e| anyhow!("{}",e))
+    }
+}
+
+impl Deref for SecretBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl AsRef<[u8]> for SecretBuf {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Clone for SecretBuf {
+    fn clone(&self) -> Self {
+        let mut buf = SecretBuf::new_or_abort(self.len());
+        buf.as_mut_bytes().copy_from_slice(self.as_bytes());
+        buf
+    }
+}
+
+impl PartialEq for SecretBuf {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes().ct_eq(other.as_bytes()).into()
+    }
+}
+
+impl Drop for SecretBuf {
+    fn drop(&mut self) {
+        let bytes = self.as_mut_bytes();
+        unsafe {
+            memsec::memzero(bytes.as_mut_ptr(), bytes.len());
+            memsec::free(self.ptr);
+        }
+    }
+}
+
+impl Debug for SecretBuf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretBuf(<redacted>)")
+    }
+}
+
+/// A text edit buffer for secrets. It is a fixed size SecretBuf, so typing never
+/// reallocates and leaves copies of the text behind in the heap.
+#[derive(Clone)]
+pub struct SecretString {
+    buf: SecretBuf,
+    len: usize,
+}
+
+impl SecretString {
+    pub fn new() -> Self {
+        SecretString::with_capacity(SECRET_STRING_CAPACITY)
+    }
+
+    // for pasted text longer than a password, e.g. a list of uris
+    pub fn with_capacity(capacity: usize) -> Self {
+        SecretString { buf: SecretBuf::new_or_abort(capacity), len: 0 }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole chars are ever inserted or deleted
+        std::str::from_utf8(self.as_bytes()).expect("secret string is utf8")
+    }
+
+    pub fn wipe(&mut self) {
+        self.buf.as_mut_bytes()[..self.len].fill(0);
+        self.len = 0;
+    }
+}
+
+impl SecretString {
+    // the whole text or an error, never a cut copy
+    pub fn try_from_str(text: &str) -> Result<Self> {
+        if text.len() > SECRET_STRING_CAPACITY {
+            return Err(anyhow!("longer than {} bytes", SECRET_STRING_CAPACITY));
+        }
+        Ok(SecretString::from(text))
+    }
+
+    pub fn from_secret(secret: &SecretBuf) -> Result<Self> {
+        SecretString::try_from_str(secret.as_str().map_err(|_| anyhow!("not utf8 text"))?)
+    }
+}
+
+impl Default for SecretString {
+    fn default() -> Self {
+        SecretString::new()
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(text: &str) -> Self {
+        let mut secret = SecretString::new();
+        secret.insert_text(text, 0);
+        secret
+    }
+}
+
+impl AsRef<str> for SecretString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl TextBuffer for SecretString {
+    fn is_mutable(&self) -> bool {
+        true
+    }
+
+    // chars that don't fit in the buffer are dropped
+    fn insert_text(&mut self, text: &str, char_index: usize) -> usize {
+        let at = self.byte_index_from_char_index(char_index);
+        let mut size = 0;
+        let mut count = 0;
+        for c in text.chars() {
+            if self.len + size + c.len_utf8() > self.buf.len() {
+                break;
+            }
+            size += c.len_utf8();
+            count += 1;
+        }
+        let len = self.len;
+        let bytes = self.buf.as_mut_bytes();
+        bytes.copy_within(at..len, at + size);
+        bytes[at..at + size].copy_from_slice(&text.as_bytes()[..size]);
+        self.len += size;
+        count
+    }
+
+    fn delete_char_range(&mut self, char_range: Range<usize>) {
+        assert!(char_range.start <= char_range.end);
+        let start = self.byte_index_from_char_index(char_range.start);
+        let end = self.byte_index_from_char_index(char_range.end);
+        let len = self.len;
+        let bytes = self.buf.as_mut_bytes();
+        bytes.copy_within(end..len, start);
+        bytes[len - (end - start)..len].fill(0);
+        self.len -= end - start;
+    }
+
+    fn clear(&mut self) {
+        self.wipe();
+    }
+}
+
+impl Debug for SecretString {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretString(<redacted>)")
+    }
+}
+
+/// The unlocked vault key. It is kept sealed under a random per-session key and only
+/// opened for the length of `with_key`, both are wiped when the session is dropped.
+pub struct SessionKey {
+    shield: SecretBuf,
+    sealed: Vec<u8>,
+}
+
+impl SessionKey {
+    pub fn new(key: SecretBuf) -> Result<Self> {
+        let shield = utils::aes256_key()?;
+        let sealed = utils::aes256_seal(&key, &shield)?;
+        Ok(SessionKey { shield, sealed })
+    }
+
+    pub fn with_key<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R> {
+        let key = utils::aes256_open(&self.sealed, &self.shield)?;
+        Ok(f(&key))
+    }
+}
+
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        self.sealed.zeroize();
+    }
+}
+
+impl Debug for SessionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionKey(<redacted>)")
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn open_session_key() {
+        let key = utils::aes256_key().unwrap();
+        let session = SessionKey::new(key.clone()).unwrap();
+        assert!(!session.sealed.windows(key.len()).any(|w| w == &key[..]));
+        assert_eq!(session.with_key(|k| k.to_vec()).unwrap(), key.to_vec());
+        assert_eq!(format!("{:?}", session), "SessionKey(<redacted>)");
+    }
+
+    #[test]
+    fn secret_buf() {
+        let buf = SecretBuf::from_vec(b"hunter2".to_vec()).unwrap();
+        assert_eq!(buf.as_bytes(), b"hunter2");
+        assert_eq!(buf.as_str().unwrap(), "hunter2");
+        assert_eq!(buf.clone(), buf);
+        assert_ne!(SecretBuf::from_slice(b"hunter3").unwrap(), buf);
+        assert_eq!(format!("{:?}", buf), "SecretBuf(<redacted>)");
+        assert!(!format!("{:?}", buf).contains("hunter2"));
+        assert_eq!(SecretBuf::new(4).unwrap().as_bytes(), &[0; 4]);
+        assert!(SecretBuf::new(0).unwrap().is_empty());
+        assert!(SecretBuf::new(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn secret_string_edit() {
+        let mut secret = SecretString::from("hunter2");
+        secret.insert_text("密码", 6);
+        assert_eq!(secret.as_str(), "hunter密码2");
+        secret.delete_char_range(1..7);
+        assert_eq!(secret.as_str(), "h码2");
+        secret.replace("abc");
+        assert_eq!(secret.as_str(), "abc");
+        assert_eq!(format!("{:?}", secret), "SecretString(<redacted>)");
+
+        let long = "x".repeat(SECRET_STRING_CAPACITY + 10);
+        assert!(SecretString::try_from_str(&long).is_err());
+        assert!(SecretString::from_secret(&SecretBuf::from_slice(&[0xff, 0xfe]).unwrap()).is_err());
+        assert_eq!(SecretString::from_secret(&SecretBuf::from_slice(b"hunter2").unwrap()).unwrap().as_str(), "hunter2");
+        assert_eq!(secret.insert_text(&long, 0), SECRET_STRING_CAPACITY - 3);
+        assert_eq!(secret.as_bytes().len(), SECRET_STRING_CAPACITY);
+        secret.clear();
+        assert!(secret.as_bytes().is_empty());
+        assert!(secret.buf.iter().all(|b| *b == 0));
+
+        let mut wide = SecretString::with_capacity(SECRET_STRING_CAPACITY * 4);
+        assert_eq!(wide.insert_text(&long, 0), long.len());
+        assert_eq!(wide.as_str(), long);
+    }
+}
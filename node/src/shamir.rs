@@ -0,0 +1,211 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::{bail, Result};
+use rand::Rng;
+
+use super::secret::SecretBuf;
+use super::utils;
+
+const SHARE_PREFIX: &str = "CPSHARE1";
+const CHECKSUM_NUM: usize = 4;
+
+/// One share of a secret split with Shamir's scheme over GF(256). `index` is the x
+/// coordinate, `data` holds one y value per secret byte.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Share {
+    pub vault_id: String,
+    pub threshold: u8,
+    pub index: u8,
+    pub data: SecretBuf,
+}
+
+impl Share {
+    // CPSHARE1:vault id:threshold:index:data hex:checksum, the checksum catches typos
+    pub fn to_text(&self) -> String {
+        let body = format!("{}:{}:{}:{}:{}", SHARE_PREFIX, self.vault_id, self.threshold, self.index, hex::encode(&*self.data));
+        let checksum = checksum(&body);
+        format!("{}:{}", body, checksum)
+    }
+
+    pub fn from_text(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (body, sum) = match text.rsplit_once(':') {
+            Some(parts) => parts,
+            None => bail!("not a share"),
+        };
+        let parts: Vec<&str> = body.split(':').collect();
+        if parts.len() != 5 || parts[0] != SHARE_PREFIX {
+            bail!("not a share");
+        }
+        if checksum(body) != sum.to_lowercase() {
+            bail!("share checksum mismatch, check it for typos");
+        }
+        Ok(Share {
+            vault_id: parts[1].to_string(),
+            threshold: parts[2].parse()?,
+            index: parts[3].parse()?,
+            data: SecretBuf::from_vec(hex::decode(parts[4])?)?,
+        })
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "share {} of vault {}, {} needed", self.index, self.vault_id, self.threshold)
+    }
+}
+
+fn checksum(body: &str) -> String {
+    let hash = utils::sha256(body.as_bytes()).unwrap_or_default();
+    hash[..CHECKSUM_NUM * 2].to_string()
+}
+
+// multiplication in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the inverse of a for any a != 0
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    let mut base = a;
+    let mut exp = 254;
+    while exp > 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+/// Splits secret into count shares, any threshold of them give it back.
+pub fn split(vault_id: &str, secret: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>> {
+    if threshold < 2 || threshold > count {
+        bail!("threshold must be between 2 and the number of shares");
+    }
+    if secret.is_empty() {
+        bail!("secret can`t empty");
+    }
+    let mut shares: Vec<Share> = (1..=count).map(|index| Ok(Share {
+        vault_id: vault_id.to_string(),
+        threshold,
+        index,
+        data: SecretBuf::new(secret.len())?,
+    })).collect::<Result<_>>()?;
+    // coefficients[0] is the secret byte, the rest are random
+    let mut coefficients = SecretBuf::new(threshold as usize)?;
+    let mut rng = rand::thread_rng();
+    for (i, byte) in secret.iter().enumerate() {
+        coefficients.as_mut_bytes()[0] = *byte;
+        rng.fill(&mut coefficients.as_mut_bytes()[1..]);
+        for share in shares.iter_mut() {
+            // horner's rule at x = index
+            let mut y = 0;
+            for c in coefficients.iter().rev() {
+                y = gf_mul(y, share.index) ^ c;
+            }
+            share.data.as_mut_bytes()[i] = y;
+        }
+    }
+    Ok(shares)
+}
+
+/// Lagrange interpolation at x = 0 over the shares, they must come from the same split.
+pub fn combine(shares: &[Share]) -> Result<SecretBuf> {
+    let first = match shares.first() {
+        Some(share) => share,
+        None => bail!("no shares given"),
+    };
+    for (i, share) in shares.iter().enumerate() {
+        if share.vault_id != first.vault_id || share.threshold != first.threshold || share.data.len() != first.data.len() {
+            bail!("shares come from different splits");
+        }
+        if share.index == 0 || shares[..i].iter().any(|other| other.index == share.index) {
+            bail!("share {} is invalid or given twice", share.index);
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        bail!("{} shares needed, only {} given", first.threshold, shares.len());
+    }
+    let shares = &shares[..first.threshold as usize];
+    let mut secret = SecretBuf::new(first.data.len())?;
+    for share in shares {
+        // basis polynomial of this share at x = 0
+        let mut basis = 1;
+        for other in shares.iter().filter(|other| other.index != share.index) {
+            basis = gf_mul(basis, gf_mul(other.index, gf_inv(other.index ^ share.index)));
+        }
+        for (byte, y) in secret.as_mut_bytes().iter_mut().zip(share.data.iter()) {
+            *byte ^= gf_mul(*y, basis);
+        }
+    }
+    Ok(secret)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gf_arithmetic() {
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn split_and_combine() {
+        let key = utils::aes256_key().unwrap();
+        let shares = split("vault-1", &key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.data != key));
+
+        assert_eq!(combine(&shares[..3]).unwrap(), key);
+        assert_eq!(combine(&shares[2..]).unwrap(), key);
+        let picked = vec![shares[4].clone(), shares[0].clone(), shares[2].clone()];
+        assert_eq!(combine(&picked).unwrap(), key);
+        assert_eq!(combine(&shares).unwrap(), key);
+
+        assert!(combine(&shares[..2]).is_err());
+        assert!(combine(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err());
+        let other = split("vault-2", &key, 3, 5).unwrap();
+        assert!(combine(&[shares[0].clone(), shares[1].clone(), other[2].clone()]).is_err());
+
+        assert!(split("vault-1", &key, 1, 5).is_err());
+        assert!(split("vault-1", &key, 6, 5).is_err());
+    }
+
+    #[test]
+    fn share_text() {
+        let key = utils::aes256_key().unwrap();
+        let shares = split("3f0c", &key, 2, 3).unwrap();
+        let text = shares[1].to_text();
+        assert!(text.starts_with("CPSHARE1:3f0c:2:2:"));
+        assert_eq!(Share::from_text(&format!(" {}\n", text)).unwrap(), shares[1]);
+
+        // one mistyped hex digit
+        let typo = text.replacen(":2:2:", ":2:3:", 1);
+        assert!(Share::from_text(&typo).unwrap_err().to_string().contains("checksum"));
+        assert!(Share::from_text("hello").is_err());
+
+        let parsed: Vec<Share> = [&shares[2], &shares[0]].iter().map(|s| Share::from_text(&s.to_text()).unwrap()).collect();
+        assert_eq!(combine(&parsed).unwrap(), key);
+    }
+}
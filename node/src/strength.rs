@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+
+use chrono::Datelike;
+use once_cell::sync::Lazy;
+
+// longer input only adds guesses, so the tail is not looked at
+const MAX_CHARS: usize = 100;
+const BRUTEFORCE_CARDINALITY: f64 = 10.;
+const MIN_GUESSES_SINGLE_CHAR: f64 = 10.;
+const MIN_GUESSES_MULTI_CHAR: f64 = 50.;
+const MIN_YEAR_SPACE: f64 = 20.;
+// argon2 guarded, an attacker with the vault file gets ~1e4 guesses a second
+const SLOW_HASH_GUESSES_PER_SECOND: f64 = 1e4;
+// a leaked site database may keep a fast unsalted hash, ~1e10 guesses a second on gpus
+const FAST_HASH_GUESSES_PER_SECOND: f64 = 1e10;
+const KEYBOARD_STARTS: f64 = 47.;
+const KEYBOARD_DEGREE: f64 = 4.;
+
+pub const MAX_SCORE: u8 = 4;
+
+// most used passwords first, the rank is the guess count
+const COMMON_PASSWORDS: &[&str] = &[
+    "123456", "password", "123456789", "12345678", "12345", "qwerty", "1234567", "111111", "1234567890", "123123",
+    "abc123", "1234", "password1", "iloveyou", "1q2w3e4r", "000000", "qwerty123", "zaq12wsx", "dragon", "sunshine",
+    "princess", "letmein", "654321", "monkey", "27653", "1qaz2wsx", "123321", "qwertyuiop", "superman", "asdfghjkl",
+    "trustno1", "football", "baseball", "welcome", "shadow", "master", "michael", "jennifer", "666666", "987654321",
+    "121212", "hello", "charlie", "aa123456", "donald", "qwe123", "login", "admin", "starwars", "passw0rd",
+    "freedom", "whatever", "ashley", "bailey", "mustang", "access", "flower", "batman", "jordan", "hunter",
+    "ranger", "buster", "soccer", "harley", "hockey", "killer", "george", "andrew", "thomas", "michelle",
+    "pepper", "daniel", "jessica", "zxcvbnm", "hunter2", "secret", "summer", "internet", "cheese", "computer",
+    "matrix", "chocolate", "pokemon", "liverpool", "samsung", "orange", "banana", "silver", "golfer", "tigger",
+    "love", "god", "sex", "money", "dolphin", "maggie", "cookie", "ginger", "joshua", "amanda",
+];
+
+static COMMON_RANKS: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| {
+    COMMON_PASSWORDS.iter().enumerate().map(|(rank, word)| (*word, rank + 1)).collect()
+});
+
+// the EFF diceware list doubles as an english dictionary, every word ranks the same
+static ENGLISH_WORDS: Lazy<HashMap<&'static str, usize>> = Lazy::new(|| {
+    eff_wordlist::large::LIST.iter().map(|(_, word)| (*word, eff_wordlist::large::LIST.len())).collect()
+});
+
+const KEYBOARD_ROWS: [(&str, &str); 4] = [
+    ("`1234567890-=", "~!@#$%^&*()_+"),
+    ("qwertyuiop[]\\", "QWERTYUIOP{}|"),
+    ("asdfghjkl;'", "ASDFGHJKL:\""),
+    ("zxcvbnm,./", "ZXCVBNM<>?"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    // no word kept, the strength is cached next to the password input
+    Dictionary { rank: usize, common: bool, l33t: bool, reversed: bool },
+    // a walk over neighbouring keys of a qwerty keyboard
+    Spatial { turns: usize, shifted: bool },
+    // abc, 6543
+    Sequence,
+    // aaa, abcabc
+    Repeat,
+    Date,
+    Bruteforce,
+}
+
+/// A part of the password that an attacker would guess as a whole, start and end are
+/// char indexes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub pattern: Pattern,
+    pub start: usize,
+    pub end: usize,
+    pub guesses: f64,
+}
+
+/// How the guessed password is stored where an attacker gets it, only the crack time
+/// depends on it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hashing {
+    // the master password, behind the vault kdf
+    Slow,
+    // item secrets, the site they are used on may keep them behind a fast hash
+    Fast,
+}
+
+impl Hashing {
+    fn guesses_per_second(self) -> f64 {
+        match self {
+            Hashing::Slow => SLOW_HASH_GUESSES_PER_SECOND,
+            Hashing::Fast => FAST_HASH_GUESSES_PER_SECOND,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Strength {
+    pub guesses: f64,
+    // 0 too guessable, 1 very guessable, 2 somewhat guessable, 3 safely unguessable, 4 very unguessable
+    pub score: u8,
+    pub crack_seconds: f64,
+    pub sequence: Vec<Match>,
+    pub warning: String,
+    pub suggestions: Vec<String>,
+}
+
+impl Strength {
+    pub fn crack_time(&self) -> String {
+        display_time(self.crack_seconds)
+    }
+}
+
+/// Estimates how many guesses the password takes. user_inputs are words that are easy to
+/// guess for this password, like the account name.
+pub fn estimate(password: &str, user_inputs: &[&str], hashing: Hashing) -> Strength {
+    let chars: Vec<char> = password.chars().take(MAX_CHARS).collect();
+    let user_ranks: HashMap<String, usize> = user_inputs.iter()
+        .flat_map(|input| input.split(|c: char| !c.is_alphanumeric()))
+        .chain(user_inputs.iter().copied())
+        .filter(|word| word.chars().count() >= 3)
+        .enumerate()
+        .map(|(rank, word)| (word.to_lowercase(), rank + 1))
+        .collect();
+    let (guesses, sequence) = minimum_guesses(&chars, &user_ranks);
+    let score = score(guesses);
+    let (warning, suggestions) = feedback(score, &sequence);
+    Strength {
+        guesses,
+        score,
+        crack_seconds: guesses / hashing.guesses_per_second(),
+        sequence,
+        warning,
+        suggestions,
+    }
+}
+
+fn score(guesses: f64) -> u8 {
+    match guesses {
+        g if g < 1e3 + 5. => 0,
+        g if g < 1e6 + 5. => 1,
+        g if g < 1e8 + 5. => 2,
+        g if g < 1e10 + 5. => 3,
+        _ => 4,
+    }
+}
+
+// the cheapest way to cover the password with matches, unmatched chars are bruteforced
+fn minimum_guesses(chars: &[char], user_ranks: &HashMap<String, usize>) -> (f64, Vec<Match>) {
+    let n = chars.len();
+    if n == 0 {
+        return (1., vec![]);
+    }
+    let matches = omnimatch(chars, user_ranks);
+    // best[k]: guesses for chars[..k] and the match that ends there
+    let mut best: Vec<(f64, Option<usize>)> = vec![(f64::INFINITY, None); n + 1];
+    best[0] = (1., None);
+    for k in 1..=n {
+        // one bruteforced char
+        let bruteforce = best[k - 1].0 * BRUTEFORCE_CARDINALITY;
+        if bruteforce < best[k].0 {
+            best[k] = (bruteforce, None);
+        }
+        for (i, m) in matches.iter().enumerate().filter(|(_, m)| m.end == k) {
+            let guesses = best[m.start].0 * m.guesses;
+            if guesses < best[k].0 {
+                best[k] = (guesses, Some(i));
+            }
+        }
+    }
+    let mut sequence = Vec::new();
+    let mut k = n;
+    while k > 0 {
+        match best[k].1 {
+            Some(i) => {
+                sequence.push(matches[i].clone());
+                k = matches[i].start;
+            }
+            None => {
+                // merge runs of bruteforced chars
+                let end = k;
+                while k > 0 && best[k].1.is_none() {
+                    k -= 1;
+                }
+                let start = k;
+                sequence.push(Match {
+                    pattern: Pattern::Bruteforce,
+                    start,
+                    end,
+                    guesses: BRUTEFORCE_CARDINALITY.powi((end - start) as i32),
+                });
+            }
+        }
+    }
+    sequence.reverse();
+    (best[n].0, sequence)
+}
+
+fn omnimatch(chars: &[char], user_ranks: &HashMap<String, usize>) -> Vec<Match> {
+    let mut matches = Vec::new();
+    dictionary_matches(chars, user_ranks, &mut matches);
+    spatial_matches(chars, &mut matches);
+    sequence_matches(chars, &mut matches);
+    repeat_matches(chars, user_ranks, &mut matches);
+    date_matches(chars, &mut matches);
+    for m in matches.iter_mut() {
+        let min = if m.end - m.start == 1 { MIN_GUESSES_SINGLE_CHAR } else { MIN_GUESSES_MULTI_CHAR };
+        m.guesses = m.guesses.max(min);
+    }
+    matches
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '(' | '{' | '[' | '<' => 'c',
+        '3' => 'e',
+        '6' | '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        c => c,
+    }
+}
+
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_uppercase()).count();
+    let lower = word.iter().filter(|c| c.is_lowercase()).count();
+    if upper == 0 {
+        1.
+    } else if lower == 0 || (upper == 1 && (word[0].is_uppercase() || word[word.len() - 1].is_uppercase())) {
+        // ALL CAPS, Capitalized or endS
+        2.
+    } else {
+        2f64.powi(upper.min(lower) as i32 + 1)
+    }
+}
+
+fn lookup(word: &str, user_ranks: &HashMap<String, usize>) -> Option<(usize, bool)> {
+    if let Some(rank) = user_ranks.get(word) {
+        return Some((*rank, true));
+    }
+    if let Some(rank) = COMMON_RANKS.get(word) {
+        return Some((*rank, true));
+    }
+    ENGLISH_WORDS.get(word).map(|rank| (*rank, false))
+}
+
+fn dictionary_matches(chars: &[char], user_ranks: &HashMap<String, usize>, matches: &mut Vec<Match>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    let unleeted: Vec<char> = lower.iter().map(|c| unleet(*c)).collect();
+    for start in 0..chars.len() {
+        for end in start + 3..=chars.len() {
+            let word: String = lower[start..end].iter().collect();
+            let reversed: String = lower[start..end].iter().rev().collect();
+            let l33t: String = unleeted[start..end].iter().collect();
+            let found = [(&word, false, false), (&l33t, true, false), (&reversed, false, true)].into_iter()
+                .filter(|(candidate, l33t, _)| !*l33t || **candidate != word)
+                .find_map(|(candidate, l33t, reversed)| lookup(candidate, user_ranks).map(|found| (l33t, reversed, found)));
+            if let Some((l33t, reversed, (rank, common))) = found {
+                let mut guesses = rank as f64 * uppercase_variations(&chars[start..end]);
+                if l33t {
+                    guesses *= 2.;
+                }
+                if reversed {
+                    guesses *= 2.;
+                }
+                matches.push(Match { pattern: Pattern::Dictionary { rank, common, l33t, reversed }, start, end, guesses });
+            }
+        }
+    }
+}
+
+// row and column on the keyboard, and whether shift is needed
+fn key_position(c: char) -> Option<(i32, i32, bool)> {
+    for (row, (plain, shifted)) in KEYBOARD_ROWS.iter().enumerate() {
+        if let Some(col) = plain.chars().position(|k| k == c) {
+            return Some((row as i32, col as i32, false));
+        }
+        if let Some(col) = shifted.chars().position(|k| k == c) {
+            return Some((row as i32, col as i32, true));
+        }
+    }
+    None
+}
+
+// the direction from a to b when they are neighbours, rows are staggered half a key
+fn key_direction(a: (i32, i32), b: (i32, i32)) -> Option<usize> {
+    let neighbours = [(0, -1), (0, 1), (-1, 0), (-1, 1), (1, -1), (1, 0)];
+    neighbours.iter().position(|(dr, dc)| (a.0 + dr, a.1 + dc) == b)
+}
+
+fn spatial_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let mut end = start + 1;
+        let mut turns = 0;
+        let mut last_direction = None;
+        let mut shifted = false;
+        while end < chars.len() {
+            let (a, b) = match (key_position(chars[end - 1]), key_position(chars[end])) {
+                (Some(a), Some(b)) => (a, b),
+                _ => break,
+            };
+            let direction = match key_direction((a.0, a.1), (b.0, b.1)) {
+                Some(direction) => direction,
+                None => break,
+            };
+            if last_direction.is_some() && last_direction != Some(direction) {
+                turns += 1;
+            }
+            last_direction = Some(direction);
+            shifted |= a.2 || b.2;
+            end += 1;
+        }
+        if end - start >= 3 {
+            let len = (end - start) as f64;
+            let mut guesses = KEYBOARD_STARTS * len * KEYBOARD_DEGREE.powi(turns as i32);
+            if shifted {
+                guesses *= 2.;
+            }
+            matches.push(Match { pattern: Pattern::Spatial { turns, shifted }, start, end, guesses });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+fn sequence_matches(chars: &[char], matches: &mut Vec<Match>) {
+    let class = |c: char| if c.is_ascii_lowercase() { 1 } else if c.is_ascii_uppercase() { 2 } else if c.is_ascii_digit() { 3 } else { 0 };
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        let same_class = |i: usize| class(chars[i]) != 0 && class(chars[i]) == class(chars[start]);
+        let mut end = start + 1;
+        while end < chars.len() && same_class(end) && chars[end] as i32 - chars[end - 1] as i32 == delta && delta.abs() == 1 {
+            end += 1;
+        }
+        if end - start >= 3 {
+            let first = chars[start];
+            let base = if "aAzZ019".contains(first) { 4. } else if first.is_ascii_digit() { 10. } else { 26. };
+            let mut guesses = base * (end - start) as f64;
+            if delta < 0 {
+                guesses *= 2.;
+            }
+            matches.push(Match { pattern: Pattern::Sequence, start, end, guesses });
+            start = end - 1;
+        } else {
+            start += 1;
+        }
+    }
+}
+
+fn repeat_matches(chars: &[char], user_ranks: &HashMap<String, usize>, matches: &mut Vec<Match>) {
+    // each distinct block is only guessed once, guessing it looks for repeats inside it again
+    let mut block_guesses: HashMap<&[char], f64> = HashMap::new();
+    for start in 0..chars.len() {
+        // (block, end) of the repeats found from this start
+        let mut found: Vec<(usize, usize)> = Vec::new();
+        for block in 1..=(chars.len() - start) / 2 {
+            // the same block repeating from an earlier start already covers this one
+            if start >= block && chars[start - block..start] == chars[start..start + block] {
+                continue;
+            }
+            // abab is a repeat of ab, which guesses it for less
+            if found.iter().any(|(smaller, end)| block % smaller == 0 && start + block <= *end) {
+                continue;
+            }
+            let mut count = 1;
+            while start + (count + 1) * block <= chars.len()
+                && chars[start..start + block] == chars[start + count * block..start + (count + 1) * block] {
+                count += 1;
+            }
+            if count < 2 || (block == 1 && count < 3) {
+                continue;
+            }
+            let part = &chars[start..start + block];
+            let guesses = *block_guesses.entry(part).or_insert_with(|| minimum_guesses(part, user_ranks).0);
+            found.push((block, start + count * block));
+            matches.push(Match {
+                pattern: Pattern::Repeat,
+                start,
+                end: start + count * block,
+                guesses: guesses * count as f64,
+            });
+        }
+    }
+}
+
+fn year_guesses(year: i32) -> f64 {
+    let reference = chrono::Local::now().year();
+    ((year - reference).abs() as f64).max(MIN_YEAR_SPACE)
+}
+
+// two digit years are 1950 to 2049
+fn full_year(year: i32, digits: usize) -> i32 {
+    match (digits, year) {
+        (2, y) if y >= 50 => 1900 + y,
+        (2, y) => 2000 + y,
+        (_, y) => y,
+    }
+}
+
+// a day, month and year in any usual order, or a year alone
+fn parse_date(digits: &[u32]) -> Option<i32> {
+    let number = |part: &[u32]| part.iter().fold(0i32, |n, d| n * 10 + *d as i32);
+    if digits.len() == 4 {
+        let year = number(digits);
+        if (1900..=2050).contains(&year) {
+            return Some(year);
+        }
+    }
+    // (day or month length, day or month length, year length) with the year first or last
+    for (a, b, y) in [(1, 1, 2), (2, 1, 2), (1, 2, 2), (2, 2, 2), (1, 1, 4), (2, 1, 4), (1, 2, 4), (2, 2, 4)] {
+        if a + b + y != digits.len() {
+            continue;
+        }
+        for year_first in [false, true] {
+            let (year, rest) = if year_first { digits.split_at(y) } else { let (rest, year) = digits.split_at(a + b); (year, rest) };
+            let (first, second) = (number(&rest[..a]), number(&rest[a..]));
+            let year = full_year(number(year), y);
+            let day_month = (1..=31).contains(&first) && (1..=12).contains(&second);
+            let month_day = (1..=12).contains(&first) && (1..=31).contains(&second);
+            if (1900..=2050).contains(&year) && (day_month || month_day) {
+                return Some(year);
+            }
+        }
+    }
+    None
+}
+
+fn date_matches(chars: &[char], matches: &mut Vec<Match>) {
+    for start in 0..chars.len() {
+        for end in start + 4..=chars.len().min(start + 10) {
+            let part = &chars[start..end];
+            let separators: Vec<&char> = part.iter().filter(|c| !c.is_ascii_digit()).collect();
+            let separated = match separators.as_slice() {
+                [] => false,
+                // both separators the same and from the usual ones
+                [a, b] if a == b && " -/._\\".contains(**a) => true,
+                _ => continue,
+            };
+            if !part[0].is_ascii_digit() || !part[part.len() - 1].is_ascii_digit() {
+                continue;
+            }
+            let digits: Vec<u32> = part.iter().filter_map(|c| c.to_digit(10)).collect();
+            if let Some(year) = parse_date(&digits) {
+                let mut guesses = year_guesses(year);
+                if digits.len() > 4 || separated {
+                    guesses *= 365.;
+                }
+                if separated {
+                    guesses *= 4.;
+                }
+                matches.push(Match { pattern: Pattern::Date, start, end, guesses });
+            }
+        }
+    }
+}
+
+fn feedback(score: u8, sequence: &[Match]) -> (String, Vec<String>) {
+    if sequence.is_empty() {
+        return ("".to_string(), vec!["Use a few words, avoid common phrases".to_string()]);
+    }
+    if score > 2 {
+        return ("".to_string(), vec![]);
+    }
+    let mut suggestions = vec!["Add another word or two, uncommon words are better".to_string()];
+    let longest = sequence.iter()
+        .filter(|m| m.pattern != Pattern::Bruteforce)
+        .max_by_key(|m| m.end - m.start);
+    let warning = match longest.map(|m| &m.pattern) {
+        Some(Pattern::Dictionary { common, l33t, reversed, .. }) => {
+            if *l33t {
+                suggestions.push("Predictable substitutions like '@' instead of 'a' don't help very much".to_string());
+            }
+            if *reversed {
+                suggestions.push("Reversed words aren't much harder to guess".to_string());
+            }
+            if *common && sequence.len() == 1 {
+                "This is a very common password"
+            } else if *common {
+                "This is similar to a commonly used password"
+            } else {
+                "A word by itself is easy to guess"
+            }
+        }
+        Some(Pattern::Spatial { turns, .. }) => {
+            suggestions.push("Use a longer keyboard pattern with more turns".to_string());
+            if *turns == 0 { "Straight rows of keys are easy to guess" } else { "Short keyboard patterns are easy to guess" }
+        }
+        Some(Pattern::Sequence) => {
+            suggestions.push("Avoid sequences".to_string());
+            "Sequences like abc or 6543 are easy to guess"
+        }
+        Some(Pattern::Repeat) => {
+            suggestions.push("Avoid repeated words and characters".to_string());
+            "Repeats like \"aaa\" or \"abcabc\" are easy to guess"
+        }
+        Some(Pattern::Date) => {
+            suggestions.push("Avoid dates and years that are associated with you".to_string());
+            "Dates are often easy to guess"
+        }
+        Some(Pattern::Bruteforce) | None => "",
+    };
+    (warning.to_string(), suggestions)
+}
+
+pub fn display_time(seconds: f64) -> String {
+    let units = [
+        (60. * 60. * 24. * 365. * 100., "century"),
+        (60. * 60. * 24. * 365., "year"),
+        (60. * 60. * 24. * 31., "month"),
+        (60. * 60. * 24., "day"),
+        (60. * 60., "hour"),
+        (60., "minute"),
+        (1., "second"),
+    ];
+    if seconds < 1. {
+        return "less than a second".to_string();
+    }
+    if seconds >= units[0].0 {
+        return "centuries".to_string();
+    }
+    for (size, name) in units {
+        if seconds >= size {
+            let count = (seconds / size).round() as u64;
+            return format!("{} {}{}", count, name, if count == 1 { "" } else { "s" });
+        }
+    }
+    unreachable!()
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn weak_patterns() {
+        let cases = [
+            ("password", "Dictionary"),
+            ("P@ssw0rd", "Dictionary"),
+            ("qwertyuiop", "Dictionary"),
+            ("zxcvbn", "Spatial"),
+            ("abcdefg", "Sequence"),
+            ("98765", "Sequence"),
+            ("aaaaaaaa", "Repeat"),
+            ("13.05.1987", "Date"),
+        ];
+        for (password, pattern) in cases {
+            let strength = estimate(password, &[], Hashing::Slow);
+            assert!(strength.score <= 1, "{} scored {}", password, strength.score);
+            assert_eq!(strength.sequence.len(), 1, "{}: {:?}", password, strength.sequence);
+            assert!(format!("{:?}", strength.sequence[0].pattern).starts_with(pattern), "{}: {:?}", password, strength.sequence);
+            assert_ne!(strength.warning, "");
+        }
+        let strength = estimate("P@ssw0rd", &[], Hashing::Slow);
+        assert_eq!(strength.sequence[0].pattern, Pattern::Dictionary { rank: 2, common: true, l33t: true, reversed: false });
+        assert!(strength.suggestions.iter().any(|s| s.contains("substitutions")));
+        assert_eq!(estimate("drowssap", &[], Hashing::Slow).sequence[0].pattern,
+                   Pattern::Dictionary { rank: 2, common: true, l33t: false, reversed: true });
+    }
+
+    #[test]
+    fn user_inputs_and_strong() {
+        let strength = estimate("alice1987", &["alice@example.com"], Hashing::Slow);
+        assert!(strength.score < estimate("alice1987", &[], Hashing::Slow).score);
+        assert_eq!(strength.sequence.len(), 2);
+        assert_eq!(strength.sequence[1].pattern, Pattern::Date);
+
+        let strength = estimate("tX9#qv!Lm2$wR8zK", &[], Hashing::Slow);
+        assert_eq!(strength.score, MAX_SCORE);
+        assert_eq!(strength.warning, "");
+        assert_eq!(strength.crack_time(), "centuries");
+        let fast = estimate("tX9#qv!Lm2$wR8zK", &[], Hashing::Fast);
+        assert_eq!(fast.score, strength.score);
+        assert!(fast.crack_seconds < strength.crack_seconds);
+        assert!(estimate("", &[], Hashing::Slow).score == 0);
+        assert!(estimate("abcdefg", &[], Hashing::Slow).guesses < estimate("abcdefgx", &[], Hashing::Slow).guesses);
+    }
+
+    #[test]
+    fn long_repeats() {
+        for block in ["a", "ab", "abc", "aab", "qwerty1234", "correcthorse"] {
+            let password: String = block.repeat(100 / block.len() + 1).chars().take(MAX_CHARS).collect();
+            let started = std::time::Instant::now();
+            let strength = estimate(&password, &[], Hashing::Slow);
+            assert!(started.elapsed().as_secs_f64() < 5., "{} took {:?}", block, started.elapsed());
+            assert!(strength.sequence.iter().any(|m| m.pattern == Pattern::Repeat), "{}: {:?}", block, strength.sequence);
+        }
+    }
+
+    #[test]
+    fn crack_time() {
+        assert_eq!(display_time(0.5), "less than a second");
+        assert_eq!(display_time(1.), "1 second");
+        assert_eq!(display_time(7200.), "2 hours");
+        assert_eq!(display_time(60. * 60. * 24. * 365. * 3.), "3 years");
+        assert_eq!(display_time(1e20), "centuries");
+    }
+}
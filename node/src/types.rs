@@ -1,36 +1,98 @@
+use std::collections::{BTreeMap, HashSet};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use super::utils::MemData;
-
-#[derive(Debug, Clone)]
-pub struct State {
-    pub items: Vec<Item>,
-    pub input_item: InputItem,
-    pub input_secret: String,
-    pub secret: MemData,
-    pub guild_tips_msg: String,
-}
+use super::kind::ItemKind;
+use super::otp::OtpAuth;
+use super::secret::SecretString;
 
 const aes_key_num: usize = 32;
 
 
 #[derive(Debug, Clone, Default)]
 pub struct InputItem {
+    pub kind: ItemKind,
     pub account_value: String,
-    pub secret_value: String,
+    pub secret_value: SecretString,
     pub desc_value: String,
+    pub otp_value: SecretString,
+    // YYYY-MM-DD, empty never expires
+    pub expires_value: String,
+    // comma separated
+    pub tags_value: String,
+    pub folder_value: String,
+    pub favorite: bool,
+    // the kind's slots by name
+    pub details: BTreeMap<String, SecretString>,
+    // custom fields in the order they are shown
+    pub fields: Vec<InputField>,
+    pub tips: String,
+}
+
+
+/// A custom field being edited, or added from the detail view.
+#[derive(Debug, Clone, Default)]
+pub struct InputField {
+    // empty for a new field
+    pub id: String,
+    pub kind: FieldKind,
+    pub name: String,
+    pub value: SecretString,
+}
+
+
+impl InputField {
+    pub fn clear(&mut self) {
+        self.id = "".to_string();
+        self.kind = FieldKind::Text;
+        self.name = "".to_string();
+        self.value.wipe();
+    }
 }
 
 
 impl InputItem {
     pub fn clear(&mut self) {
         self.account_value = "".to_string();
-        self.secret_value = "".to_string();
+        self.secret_value.wipe();
         self.desc_value = "".to_string();
+        self.otp_value.wipe();
+        self.expires_value = "".to_string();
+        self.kind = ItemKind::Login;
+        self.tags_value = "".to_string();
+        self.folder_value = "".to_string();
+        self.favorite = false;
+        for value in self.details.values_mut() {
+            value.wipe();
+        }
+        self.details.clear();
+        for field in &mut self.fields {
+            field.clear();
+        }
+        self.fields.clear();
+        self.tips = "".to_string();
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputPassword {
+    pub old_value: SecretString,
+    pub new_value: SecretString,
+    pub confirm_value: SecretString,
+    pub tips: String,
+}
+
+
+impl InputPassword {
+    pub fn clear(&mut self) {
+        self.old_value.wipe();
+        self.new_value.wipe();
+        self.confirm_value.wipe();
+        self.tips = "".to_string();
     }
 }
 
@@ -38,27 +100,526 @@ impl InputItem {
 #[derive(Debug, Clone,PartialEq, Default, Deserialize, Serialize)]
 pub struct Item {
     pub(crate) id: String,
+    #[serde(default)]
+    pub(crate) version: u32,
     pub(crate) account: String,
     pub(crate) secret: String,
     pub(crate) desc: String,
-    pub(crate) status: usize,
+    pub(crate) status: ItemStatus,
+    // only version 0 items keep the secret nonce apart
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub nonce: String,
+    // sealed otpauth:// uri of an authenticator item
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) otp: String,
+    // unix seconds the secret was last set, 0 when unknown
+    #[serde(default)]
+    pub(crate) secret_changed: u64,
+    // unix seconds, items stored before these were kept get them backfilled on unlock
+    #[serde(default)]
+    pub(crate) created: u64,
+    #[serde(default)]
+    pub(crate) updated: u64,
+    // viewed or a code copied, 0 never
+    #[serde(default)]
+    pub(crate) last_used: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) expires: Option<u64>,
+    // custom fields in the order they are shown
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) fields: Vec<CustomField>,
+    // older items load as logins
+    #[serde(default)]
+    pub(crate) kind: ItemKind,
+    // sealed json of the kind's slots
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) details: String,
+    // sealed json list of tags
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) tags: String,
+    // sealed folder path such as Work/Mail
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub(crate) folder: String,
+    // pinned to the top of the list
+    #[serde(default)]
+    pub(crate) favorite: bool,
+    // files kept as chunks apart from the item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) attachments: Vec<Attachment>,
+    // unix seconds the item was moved to the trash, 0 when it is not there
+    #[serde(default)]
+    pub(crate) trashed: u64,
+    // status a trashed item is restored to
+    #[serde(default)]
+    pub(crate) trashed_from: ItemStatus,
+}
+
+
+/// Where an item is in its life. Archived items are kept out of the list, trashed
+/// ones are purged after a while. Stored as the number older vaults already have.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(from = "usize", into = "usize")]
+pub enum ItemStatus {
+    Active,
+    Archived,
+    Trashed,
+}
+
+impl Default for ItemStatus {
+    fn default() -> Self {
+        ItemStatus::Active
+    }
+}
+
+// an unknown status keeps the item in the list rather than losing it
+impl From<usize> for ItemStatus {
+    fn from(status: usize) -> Self {
+        match status {
+            1 => ItemStatus::Archived,
+            2 => ItemStatus::Trashed,
+            _ => ItemStatus::Active,
+        }
+    }
+}
+
+impl From<ItemStatus> for usize {
+    fn from(status: ItemStatus) -> Self {
+        match status {
+            ItemStatus::Active => 0,
+            ItemStatus::Archived => 1,
+            ItemStatus::Trashed => 2,
+        }
+    }
+}
+
+
+/// A file attached to an item. Its content is in self_encryption chunks stored by
+/// hash, only the sealed data map tells which chunks and how to decrypt them.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct Attachment {
+    pub id: String,
+    // hex cipher Envelopes of the file name and of the data map json
+    pub name: String,
+    pub data_map: String,
+    pub size: u64,
+    // unix seconds
+    pub added: u64,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum FieldKind {
+    Text,
+    // hidden until revealed
+    Concealed,
+    Url,
+    Email,
+    Phone,
+    // YYYY-MM-DD
+    Date,
+    // an otpauth:// uri or a base32 secret, shown as the current code
+    Totp,
+}
+
+impl Default for FieldKind {
+    fn default() -> Self {
+        FieldKind::Text
+    }
+}
+
+impl FieldKind {
+    pub const ALL: [FieldKind; 7] = [FieldKind::Text, FieldKind::Concealed, FieldKind::Url, FieldKind::Email, FieldKind::Phone, FieldKind::Date, FieldKind::Totp];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FieldKind::Text => "Text",
+            FieldKind::Concealed => "Concealed",
+            FieldKind::Url => "URL",
+            FieldKind::Email => "Email",
+            FieldKind::Phone => "Phone",
+            FieldKind::Date => "Date",
+            FieldKind::Totp => "TOTP",
+        }
+    }
+
+    // values that are not shown until revealed
+    pub fn is_hidden(&self) -> bool {
+        *self == FieldKind::Concealed || *self == FieldKind::Totp
+    }
+}
+
+
+/// A custom field of an item, name and value each sealed with the item id and the
+/// field id as associated data, so fields can be moved without sealing them again.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct CustomField {
+    pub id: String,
+    pub kind: FieldKind,
+    // hex cipher Envelopes
+    pub name: String,
+    pub value: String,
+}
+
+/// An item with account and desc decrypted, only kept in memory after unlock.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Entry {
+    pub item: Item,
+    pub account: String,
+    pub desc: String,
+    pub tags: Vec<String>,
+    pub folder: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Kdf {
+    // m_cost in KiB
+    Argon2id { m_cost: u32, t_cost: u32, p_cost: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+}
+
+impl Kdf {
+    pub fn argon2id() -> Self {
+        Kdf::Argon2id { m_cost: 64 * 1024, t_cost: 3, p_cost: 1 }
+    }
+
+    pub fn scrypt() -> Self {
+        Kdf::Scrypt { log_n: 17, r: 8, p: 1 }
+    }
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::argon2id()
+    }
+}
+
+/// Per-vault key derivation settings, the salt is hex.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KdfParams {
+    pub kdf: Kdf,
+    pub salt: String,
+}
+
+impl KdfParams {
+    pub fn new(kdf: Kdf) -> Result<Self> {
+        let salt = super::utils::gen_rand_key(KDF_SALT_NUM)?;
+        Ok(KdfParams { kdf, salt: hex::encode(salt) })
+    }
+}
+
+const KDF_SALT_NUM: usize = 16;
+
+// 0: legacy padded key, 1: KdfParams + sha256 of the key, 2: VaultHeader,
+// 3: items encrypted with a random vault key wrapped by the master key, 4: key slots,
+// 5: vault id and key check, 6: the key check is a cipher Envelope
+pub const VAULT_HEADER_VERSION: u32 = 6;
+
+// 0: raw sealed verifier and wrapped key, 1: cipher Envelopes bound to the slot id
+pub const KEY_SLOT_VERSION: u32 = 1;
+
+/// Version 4 keeps every unlock secret in `slots`; kdf, verifier and wrapped_key are
+/// only read from version 2 and 3 headers. key_check is the verifier canary sealed with
+/// the vault key itself, it tells whether a key handed in is this vault's.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct VaultHeader {
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    #[serde(default)]
+    pub slots: Vec<KeySlot>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub key_check: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfParams>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub verifier: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub wrapped_key: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum KeySlotKind {
+    Password,
+    Keyfile,
+    Recovery,
+    // wrapped by the key the recovery shares split
+    Shares,
+}
+
+/// One unlock secret: the verifier is a hex sealed canary that only opens with the key
+/// derived from the secret, wrapped_key is the hex sealed vault key.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct KeySlot {
+    pub id: String,
+    pub label: String,
+    pub kind: KeySlotKind,
+    pub kdf: KdfParams,
+    pub verifier: String,
+    pub wrapped_key: String,
+    pub time: String,
+    #[serde(default)]
+    pub version: u32,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputKeySlot {
+    pub label_value: String,
+    pub secret_value: SecretString,
+    pub keyfile_value: String,
+    pub test_value: SecretString,
+    // id of the slot waiting for a revoke confirm
+    pub revoking: Option<String>,
+    // loaded when the page opens and after a slot is added or revoked
+    pub slots: Vec<KeySlot>,
+    pub tips: String,
+}
+
+
+impl InputKeySlot {
+    pub fn clear(&mut self) {
+        self.label_value = "".to_string();
+        self.secret_value.wipe();
+        self.keyfile_value = "".to_string();
+        self.test_value.wipe();
+        self.revoking = None;
+        self.slots.clear();
+        self.tips = "".to_string();
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputShares {
+    pub threshold_value: String,
+    pub count_value: String,
+    // share texts, shown until the page is closed
+    pub shares: Vec<SecretString>,
+    pub tips: String,
+}
+
+
+impl InputShares {
+    pub fn clear(&mut self) {
+        self.threshold_value = "".to_string();
+        self.count_value = "".to_string();
+        self.shares.clear();
+        self.tips = "".to_string();
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputRecovery {
+    // generate a recovery key when the vault is created
+    pub create: bool,
+    pub key_value: SecretString,
+    pub path_value: String,
+    pub tips: String,
+}
+
+
+impl InputRecovery {
+    pub fn clear(&mut self) {
+        self.key_value.wipe();
+        self.path_value = "".to_string();
+        self.tips = "".to_string();
+    }
+}
+
+
+/// Vault wide preferences, a field missing from older vaults takes its default.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    // master passwords scoring lower are refused, 0 to 4
+    pub min_password_score: u8,
+    // local HIBP password list, the sorted text download or a built index
+    pub hibp_path: String,
+    // the audit flags secrets older than this, 0 never
+    pub rotation_days: u64,
+    // old field values kept per field of an item
+    pub history_count: usize,
+    // old field values are dropped after this, 0 never
+    pub history_days: u64,
+    // trashed items are purged after this, 0 never
+    pub trash_days: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings { min_password_score: 3, hibp_path: "".to_string(), rotation_days: 180, history_count: 10, history_days: 365, trash_days: 30 }
+    }
+}
+
+
+/// A value an item field had before it was changed, stored apart from the item.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize, Serialize)]
+pub struct FieldVersion {
+    pub field: String,
+    // hex cipher Envelope, sealed with the item id, field and replaced as associated data
+    pub value: String,
+    // unix seconds the value was replaced
+    pub replaced: u64,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct HomeFilter {
+    // a folder shows its subfolders too, empty for every folder
+    pub folder: String,
+    pub tag: String,
+    pub favorites: bool,
+    // the archive instead of the active items
+    pub archived: bool,
+    // ids found in the index, None shows every item
+    pub ids: Option<HashSet<String>>,
+    // items were changed since ids were looked up
+    pub stale: bool,
+}
+
+
+impl HomeFilter {
+    pub fn clear(&mut self) {
+        self.folder = "".to_string();
+        self.tag = "".to_string();
+        self.favorites = false;
+        self.archived = false;
+        self.ids = None;
+        self.stale = false;
+    }
+
+    pub fn shows(&self, entry: &Entry) -> bool {
+        let status = if self.archived { ItemStatus::Archived } else { ItemStatus::Active };
+        if entry.item.status != status {
+            return false;
+        }
+        match &self.ids {
+            Some(ids) => ids.contains(&entry.item.id),
+            None => true,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputHistory {
+    // item the versions were loaded for, empty to load again
+    pub id: String,
+    // oldest first
+    pub versions: Vec<FieldVersion>,
+    // only this version is shown decrypted
+    pub revealed: Option<usize>,
+    pub tips: String,
+}
+
+
+impl InputHistory {
+    pub fn clear(&mut self) {
+        self.id = "".to_string();
+        self.versions.clear();
+        self.revealed = None;
+        self.tips = "".to_string();
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputTrash {
+    // item id to purge once confirmed, TRASH_ALL for every trashed item
+    pub confirm: Option<String>,
+    pub tips: String,
 }
 
+
+impl InputTrash {
+    pub fn clear(&mut self) {
+        self.confirm = None;
+        self.tips = "".to_string();
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct InputAttachment {
+    // file to attach
+    pub path_value: String,
+    // file or directory decrypted copies are saved to
+    pub save_path_value: String,
+    // attachment waiting for its removal to be confirmed
+    pub removing: Option<String>,
+    pub tips: String,
+}
+
+
+impl InputAttachment {
+    pub fn clear(&mut self) {
+        self.path_value = "".to_string();
+        self.save_path_value = "".to_string();
+        self.removing = None;
+        self.tips = "".to_string();
+    }
+}
+
+
+// a migration uri carries every account of an authenticator export
+const IMPORT_TEXT_CAPACITY: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct InputImport {
+    // pasted otpauth:// and otpauth-migration:// uris
+    pub text_value: SecretString,
+    // png files with QR codes, one path per line
+    pub path_value: String,
+    // parsed but not yet saved
+    pub preview: Vec<OtpAuth>,
+    pub tips: String,
+}
+
+
+impl Default for InputImport {
+    fn default() -> Self {
+        InputImport {
+            text_value: SecretString::with_capacity(IMPORT_TEXT_CAPACITY),
+            path_value: "".to_string(),
+            preview: Vec::new(),
+            tips: "".to_string(),
+        }
+    }
+}
+
+
+impl InputImport {
+    pub fn clear(&mut self) {
+        self.text_value.wipe();
+        self.path_value = "".to_string();
+        self.preview.clear();
+        self.tips = "".to_string();
+    }
+}
+
+
+// items in an export stay sealed with the vault key, so it only imports into the vault it came from
+pub const EXPORT_VERSION: &str = "3";
+
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Export {
     pub version: String,
+    // vault header id
+    #[serde(default)]
+    pub vault: String,
     pub desc: String,
     pub time: String,
     pub content: String,
 }
 
 impl Export {
-    pub fn new(version: String, content: String, desc: String) -> Self {
+    pub fn new(version: String, vault: String, content: String, desc: String) -> Self {
         use chrono::prelude::Local;
         let time = Local::now().to_string();
         return Export {
             version,
+            vault,
             desc,
             time,
             content,
@@ -78,6 +639,14 @@ mod test {
         println!("{}", new_key);
     }
 
+    #[test]
+    fn old_export_has_no_vault() {
+        let old = r#"{"version":"2","desc":"","time":"","content":""}"#;
+        let export: Export = serde_json::from_str(old).unwrap();
+        assert_eq!(export.vault, "");
+        assert_ne!(export.version, EXPORT_VERSION);
+    }
+
     fn get_valid_key(key: String) -> Result<String> {
         let key_vec = key.into_bytes();
         let mut new_key: Vec<u8> = vec![];
@@ -1,10 +1,21 @@
 use std::fmt::{Debug, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
-use aes_gcm::aead::{Aead, AeadMut, NewAead};
-use anyhow::{anyhow, Result};
+use aes_gcm::aead::{Aead, AeadMut, NewAead, Payload};
+use anyhow::{anyhow, bail, Result};
+use argon2::{Algorithm, Argon2, Version};
+use chrono::{NaiveDate, TimeZone, Utc};
+use hkdf::Hkdf;
 use rand::Rng;
 use self_encryption::{bytes::Bytes, ChunkInfo, DataMap, decrypt_full_set, encrypt, EncryptedChunk};
 use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use super::secret::SecretBuf;
+use super::types::{Kdf, KdfParams};
 
 const AES_256_KEY_NUM: usize = 32;
 const AES_256_NONCE_NUM: usize = 12;
@@ -19,6 +30,29 @@ const MIN_ENCRYPT_BYTE_NUMS: usize = 3072;
 
 
 
+// unix seconds
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+// YYYY-MM-DD in UTC
+pub fn format_date(secs: u64) -> String {
+    match Utc.timestamp_opt(secs as i64, 0).single() {
+        Some(time) => time.format("%Y-%m-%d").to_string(),
+        None => "".to_string(),
+    }
+}
+
+// the start of the day in UTC
+pub fn parse_date(text: &str) -> Result<u64> {
+    let date = NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|_| anyhow!("{} is not a YYYY-MM-DD date", text))?;
+    let time = Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default()).timestamp();
+    if time < 0 {
+        bail!("{} is before 1970", text);
+    }
+    Ok(time as u64)
+}
+
 pub fn sha256(msg: &[u8]) -> Result<String> {
     let hash = Sha256::digest(msg);
     let hash_hex = hex::encode(hash);
@@ -26,7 +60,7 @@ pub fn sha256(msg: &[u8]) -> Result<String> {
 }
 
 // all parameter is hex
-pub fn aes256_hex_decrypt(msg: &str, key: &str, nonce: &str) -> Result<Vec<u8>> {
+pub fn aes256_hex_decrypt(msg: &str, key: &str, nonce: &str) -> Result<SecretBuf> {
     let msg_hex = hex::decode(msg.trim_start_matches("0x"))?;
     let key_hex = hex::decode(key.trim_start_matches("0x"))?;
     let nonce_hex = hex::decode(nonce.trim_start_matches("0x"))?;
@@ -34,7 +68,7 @@ pub fn aes256_hex_decrypt(msg: &str, key: &str, nonce: &str) -> Result<Vec<u8>>
     let nonce = Nonce::from_slice(nonce_hex.as_slice());
     let cipher = Aes256Gcm::new(key);
     let ciphertext = cipher.decrypt(nonce, msg_hex.as_slice()).map_err(|e| anyhow!("{}",e))?;
-    Ok(ciphertext)
+    SecretBuf::from_vec(ciphertext)
 }
 
 pub fn aes256_hex_encrypt(msg: &str, key: &str) -> Result<(String, String)> {
@@ -50,13 +84,13 @@ pub fn aes256_hex_encrypt(msg: &str, key: &str) -> Result<(String, String)> {
 }
 
 
-pub fn aes256_decode(msg: &[u8], key: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+pub fn aes256_decode(msg: &[u8], key: &[u8], nonce: &[u8]) -> Result<SecretBuf> {
     let key = Key::from_slice(key);
     // 96-bits; unique per message
     let nonce = Nonce::from_slice(nonce);
     let cipher = Aes256Gcm::new(key);
     let ciphertext = cipher.decrypt(nonce, msg).map_err(|e| anyhow!("{}",e))?;
-    Ok(ciphertext)
+    SecretBuf::from_vec(ciphertext)
 }
 
 
@@ -83,8 +117,71 @@ pub fn aes256_encode_with_nonce(msg: &[u8], key: &[u8],nonce:&[u8]) -> Result<Ve
 
 
 
-pub fn aes256_key() -> Result<Vec<u8>> {
-    gen_rand_key(AES_256_KEY_NUM)
+// aad is authenticated but not encrypted, decoding fails unless the same aad is given
+pub fn aes256_encode_aad(msg: &[u8], key: &[u8], aad: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let key = Key::from_slice(key);
+    let cipher = Aes256Gcm::new(key);
+    let nonce = gen_rand_key(AES_256_NONCE_NUM)?;
+    let nonce_array = Nonce::from_slice(nonce.as_slice());
+    let ciphertext = cipher.encrypt(nonce_array, Payload { msg, aad }).map_err(|e| anyhow!("{}",e))?;
+    Ok((ciphertext, nonce))
+}
+
+
+pub fn aes256_decode_aad(msg: &[u8], key: &[u8], nonce: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+    let key = Key::from_slice(key);
+    let nonce = Nonce::from_slice(nonce);
+    let cipher = Aes256Gcm::new(key);
+    let plaintext = cipher.decrypt(nonce, Payload { msg, aad }).map_err(|e| anyhow!("{}",e))?;
+    SecretBuf::from_vec(plaintext)
+}
+
+
+// nonce || ciphertext
+pub fn aes256_seal(msg: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let (mut ciphertext, mut sealed) = aes256_encode(msg, key)?;
+    sealed.append(&mut ciphertext);
+    Ok(sealed)
+}
+
+
+pub fn aes256_open(sealed: &[u8], key: &[u8]) -> Result<SecretBuf> {
+    if sealed.len() < AES_256_NONCE_NUM {
+        bail!("sealed data too short");
+    }
+    let (nonce, ciphertext) = sealed.split_at(AES_256_NONCE_NUM);
+    aes256_decode(ciphertext, key, nonce)
+}
+
+
+pub fn aes256_seal_aad(msg: &[u8], key: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let (mut ciphertext, mut sealed) = aes256_encode_aad(msg, key, aad)?;
+    sealed.append(&mut ciphertext);
+    Ok(sealed)
+}
+
+
+pub fn aes256_open_aad(sealed: &[u8], key: &[u8], aad: &[u8]) -> Result<SecretBuf> {
+    if sealed.len() < AES_256_NONCE_NUM {
+        bail!("sealed data too short");
+    }
+    let (nonce, ciphertext) = sealed.split_at(AES_256_NONCE_NUM);
+    aes256_decode_aad(ciphertext, key, nonce, aad)
+}
+
+
+pub fn aes256_key() -> Result<SecretBuf> {
+    let mut key = SecretBuf::new(AES_256_KEY_NUM)?;
+    rand::thread_rng().fill(key.as_mut_bytes());
+    Ok(key)
+}
+
+
+// a key of its own for each use of the vault key, told apart by info
+pub fn subkey(key: &[u8], info: &[u8]) -> Result<SecretBuf> {
+    let mut subkey = SecretBuf::new(AES_256_KEY_NUM)?;
+    Hkdf::<Sha256>::new(None, key).expand(info, subkey.as_mut_bytes()).map_err(|e| anyhow!("{}",e))?;
+    Ok(subkey)
 }
 
 
@@ -99,19 +196,47 @@ pub fn gen_rand_key(num: usize) -> Result<Vec<u8>> {
 }
 
 
-pub fn get_valid_aes_key(key: String) -> Result<String> {
-    let key_vec = key.into_bytes();
-    let mut new_key: Vec<u8> = vec![];
-    let time =  AES_256_KEY_NUM/ key_vec.len();
-    let nums = AES_256_KEY_NUM % key_vec.len();
-    for _ in 0..time {
-        new_key.append(&mut key_vec.clone());
+pub fn derive_key(password: &[u8], params: &KdfParams) -> Result<SecretBuf> {
+    let salt = hex::decode(&params.salt)?;
+    let mut key = SecretBuf::new(AES_256_KEY_NUM)?;
+    match params.kdf {
+        Kdf::Argon2id { m_cost, t_cost, p_cost } => {
+            let argon_params = argon2::Params::new(m_cost, t_cost, p_cost, Some(AES_256_KEY_NUM))
+                .map_err(|e| anyhow!("{}",e))?;
+            let argon = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon_params);
+            argon.hash_password_into(password, &salt, key.as_mut_bytes()).map_err(|e| anyhow!("{}",e))?;
+        }
+        Kdf::Scrypt { log_n, r, p } => {
+            let scrypt_params = scrypt::Params::new(log_n, r, p).map_err(|e| anyhow!("{}",e))?;
+            scrypt::scrypt(password, &salt, &scrypt_params, key.as_mut_bytes()).map_err(|e| anyhow!("{}",e))?;
+        }
+    }
+    Ok(key)
+}
+
+
+// the key vaults used before KdfParams existed: the password repeated up to 32 bytes,
+// only kept to migrate those vaults
+pub fn legacy_aes_key(password: &[u8]) -> Result<SecretBuf> {
+    if password.is_empty() {
+        bail!("password can`t empty");
     }
-    for i in 0..nums {
-        new_key.push(*key_vec.get(i).unwrap());
+    let mut key = SecretBuf::new(AES_256_KEY_NUM)?;
+    for (b, p) in key.as_mut_bytes().iter_mut().zip(password.iter().cycle()) {
+        *b = *p;
     }
-    let result = String::from_utf8(new_key)?;
-    Ok(result)
+    Ok(key)
+}
+
+// a new file only the owner can read, for plaintext written out on request
+pub fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+    file.write_all(data)?;
+    Ok(())
 }
 
 
@@ -125,7 +250,7 @@ pub struct MemData {
 
 impl Debug for MemData {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        write!(f, "MemData {{ length: {}, chunks: {} }}", self.length, self.encrypted_chunks.len())
     }
 }
 
@@ -174,10 +299,28 @@ impl MemData {
         };
         Ok(mem_data)
     }
-    pub fn decrypt(&self) -> Result<Vec<u8>> {
+    // a data map and length kept apart from chunks loaded again
+    pub fn from_parts(data_map: DataMap, length: usize, encrypted_chunks: Vec<EncryptedChunk>) -> Self {
+        MemData { data_map, length, encrypted_chunks }
+    }
+
+    pub fn data_map(&self) -> &DataMap {
+        &self.data_map
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn chunks(&self) -> &[EncryptedChunk] {
+        &self.encrypted_chunks
+    }
+
+    pub fn decrypt(&self) -> Result<SecretBuf> {
         let content = decrypt_full_set(&self.data_map, self.encrypted_chunks.as_ref())?;
-        let vec = content.to_vec();
-        let r = vec[0..self.length].to_vec();
+        let mut vec = content.to_vec();
+        let r = SecretBuf::from_slice(&vec[0..self.length])?;
+        vec.zeroize();
         Ok(r)
     }
 }
@@ -191,10 +334,30 @@ mod test {
 
 
     #[test]
-    fn test_valid_aes_key(){
-        let new_key = get_valid_aes_key("abcd1234".to_string()).unwrap();
-        println!("{}",new_key);
+    fn test_legacy_aes_key() {
+        let key = legacy_aes_key(b"abcd1234").unwrap();
+        assert_eq!(key.as_bytes(), b"abcd1234abcd1234abcd1234abcd1234");
+        let key = legacy_aes_key("密码".as_bytes()).unwrap();
+        assert_eq!(key.len(), 32);
+        assert!(legacy_aes_key(b"").is_err());
+    }
+
 
+    #[test]
+    fn test_derive_key() {
+        let argon = KdfParams::new(Kdf::Argon2id { m_cost: 64, t_cost: 1, p_cost: 1 }).unwrap();
+        let key = derive_key("密码 password".as_bytes(), &argon).unwrap();
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, derive_key("密码 password".as_bytes(), &argon).unwrap());
+        assert_ne!(key, derive_key(b"password", &argon).unwrap());
+
+        let other_salt = KdfParams::new(argon.kdf).unwrap();
+        assert_ne!(key, derive_key("密码 password".as_bytes(), &other_salt).unwrap());
+
+        let scrypt = KdfParams::new(Kdf::Scrypt { log_n: 4, r: 8, p: 1 }).unwrap();
+        let key = derive_key(b"password", &scrypt).unwrap();
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, derive_key(b"password", &scrypt).unwrap());
     }
 
 
@@ -211,8 +374,28 @@ mod test {
 
         let plain_text = aes256_decode(chipper_text.as_slice(), key.as_slice(), nonce.as_slice()).unwrap();
 
-        println!("decode: {:?}", String::from_utf8(plain_text));
+        println!("decode: {:?}", plain_text.as_str());
+
+    }
 
+
+    #[test]
+    fn test_seal() {
+        let key = aes256_key().unwrap();
+        let sealed = aes256_seal(b"canary", &key).unwrap();
+        assert_eq!(aes256_open(&sealed, &key).unwrap().as_bytes(), b"canary");
+        assert!(aes256_open(&sealed, &aes256_key().unwrap()).is_err());
+        assert!(aes256_open(&sealed[..5], &key).is_err());
+    }
+
+
+    #[test]
+    fn test_seal_aad() {
+        let key = aes256_key().unwrap();
+        let sealed = aes256_seal_aad(b"secret", &key, b"item-1:secret").unwrap();
+        assert_eq!(aes256_open_aad(&sealed, &key, b"item-1:secret").unwrap().as_bytes(), b"secret");
+        assert!(aes256_open_aad(&sealed, &key, b"item-2:secret").is_err());
+        assert!(aes256_open(&sealed, &key).is_err());
     }
 
 
@@ -238,6 +421,6 @@ mod test {
         let mem_data = MemData::new(res.into_bytes()).unwrap();
         let d = mem_data.decrypt().unwrap();
 
-        println!("{:?}", String::from_utf8(d).unwrap());
+        println!("{:?}", d.as_str().unwrap());
     }
 }
\ No newline at end of file
@@ -0,0 +1,741 @@
+use anyhow::{anyhow, bail, Result};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use super::cipher::{DEFAULT_SUITE, Envelope};
+use super::db::Database;
+use super::item;
+use super::recovery;
+use super::secret::SecretBuf;
+use super::shamir::{self, Share};
+use super::types::{Item, Kdf, KdfParams, KEY_SLOT_VERSION, KeySlot, KeySlotKind, VAULT_HEADER_VERSION, VaultHeader};
+use super::utils;
+
+const VERIFIER_CANARY: &[u8] = b"CPandas vault verifier";
+
+pub const MASTER_SLOT_LABEL: &str = "master";
+
+pub const RECOVERY_SLOT_LABEL: &str = "recovery key";
+
+pub const SHARES_SLOT_LABEL: &str = "recovery shares";
+
+pub fn is_created(db: &Database) -> Result<bool> {
+    Ok(db.get_vault_header()?.is_some() || db.get_secret_hash()?.is_some())
+}
+
+// first launch: a random vault key for the items, wrapped by the master password slot
+pub fn create(db: &Database, password: &[u8]) -> Result<SecretBuf> {
+    let vault_key = utils::aes256_key()?;
+    let slot = new_slot(MASTER_SLOT_LABEL, KeySlotKind::Password, password, &vault_key)?;
+    db.put_vault_header(&new_header(vec![slot], &vault_key)?)?;
+    Ok(vault_key)
+}
+
+// Argon2id by default, scrypt when argon2 can't run here (e.g. its memory cost can't be allocated)
+fn derive_new_key(secret: &[u8]) -> Result<(KdfParams, SecretBuf)> {
+    let params = KdfParams::new(Kdf::default())?;
+    match utils::derive_key(secret, &params) {
+        Ok(key) => Ok((params, key)),
+        Err(e) => {
+            log::warn!("argon2id failed, fall back to scrypt: {}", e);
+            let params = KdfParams::new(Kdf::scrypt())?;
+            let key = utils::derive_key(secret, &params)?;
+            Ok((params, key))
+        }
+    }
+}
+
+fn new_header(slots: Vec<KeySlot>, vault_key: &[u8]) -> Result<VaultHeader> {
+    let id = Uuid::new_v4().to_string();
+    Ok(VaultHeader {
+        version: VAULT_HEADER_VERSION,
+        key_check: seal_key_check(&id, vault_key)?,
+        id,
+        slots,
+        kdf: None,
+        verifier: "".to_string(),
+        wrapped_key: "".to_string(),
+    })
+}
+
+fn new_slot(label: &str, kind: KeySlotKind, secret: &[u8], vault_key: &[u8]) -> Result<KeySlot> {
+    let (params, master_key) = derive_new_key(secret)?;
+    seal_slot(&Uuid::new_v4().to_string(), label, kind, params, &master_key, vault_key)
+}
+
+// both envelopes are bound to the slot id, so they can't be swapped between slots
+fn slot_aad(id: &str, part: &str) -> Vec<u8> {
+    format!("cpandas:slot:{}:{}", id, part).into_bytes()
+}
+
+fn seal_slot(id: &str, label: &str, kind: KeySlotKind, kdf: KdfParams, master_key: &[u8], vault_key: &[u8]) -> Result<KeySlot> {
+    let verifier = Envelope::seal(DEFAULT_SUITE, master_key, VERIFIER_CANARY, &slot_aad(id, "verifier"))?;
+    let wrapped_key = Envelope::seal(DEFAULT_SUITE, master_key, vault_key, &slot_aad(id, "key"))?;
+    Ok(KeySlot {
+        id: id.to_string(),
+        label: label.to_string(),
+        kind,
+        kdf,
+        verifier: verifier.to_hex(),
+        wrapped_key: wrapped_key.to_hex(),
+        time: chrono::Local::now().to_string(),
+        version: KEY_SLOT_VERSION,
+    })
+}
+
+// returns the vault key, None when the secret doesn't open this slot
+fn open_slot(slot: &KeySlot, secret: &[u8]) -> Result<Option<SecretBuf>> {
+    let master_key = utils::derive_key(secret, &slot.kdf)?;
+    open_slot_key(slot, &master_key)
+}
+
+fn open_slot_key(slot: &KeySlot, master_key: &[u8]) -> Result<Option<SecretBuf>> {
+    if slot.version == 0 {
+        if !check_verifier(&slot.verifier, master_key)? {
+            return Ok(None);
+        }
+        return Ok(Some(utils::aes256_open(&hex::decode(&slot.wrapped_key)?, master_key)?));
+    }
+    // a wrong key fails the tag check
+    let canary = match Envelope::from_hex(&slot.verifier)?.open(master_key, &slot_aad(&slot.id, "verifier")) {
+        Ok(canary) => canary,
+        Err(_) => return Ok(None),
+    };
+    if !bool::from(canary.ct_eq(VERIFIER_CANARY)) {
+        return Ok(None);
+    }
+    Ok(Some(Envelope::from_hex(&slot.wrapped_key)?.open(master_key, &slot_aad(&slot.id, "key"))?))
+}
+
+// the vault key sealed under itself, bound to the vault id
+fn seal_key_check(vault_id: &str, vault_key: &[u8]) -> Result<String> {
+    let aad = format!("cpandas:vault:{}:key check", vault_id);
+    Ok(Envelope::seal(DEFAULT_SUITE, vault_key, VERIFIER_CANARY, aad.as_bytes())?.to_hex())
+}
+
+fn check_key(header: &VaultHeader, vault_key: &[u8]) -> Result<bool> {
+    let aad = format!("cpandas:vault:{}:key check", header.id);
+    let canary = match Envelope::from_hex(&header.key_check)?.open(vault_key, aad.as_bytes()) {
+        Ok(canary) => canary,
+        Err(_) => return Ok(false),
+    };
+    Ok(canary.ct_eq(VERIFIER_CANARY).into())
+}
+
+// the raw sealed verifiers of version 0 slots and of version 2 and 3 headers
+fn check_verifier(verifier: &str, master_key: &[u8]) -> Result<bool> {
+    let verifier = hex::decode(verifier)?;
+    // a wrong key fails the GCM tag check
+    let canary = match utils::aes256_open(&verifier, master_key) {
+        Ok(canary) => canary,
+        Err(_) => return Ok(false),
+    };
+    Ok(canary.ct_eq(VERIFIER_CANARY).into())
+}
+
+// returns the vault key, None when no key slot opens with the secret; items of an
+// older version are upgraded once the key is known
+pub fn unlock(db: &Database, secret: &[u8]) -> Result<Option<SecretBuf>> {
+    let vault_key = unlock_key(db, secret)?;
+    if let Some(key) = &vault_key {
+        upgrade_header(db, key)?;
+        item::upgrade_items(db, key)?;
+    }
+    Ok(vault_key)
+}
+
+// version 4 headers get a vault id, version 5 ones a key check in an envelope
+fn upgrade_header(db: &Database, vault_key: &[u8]) -> Result<()> {
+    let mut header = get_header(db)?;
+    if header.version >= VAULT_HEADER_VERSION {
+        return Ok(());
+    }
+    if header.id == "" {
+        header.id = Uuid::new_v4().to_string();
+    }
+    header.key_check = seal_key_check(&header.id, vault_key)?;
+    header.version = VAULT_HEADER_VERSION;
+    db.put_vault_header(&header)
+}
+
+fn unlock_key(db: &Database, secret: &[u8]) -> Result<Option<SecretBuf>> {
+    if let Some(header) = db.get_vault_header()? {
+        if header.version > VAULT_HEADER_VERSION {
+            bail!("vault version {} is newer than this app", header.version);
+        }
+        if header.slots.is_empty() {
+            return unlock_single_key(db, header, secret);
+        }
+        for (index, slot) in header.slots.iter().enumerate() {
+            let master_key = utils::derive_key(secret, &slot.kdf)?;
+            if let Some(vault_key) = open_slot_key(slot, &master_key)? {
+                if slot.version < KEY_SLOT_VERSION {
+                    upgrade_slot(db, header, index, &master_key, &vault_key)?;
+                }
+                return Ok(Some(vault_key));
+            }
+        }
+        return Ok(None);
+    }
+    let secret_hash = match db.get_secret_hash()? {
+        Some(hash) => hash,
+        None => return Ok(Some(create(db, secret)?)),
+    };
+    match db.get_kdf_params()? {
+        Some(params) => {
+            let key = utils::derive_key(secret, &params)?;
+            if !check_secret_hash(&secret_hash, &key)? {
+                return Ok(None);
+            }
+            let vault_key = migrate(db, params, &key, &key)?;
+            Ok(Some(vault_key))
+        }
+        None => {
+            // vaults created before KdfParams used the padded password as key
+            let legacy_key = utils::legacy_aes_key(secret)?;
+            if !check_secret_hash(&secret_hash, &legacy_key)? {
+                return Ok(None);
+            }
+            let (params, master_key) = derive_new_key(secret)?;
+            let vault_key = migrate(db, params, &master_key, &legacy_key)?;
+            Ok(Some(vault_key))
+        }
+    }
+}
+
+// reseals a version 0 slot in envelopes once its secret is known, the rest stay as they are
+fn upgrade_slot(db: &Database, mut header: VaultHeader, index: usize, master_key: &[u8], vault_key: &[u8]) -> Result<()> {
+    let slot = &header.slots[index];
+    log::info!("upgrade key slot {}", slot.label);
+    let mut upgraded = seal_slot(&slot.id, &slot.label, slot.kind, slot.kdf.clone(), master_key, vault_key)?;
+    upgraded.time = slot.time.clone();
+    header.slots[index] = upgraded;
+    db.put_vault_header(&header)
+}
+
+// version 2 and 3 headers hold a single master key
+fn unlock_single_key(db: &Database, header: VaultHeader, password: &[u8]) -> Result<Option<SecretBuf>> {
+    let params = header.kdf.ok_or_else(|| anyhow!("vault header without key slots"))?;
+    let master_key = utils::derive_key(password, &params)?;
+    if !check_verifier(&header.verifier, &master_key)? {
+        return Ok(None);
+    }
+    if header.wrapped_key == "" {
+        // items are still encrypted with the master key itself
+        let vault_key = migrate(db, params, &master_key, &master_key)?;
+        return Ok(Some(vault_key));
+    }
+    let vault_key = utils::aes256_open(&hex::decode(&header.wrapped_key)?, &master_key)?;
+    log::info!("move vault master key into a key slot");
+    let slot = KeySlot {
+        id: Uuid::new_v4().to_string(),
+        label: MASTER_SLOT_LABEL.to_string(),
+        kind: KeySlotKind::Password,
+        kdf: params,
+        verifier: header.verifier,
+        wrapped_key: header.wrapped_key,
+        time: chrono::Local::now().to_string(),
+        // still the raw sealed verifier and key, resealed on the next unlock
+        version: 0,
+    };
+    db.put_vault_header(&new_header(vec![slot], &vault_key)?)?;
+    Ok(Some(vault_key))
+}
+
+// only for vaults that still store the sha256 of their key
+fn check_secret_hash(secret_hash: &[u8], key: &[u8]) -> Result<bool> {
+    let key_hash = utils::sha256(key)?;
+    Ok(key_hash.as_bytes().ct_eq(secret_hash).into())
+}
+
+// move an older vault to the current header: a new vault key in a master slot,
+// every item re-encrypted from items_key to it in the same batch
+fn migrate(db: &Database, params: KdfParams, master_key: &[u8], items_key: &[u8]) -> Result<SecretBuf> {
+    log::info!("migrate vault to header version {}", VAULT_HEADER_VERSION);
+    let vault_key = utils::aes256_key()?;
+    let slot = seal_slot(&Uuid::new_v4().to_string(), MASTER_SLOT_LABEL, KeySlotKind::Password, params, master_key, &vault_key)?;
+    let items = db.get_item_list()?.unwrap_or_default();
+    let items = reencrypt_items(&items, items_key, &vault_key)?;
+    db.rekey(&new_header(vec![slot], &vault_key)?, &items)?;
+    Ok(vault_key)
+}
+
+// returns false when the old password opens no slot; only the slot it opens is
+// replaced, the items stay as they are
+pub fn change_password(db: &Database, old_password: &str, new_password: &str) -> Result<bool> {
+    // brings older vaults to key slots first
+    if unlock(db, old_password.as_bytes())?.is_none() {
+        return Ok(false);
+    }
+    let mut header = get_header(db)?;
+    // keyfile and recovery slots keep their own secret
+    for slot in header.slots.iter_mut().filter(|slot| slot.kind == KeySlotKind::Password) {
+        if let Some(vault_key) = open_slot(slot, old_password.as_bytes())? {
+            let (params, master_key) = derive_new_key(new_password.as_bytes())?;
+            *slot = seal_slot(&slot.id, &slot.label, KeySlotKind::Password, params, &master_key, &vault_key)?;
+            db.put_vault_header(&header)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn get_header(db: &Database) -> Result<VaultHeader> {
+    db.get_vault_header()?.ok_or_else(|| anyhow!("vault not created"))
+}
+
+pub fn list_slots(db: &Database) -> Result<Vec<KeySlot>> {
+    match db.get_vault_header()? {
+        Some(header) => Ok(header.slots),
+        None => Ok(Vec::new()),
+    }
+}
+
+// vault_key is the key of the unlocked vault
+pub fn add_slot(db: &Database, vault_key: &[u8], label: &str, kind: KeySlotKind, secret: &[u8]) -> Result<KeySlot> {
+    if secret.is_empty() {
+        bail!("secret can`t empty");
+    }
+    let mut header = get_header(db)?;
+    let slot = new_slot(label, kind, secret, vault_key)?;
+    header.slots.push(slot.clone());
+    db.put_vault_header(&header)?;
+    Ok(slot)
+}
+
+pub fn test_slot(db: &Database, id: &str, secret: &[u8]) -> Result<bool> {
+    let header = get_header(db)?;
+    let slot = header.slots.iter().find(|slot| slot.id == id).ok_or_else(|| anyhow!("key slot not found"))?;
+    Ok(open_slot(slot, secret)?.is_some())
+}
+
+pub fn revoke_slot(db: &Database, id: &str) -> Result<()> {
+    let mut header = get_header(db)?;
+    if !header.slots.iter().any(|slot| slot.id == id) {
+        bail!("key slot not found");
+    }
+    if header.slots.len() == 1 {
+        bail!("can`t revoke the last key slot");
+    }
+    header.slots.retain(|slot| slot.id != id);
+    db.put_vault_header(&header)
+}
+
+// the shares split a random key wrapping the vault key in a slot of its own, so a new
+// split or revoking the slot makes the old shares useless
+pub fn split_key(db: &Database, vault_key: &[u8], threshold: u8, count: u8) -> Result<Vec<Share>> {
+    let mut header = get_header(db)?;
+    if header.key_check == "" {
+        bail!("unlock the vault once with a password first");
+    }
+    if !check_key(&header, vault_key)? {
+        bail!("not the key of this vault");
+    }
+    let share_key = utils::aes256_key()?;
+    let shares = shamir::split(&header.id, &share_key, threshold, count)?;
+    let slot = new_slot(SHARES_SLOT_LABEL, KeySlotKind::Shares, &share_key, vault_key)?;
+    header.slots.retain(|slot| slot.kind != KeySlotKind::Shares);
+    header.slots.push(slot);
+    db.put_vault_header(&header)?;
+    Ok(shares)
+}
+
+// returns the vault key, None when the shares open no shares slot
+pub fn unlock_with_shares(db: &Database, shares: &[Share]) -> Result<Option<SecretBuf>> {
+    let header = get_header(db)?;
+    if header.version > VAULT_HEADER_VERSION {
+        bail!("vault version {} is newer than this app", header.version);
+    }
+    if header.id == "" {
+        bail!("unlock the vault once with a password first");
+    }
+    if let Some(share) = shares.iter().find(|share| share.vault_id != header.id) {
+        bail!("{} is not for this vault", share);
+    }
+    let share_key = shamir::combine(shares)?;
+    for slot in header.slots.iter().filter(|slot| slot.kind == KeySlotKind::Shares) {
+        if let Some(vault_key) = open_slot(slot, &share_key)? {
+            item::upgrade_items(db, &vault_key)?;
+            return Ok(Some(vault_key));
+        }
+    }
+    Ok(None)
+}
+
+pub fn vault_id(db: &Database) -> Result<String> {
+    Ok(get_header(db)?.id)
+}
+
+// a new recovery key slot replaces the old one; the key is returned to be written
+// down, it is never stored
+pub fn new_recovery_key(db: &Database, vault_key: &[u8]) -> Result<SecretBuf> {
+    let key = recovery::new_recovery_key()?;
+    let slot = new_slot(RECOVERY_SLOT_LABEL, KeySlotKind::Recovery, &key, vault_key)?;
+    let mut header = get_header(db)?;
+    header.slots.retain(|slot| slot.kind != KeySlotKind::Recovery);
+    header.slots.push(slot);
+    db.put_vault_header(&header)?;
+    Ok(key)
+}
+
+// returns the vault key, None when the recovery key opens no recovery slot
+pub fn unlock_with_recovery_key(db: &Database, text: &str) -> Result<Option<SecretBuf>> {
+    let key = recovery::parse_key(text)?;
+    let header = get_header(db)?;
+    if header.version > VAULT_HEADER_VERSION {
+        bail!("vault version {} is newer than this app", header.version);
+    }
+    for slot in header.slots.iter().filter(|slot| slot.kind == KeySlotKind::Recovery) {
+        if let Some(vault_key) = open_slot(slot, &key)? {
+            upgrade_header(db, &vault_key)?;
+            item::upgrade_items(db, &vault_key)?;
+            return Ok(Some(vault_key));
+        }
+    }
+    Ok(None)
+}
+
+// after a recovery the master slot is replaced by one for the new password, or added
+// when it was revoked
+pub fn reset_master_password(db: &Database, vault_key: &[u8], password: &[u8]) -> Result<()> {
+    if password.is_empty() {
+        bail!("password can`t empty");
+    }
+    let mut header = get_header(db)?;
+    let master = header.slots.iter_mut()
+        .find(|slot| slot.label == MASTER_SLOT_LABEL && slot.kind == KeySlotKind::Password);
+    match master {
+        Some(master) => {
+            let (params, master_key) = derive_new_key(password)?;
+            *master = seal_slot(&master.id, MASTER_SLOT_LABEL, KeySlotKind::Password, params, &master_key, vault_key)?;
+        }
+        None => header.slots.insert(0, new_slot(MASTER_SLOT_LABEL, KeySlotKind::Password, password, vault_key)?),
+    }
+    db.put_vault_header(&header)
+}
+
+// tampered items are left out, they stay as they are and are listed as such
+pub fn reencrypt_items(items: &[Item], old_key: &[u8], new_key: &[u8]) -> Result<Vec<Item>> {
+    let mut result = Vec::new();
+    for it in items {
+        match item::reencrypt(it, old_key, new_key) {
+            Ok(it) => result.push(it),
+            Err(e) if e.is::<item::TamperError>() => log::error!("skip re-encrypt: {}", e),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(result)
+}
+
+
+#[cfg(test)]
+mod test {
+    use crate::constants::KDF_PARAMS_KEY;
+
+    use super::*;
+    use crate::db::TempDatabase;
+
+    fn put_secret(db: &Database, secret: &str, key: &[u8]) -> String {
+        let (ciphertext, nonce) = utils::aes256_encode(secret.as_bytes(), key).unwrap();
+        let item = Item {
+            id: Uuid::new_v4().to_string(),
+            account: "test01".to_string(),
+            secret: hex::encode(ciphertext),
+            desc: "email".to_string(),
+            nonce: hex::encode(nonce),
+            ..Default::default()
+        };
+        db.put_item(&item).unwrap();
+        item.id
+    }
+
+    fn put_current_secret(db: &Database, secret: &str, key: &[u8]) -> String {
+        let item = item::new_item(key, "test01", secret, "email").unwrap();
+        db.put_item(&item).unwrap();
+        item.id
+    }
+
+    fn read_secret(db: &Database, id: &str, key: &[u8]) -> String {
+        let item = db.get_item(id).unwrap().unwrap();
+        assert_eq!(item.version, item::ITEM_VERSION);
+        item::open_secret(key, &item).unwrap().as_str().unwrap().to_string()
+    }
+
+    fn fast_params() -> KdfParams {
+        KdfParams::new(Kdf::Argon2id { m_cost: 64, t_cost: 1, p_cost: 1 }).unwrap()
+    }
+
+    #[test]
+    fn create_and_unlock() {
+        let db = TempDatabase::new();
+        let vault_key = unlock(&db, "密码1234".as_bytes()).unwrap().unwrap();
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert_eq!(header.version, VAULT_HEADER_VERSION);
+        assert_eq!(header.slots.len(), 1);
+        let master_key = utils::derive_key("密码1234".as_bytes(), &header.slots[0].kdf).unwrap();
+        assert_ne!(master_key, vault_key);
+        assert_eq!(header.slots[0].version, KEY_SLOT_VERSION);
+        assert_eq!(open_slot_key(&header.slots[0], &master_key).unwrap(), Some(vault_key.clone()));
+        assert!(check_key(&header, &vault_key).unwrap());
+        assert!(!check_key(&header, &master_key).unwrap());
+        // an envelope moved to another slot doesn't open
+        let mut swapped = header.slots[0].clone();
+        swapped.id = "other slot".to_string();
+        assert_eq!(open_slot_key(&swapped, &master_key).unwrap(), None);
+        assert_eq!(unlock(&db, b"wrong").unwrap(), None);
+
+        let id = put_secret(&db, "my secret", &vault_key);
+        assert_eq!(unlock(&db, "密码1234".as_bytes()).unwrap(), Some(vault_key.clone()));
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+    }
+
+    #[test]
+    fn migrate_legacy_vault() {
+        let db = TempDatabase::new();
+        let legacy_key = utils::legacy_aes_key(b"abcd1234").unwrap();
+        db.put_secret_hash(utils::sha256(&legacy_key).unwrap()).unwrap();
+        let id = put_secret(&db, "my secret", &legacy_key);
+        // one broken item doesn't stop the rest
+        let broken = put_secret(&db, "lost", &utils::aes256_key().unwrap());
+
+        assert_eq!(unlock(&db, b"wrong").unwrap(), None);
+        assert!(db.get_kdf_params().unwrap().is_none());
+
+        let vault_key = unlock(&db, b"abcd1234").unwrap().unwrap();
+        assert_ne!(vault_key, legacy_key);
+        assert_eq!(db.get_vault_header().unwrap().unwrap().version, VAULT_HEADER_VERSION);
+        assert!(db.get_secret_hash().unwrap().is_none());
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+        assert_eq!(db.get_item(&broken).unwrap().unwrap().version, 0);
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key.clone()));
+
+        // the same for an item upgraded in a vault that already has a header
+        let broken = put_secret(&db, "lost", &utils::aes256_key().unwrap());
+        let id = put_secret(&db, "my other secret", &vault_key);
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key.clone()));
+        assert_eq!(read_secret(&db, &id, &vault_key), "my other secret");
+        assert_eq!(db.get_item(&broken).unwrap().unwrap().version, 0);
+        assert!(item::open_secret(&vault_key, &db.get_item(&broken).unwrap().unwrap()).unwrap_err().is::<item::TamperError>());
+    }
+
+    #[test]
+    fn change_vault_password() {
+        let db = TempDatabase::new();
+        let vault_key = unlock(&db, b"old password").unwrap().unwrap();
+        let id = put_current_secret(&db, "my secret", &vault_key);
+        let item = db.get_item(&id).unwrap().unwrap();
+        add_slot(&db, &vault_key, "recovery", KeySlotKind::Password, b"recovery").unwrap();
+        add_slot(&db, &vault_key, "usb key", KeySlotKind::Keyfile, b"keyfile text").unwrap();
+
+        assert!(!change_password(&db, "wrong", "new password").unwrap());
+        // a keyfile slot never turns into a password slot
+        assert!(!change_password(&db, "keyfile text", "new password").unwrap());
+        assert_eq!(unlock(&db, b"keyfile text").unwrap(), Some(vault_key.clone()));
+        assert_eq!(unlock(&db, b"old password").unwrap(), Some(vault_key.clone()));
+
+        assert!(change_password(&db, "old password", "new password").unwrap());
+        assert_eq!(unlock(&db, b"old password").unwrap(), None);
+        assert_eq!(unlock(&db, b"new password").unwrap(), Some(vault_key.clone()));
+        assert_eq!(unlock(&db, b"recovery").unwrap(), Some(vault_key.clone()));
+        let labels: Vec<String> = list_slots(&db).unwrap().into_iter().map(|slot| slot.label).collect();
+        assert_eq!(labels, vec![MASTER_SLOT_LABEL.to_string(), "recovery".to_string(), "usb key".to_string()]);
+        assert_eq!(list_slots(&db).unwrap()[2].kind, KeySlotKind::Keyfile);
+        assert_eq!(db.get_item(&id).unwrap().unwrap(), item);
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+    }
+
+    #[test]
+    fn manage_key_slots() {
+        let db = TempDatabase::new();
+        let vault_key = unlock(&db, b"personal").unwrap().unwrap();
+        let keyfile = utils::gen_rand_key(64).unwrap();
+        let recovery = add_slot(&db, &vault_key, "recovery", KeySlotKind::Password, b"offline phrase").unwrap();
+        let file_slot = add_slot(&db, &vault_key, "usb key", KeySlotKind::Keyfile, &keyfile).unwrap();
+        assert_eq!(list_slots(&db).unwrap().len(), 3);
+        assert!(add_slot(&db, &vault_key, "empty", KeySlotKind::Password, b"").is_err());
+
+        assert_eq!(unlock(&db, b"offline phrase").unwrap(), Some(vault_key.clone()));
+        assert_eq!(unlock(&db, &keyfile).unwrap(), Some(vault_key.clone()));
+        assert!(test_slot(&db, &recovery.id, b"offline phrase").unwrap());
+        assert!(!test_slot(&db, &recovery.id, b"personal").unwrap());
+        assert!(test_slot(&db, &file_slot.id, &keyfile).unwrap());
+
+        revoke_slot(&db, &recovery.id).unwrap();
+        assert_eq!(unlock(&db, b"offline phrase").unwrap(), None);
+        assert!(test_slot(&db, &recovery.id, b"offline phrase").is_err());
+        assert!(revoke_slot(&db, &recovery.id).is_err());
+
+        let master = list_slots(&db).unwrap()[0].id.clone();
+        revoke_slot(&db, &master).unwrap();
+        assert_eq!(unlock(&db, b"personal").unwrap(), None);
+        assert!(revoke_slot(&db, &file_slot.id).is_err());
+        assert_eq!(unlock(&db, &keyfile).unwrap(), Some(vault_key));
+    }
+
+    #[test]
+    fn upgrade_hashed_vault() {
+        let db = TempDatabase::new();
+        let params = fast_params();
+        let key = utils::derive_key(b"abcd1234", &params).unwrap();
+        db.put(KDF_PARAMS_KEY, serde_json::to_string(&params).unwrap()).unwrap();
+        db.put_secret_hash(utils::sha256(&key).unwrap()).unwrap();
+        let id = put_secret(&db, "my secret", &key);
+
+        assert_eq!(unlock(&db, b"wrong").unwrap(), None);
+        assert!(db.get_vault_header().unwrap().is_none());
+
+        let vault_key = unlock(&db, b"abcd1234").unwrap().unwrap();
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert_eq!(header.slots[0].kdf, params);
+        assert!(db.get_secret_hash().unwrap().is_none());
+        assert!(db.get_kdf_params().unwrap().is_none());
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key));
+        assert_eq!(unlock(&db, b"wrong").unwrap(), None);
+    }
+
+    #[test]
+    fn upgrade_single_key_vault() {
+        let db = TempDatabase::new();
+        let params = fast_params();
+        let key = utils::derive_key(b"abcd1234", &params).unwrap();
+        let verifier = utils::aes256_seal(VERIFIER_CANARY, &key).unwrap();
+        let header = VaultHeader {
+            version: 2,
+            id: "".to_string(),
+            slots: vec![],
+            key_check: "".to_string(),
+            kdf: Some(params.clone()),
+            verifier: hex::encode(&verifier),
+            wrapped_key: "".to_string(),
+        };
+        db.put_vault_header(&header).unwrap();
+        let id = put_secret(&db, "my secret", &key);
+
+        let vault_key = unlock(&db, b"abcd1234").unwrap().unwrap();
+        assert_ne!(vault_key, key);
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key));
+
+        // version 3: the vault key is already wrapped, only the header changes
+        let db = TempDatabase::new();
+        let vault_key = utils::aes256_key().unwrap();
+        let header = VaultHeader {
+            version: 3,
+            id: "".to_string(),
+            slots: vec![],
+            key_check: "".to_string(),
+            kdf: Some(params),
+            verifier: hex::encode(&verifier),
+            wrapped_key: hex::encode(utils::aes256_seal(&vault_key, &key).unwrap()),
+        };
+        db.put_vault_header(&header).unwrap();
+        let id = put_current_secret(&db, "my secret", &vault_key);
+        let item = db.get_item(&id).unwrap().unwrap();
+
+        assert_eq!(unlock(&db, b"wrong").unwrap(), None);
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key.clone()));
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert_eq!(header.version, VAULT_HEADER_VERSION);
+        assert_eq!(header.slots.len(), 1);
+        assert_eq!(header.slots[0].version, 0);
+        assert_eq!(db.get_item(&id).unwrap().unwrap(), item);
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key.clone()));
+        // the raw sealed slot moves to envelopes once it is opened
+        let slot = db.get_vault_header().unwrap().unwrap().slots.remove(0);
+        assert_eq!(slot.version, KEY_SLOT_VERSION);
+        assert_eq!(slot.id, header.slots[0].id);
+        assert!(Envelope::from_hex(&slot.wrapped_key).is_ok());
+        assert_eq!(unlock(&db, b"abcd1234").unwrap(), Some(vault_key));
+    }
+
+    #[test]
+    fn unlock_with_key_shares() {
+        let db = TempDatabase::new();
+        let vault_key = unlock(&db, b"abcd1234").unwrap().unwrap();
+        let id = put_current_secret(&db, "my secret", &vault_key);
+        assert!(split_key(&db, &utils::aes256_key().unwrap(), 2, 3).is_err());
+        let shares = split_key(&db, &vault_key, 2, 3).unwrap();
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert!(shares.iter().all(|share| share.vault_id == header.id));
+        // the shares hold a wrapping key, not the vault key
+        assert_ne!(shamir::combine(&shares[..2]).unwrap(), vault_key);
+        assert_eq!(header.slots.iter().filter(|slot| slot.kind == KeySlotKind::Shares).count(), 1);
+
+        let picked = vec![shares[2].clone(), shares[0].clone()];
+        assert_eq!(unlock_with_shares(&db, &picked).unwrap(), Some(vault_key.clone()));
+        assert!(unlock_with_shares(&db, &shares[..1]).is_err());
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+
+        // shares of another key with this vault's id, or of the raw vault key
+        let other = shamir::split(&header.id, &utils::aes256_key().unwrap(), 2, 3).unwrap();
+        assert_eq!(unlock_with_shares(&db, &other[..2]).unwrap(), None);
+        let raw = shamir::split(&header.id, &vault_key, 2, 3).unwrap();
+        assert_eq!(unlock_with_shares(&db, &raw[..2]).unwrap(), None);
+        let other = shamir::split("other vault", &vault_key, 2, 3).unwrap();
+        assert!(unlock_with_shares(&db, &other[..2]).is_err());
+
+        // a new split replaces the old shares, revoking the slot drops the new ones
+        let shares = split_key(&db, &vault_key, 2, 2).unwrap();
+        assert_eq!(unlock_with_shares(&db, &picked).unwrap(), None);
+        assert_eq!(unlock_with_shares(&db, &shares).unwrap(), Some(vault_key.clone()));
+        let slot = list_slots(&db).unwrap().into_iter().find(|slot| slot.kind == KeySlotKind::Shares).unwrap();
+        revoke_slot(&db, &slot.id).unwrap();
+        assert_eq!(unlock_with_shares(&db, &shares).unwrap(), None);
+
+        // a version 4 header gets its id and key check on the next unlock
+        let mut header = db.get_vault_header().unwrap().unwrap();
+        header.version = 4;
+        header.id = "".to_string();
+        header.key_check = "".to_string();
+        db.put_vault_header(&header).unwrap();
+        assert!(unlock_with_shares(&db, &shares).is_err());
+        assert!(split_key(&db, &vault_key, 2, 2).is_err());
+        unlock(&db, b"abcd1234").unwrap().unwrap();
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert_eq!(header.version, VAULT_HEADER_VERSION);
+        assert_ne!(header.id, "");
+        let shares = split_key(&db, &vault_key, 2, 2).unwrap();
+        assert_eq!(unlock_with_shares(&db, &shares).unwrap(), Some(vault_key));
+    }
+
+    #[test]
+    fn recover_with_recovery_key() {
+        let db = TempDatabase::new();
+        let vault_key = unlock(&db, b"forgotten").unwrap().unwrap();
+        let id = put_current_secret(&db, "my secret", &vault_key);
+        let first = recovery::format_key(&new_recovery_key(&db, &vault_key).unwrap()).unwrap();
+        let key = recovery::format_key(&new_recovery_key(&db, &vault_key).unwrap()).unwrap();
+        let slots = list_slots(&db).unwrap();
+        assert_eq!(slots.iter().filter(|slot| slot.kind == KeySlotKind::Recovery).count(), 1);
+
+        assert_eq!(unlock_with_recovery_key(&db, first.as_str().unwrap()).unwrap(), None);
+        assert_eq!(unlock(&db, key.as_bytes()).unwrap(), None);
+        assert!(unlock_with_recovery_key(&db, "not a key").is_err());
+        let recovered = unlock_with_recovery_key(&db, &key.as_str().unwrap().to_lowercase()).unwrap();
+        assert_eq!(recovered, Some(vault_key.clone()));
+
+        reset_master_password(&db, &vault_key, b"new password").unwrap();
+        assert_eq!(unlock(&db, b"forgotten").unwrap(), None);
+        assert_eq!(unlock(&db, b"new password").unwrap(), Some(vault_key.clone()));
+        assert_eq!(list_slots(&db).unwrap()[0].id, slots[0].id);
+        assert_eq!(read_secret(&db, &id, &vault_key), "my secret");
+
+        // the master slot was revoked: a new one is added
+        revoke_slot(&db, &slots[0].id).unwrap();
+        reset_master_password(&db, &vault_key, b"newer password").unwrap();
+        assert_eq!(list_slots(&db).unwrap()[0].label, MASTER_SLOT_LABEL);
+        assert_eq!(unlock(&db, b"newer password").unwrap(), Some(vault_key.clone()));
+        assert_eq!(unlock_with_recovery_key(&db, key.as_str().unwrap()).unwrap(), Some(vault_key.clone()));
+
+        // an older header is upgraded by a recovery unlock too
+        let mut header = db.get_vault_header().unwrap().unwrap();
+        header.version = VAULT_HEADER_VERSION - 1;
+        header.key_check = "".to_string();
+        db.put_vault_header(&header).unwrap();
+        assert_eq!(unlock_with_recovery_key(&db, key.as_str().unwrap()).unwrap(), Some(vault_key));
+        let header = db.get_vault_header().unwrap().unwrap();
+        assert_eq!(header.version, VAULT_HEADER_VERSION);
+        assert_ne!(header.key_check, "");
+    }
+}